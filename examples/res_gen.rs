@@ -8,6 +8,8 @@ use chrono::DateTime;
 use obadgen::box_err::BoxResult;
 use obadgen::cert_gen;
 use obadgen::constants;
+use obadgen::settings::{CertKind, KeygenSettings};
+use obadgen::signature::Algorithm;
 use obadgen::Assertion;
 use obadgen::BadgeClass;
 use obadgen::CryptographicKey;
@@ -91,13 +93,15 @@ fn write_with_key() -> BoxResult<()> {
         "hello.world.example".to_string(),
         "localhost".to_string(),
     ];
-    // let cert = cert_gen::create_rsa_cert(subject_alt_names)?;
-    let certified_key = rcgen::generate_simple_self_signed(subject_alt_names)?;
-    let cert_cont = cert_gen::Container {
-        certified_key,
+    let cert_cont = cert_gen::create(&KeygenSettings {
+        algorithm: Algorithm::ES256,
+        cert: CertKind::SelfSigned,
+        subject_alt_names: subject_alt_names.to_vec(),
+        validity_days: 100 * 365,
         file_base: constants::ISSUER_CERT_PATH_BASE.into(),
-    };
-    cert_cont.write_files()?;
+        ..Default::default()
+    })?;
+    cert_cont.write_files(true)?;
     cert_cont.write_license_files(REUSE_EXPRS)?;
     // let (_issuer_key_priv, issuer_key_pub) = write_key_pair(
     //     constants::ISSUER_KEY_PATH_PRIV,
@@ -107,7 +111,7 @@ fn write_with_key() -> BoxResult<()> {
     let crypto_key = CryptographicKey::builder()
         .id(constants::ISSUER_KEY_ID)
         .owner(constants::ISSUER_WITH_KEY_ID)
-        .public_key_pem(cert_cont.certified_key.signing_key.public_key_pem())
+        .public_key_pem(cert_cont.pub_pem()?)
         .build();
     write_to_file(constants::ISSUER_KEY_PATH, crypto_key.to_json_ld()?)?;
 
@@ -115,7 +119,7 @@ fn write_with_key() -> BoxResult<()> {
         .id(constants::ISSUER_WITH_KEY_ID)
         .name("Issuer - with key")
         .url(constants::ISSUER_WITH_KEY_URL)
-        .public_key(constants::ISSUER_KEY_ID)
+        .public_key([constants::ISSUER_KEY_ID.to_string()])
         .build();
     write_to_file(constants::ISSUER_WITH_KEY_PATH, issuer.to_json_ld()?)?;
 
@@ -0,0 +1,153 @@
+// SPDX-FileCopyrightText: 2026 Robin Vobruba <hoijui.quaero@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Creation of new assertions from individual values,
+//! as an alternative to writing the JSON-LD by hand.
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::box_err::BoxResult;
+use crate::process::Error;
+use crate::settings::{IdentityHashing, NewAssertionSettings, VerificationKind};
+use crate::{Assertion, Identity, IdentityError, Verification, VerificationType};
+
+/// Creates a recipient identity, hashed as requested.
+///
+/// # Errors
+///
+/// If the recipient is not a valid identity of the requested type.
+pub fn create_identity(settings: &NewAssertionSettings) -> Result<Identity, IdentityError> {
+    let (r#type, value) = (settings.identity_type, settings.recipient.as_str());
+    match &settings.hashing {
+        IdentityHashing::Plain => Identity::plain(r#type, value),
+        IdentityHashing::Unsalted => Identity::hashed(r#type, value, None),
+        IdentityHashing::Salted(salt) => Identity::hashed(r#type, value, Some(salt.clone())),
+    }
+}
+
+/// Creates a new assertion.
+///
+/// Signed assertions without an explicit `id`
+/// get a random one in the `urn:uuid` namespace,
+/// as recommended by the specification.
+///
+/// # Errors
+///
+/// If a hosted assertion has no `id`,
+/// or `creator` is given for a hosted assertion.
+///
+/// If the recipient is not a valid identity of the requested type.
+pub fn create(settings: &NewAssertionSettings) -> BoxResult<Assertion> {
+    let id = match (&settings.id, settings.verification) {
+        (Some(id), _) => id.clone(),
+        (None, VerificationKind::Signed) => format!("urn:uuid:{}", Uuid::new_v4()),
+        (None, VerificationKind::Hosted) => {
+            return Err(Error::InvalidSettings {
+                msg: "A hosted assertion requires an id, the IRI it is hosted under".to_owned(),
+            }
+            .into());
+        }
+    };
+    let verification_type = match settings.verification {
+        VerificationKind::Hosted => {
+            if settings.creator.is_some() {
+                return Err(Error::InvalidSettings {
+                    msg: "A creator (key) can only be set for signed assertions".to_owned(),
+                }
+                .into());
+            }
+            VerificationType::HostedBadge
+        }
+        VerificationKind::Signed => VerificationType::SignedBadge {
+            creator: settings.creator.clone(),
+        },
+    };
+    let issued_on = settings
+        .issued_on
+        .unwrap_or_else(|| Utc::now().fixed_offset());
+
+    let mut assertion = Assertion::builder()
+        .id(id)
+        .badge(settings.badge_id.as_str())
+        .recipient(create_identity(settings)?)
+        .verification(Verification::new(verification_type))
+        .issued_on(issued_on)
+        .evidence(settings.evidence.clone())
+        .build();
+    assertion.expires = settings.expires.map(Into::into);
+    assertion.narrative.clone_from(&settings.narrative);
+
+    Ok(assertion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{constants, IdentityType, ToJsonLd};
+    use chrono::DateTime;
+
+    const EXP_JSON_LD_SIMPLE: &str = r#"{
+  "@context": "https://w3id.org/openbadges/v2",
+  "type": "Assertion",
+  "id": "https://raw.githubusercontent.com/hoijui/obadgen/master/res/ob-ents/badge-assertion-simple.json",
+  "badge": "https://raw.githubusercontent.com/hoijui/obadgen/master/res/ob-ents/badge-definition-simple.json",
+  "recipient": {
+    "type": "email",
+    "identity": "sha256$488842626ec74a0468d90ea17dc4e11c2d0e8e54e45c5075fbd1d2e767f44249",
+    "hashed": true
+  },
+  "verification": {
+    "type": "HostedBadge"
+  },
+  "issuedOn": "2022-06-17T23:59:59Z",
+  "expires": "2099-06-30T23:59:59Z"
+}"#;
+
+    #[test]
+    fn create_hosted() -> BoxResult<()> {
+        let settings = NewAssertionSettings {
+            id: Some(constants::BADGE_ASSERTION_SIMPLE_ID.to_owned()),
+            badge_id: constants::BADGE_DEFINITION_SIMPLE_ID.to_owned(),
+            recipient: constants::BADGE_ASSERTION_RECIPIENT_EMAIL.to_owned(),
+            identity_type: IdentityType::EMail,
+            issued_on: Some(DateTime::parse_from_rfc3339(constants::DT_PAST)?),
+            expires: Some(DateTime::parse_from_rfc3339(constants::DT_FAR_FUTURE)?),
+            ..NewAssertionSettings::default()
+        };
+        assert_eq!(create(&settings)?.to_json_ld()?, EXP_JSON_LD_SIMPLE);
+
+        let settings = NewAssertionSettings {
+            id: None,
+            ..settings
+        };
+        assert!(create(&settings).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn create_signed() -> BoxResult<()> {
+        let settings = NewAssertionSettings {
+            badge_id: constants::BADGE_DEFINITION_WITH_KEY_ID.to_owned(),
+            recipient: constants::BADGE_ASSERTION_RECIPIENT_EMAIL.to_owned(),
+            hashing: IdentityHashing::Salted(constants::BADGE_ASSERTION_RECIPIENT_SALT.to_owned()),
+            verification: VerificationKind::Signed,
+            creator: Some(constants::ISSUER_KEY_ID.to_owned()),
+            ..NewAssertionSettings::default()
+        };
+        let assertion = create(&settings)?;
+        assert!(assertion.id.starts_with("urn:uuid:"));
+        assert_eq!(
+            assertion.recipient.identity,
+            *constants::BADGE_ASSERTION_RECIPIENT_EMAIL_HASH_SALTED
+        );
+        assert_eq!(
+            assertion.verification.r#type,
+            VerificationType::SignedBadge {
+                creator: Some(constants::ISSUER_KEY_ID.to_owned())
+            }
+        );
+        Ok(())
+    }
+}
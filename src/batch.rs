@@ -0,0 +1,404 @@
+// SPDX-FileCopyrightText: 2026 Robin Vobruba <hoijui.quaero@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Baking of many badges at once,
+//! one per recipient of a recipient list.
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::box_err::BoxResult;
+use crate::process::{self, Error};
+use crate::settings::{BatchSettings, HostedPayload, Settings};
+use crate::{Identity, IdentityType, ToJsonLd, VerificationType};
+
+/// The file name of the manifest,
+/// written into the output directory.
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// A single entry of a recipient list.
+/// Exactly one of the fields has to be set.
+#[derive(Debug, Default, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Recipient {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub telephone: Option<String>,
+}
+
+impl Recipient {
+    /// Returns the type and (plain-text) value of the recipients identity.
+    ///
+    /// # Errors
+    ///
+    /// If not exactly one of the identity fields is set.
+    pub fn identity(&self) -> Result<(IdentityType, &str), Error> {
+        let present: Vec<(IdentityType, &str)> = [
+            (IdentityType::EMail, &self.email),
+            (IdentityType::Url, &self.url),
+            (IdentityType::Telephone, &self.telephone),
+        ]
+        .into_iter()
+        .filter_map(|(r#type, value)| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(|value| (r#type, value))
+        })
+        .collect();
+        match present.as_slice() {
+            [identity] => Ok(*identity),
+            _ => Err(Error::InvalidRecipients {
+                msg: format!(
+                    "Exactly one of email, url and telephone has to be set, but {} are: {self:?}",
+                    present.len()
+                ),
+            }),
+        }
+    }
+
+    /// Creates a hashed and salted identity for this recipient,
+    /// using a fresh, random salt.
+    ///
+    /// # Errors
+    ///
+    /// If not exactly one of the identity fields is set,
+    /// or it is not a valid identity.
+    ///
+    /// If generating the salt fails.
+    pub fn to_hashed_identity(&self) -> BoxResult<Identity> {
+        let (r#type, value) = self.identity()?;
+        Ok(Identity::hashed_salted(r#type, value)?)
+    }
+}
+
+/// Maps one recipient to the files created for it.
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    pub recipient: Recipient,
+    /// The IRI of the created assertion
+    pub assertion_id: String,
+    /// The baked image, relative to the output directory
+    pub image: PathBuf,
+    /// The assertion JSON-LD to be hosted under `assertion_id`,
+    /// relative to the output directory;
+    /// only present for hosted assertions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub assertion: Option<PathBuf>,
+}
+
+fn recipients_err<S: Into<String>>(msg: S) -> Error {
+    Error::InvalidRecipients { msg: msg.into() }
+}
+
+/// Reads a recipient list,
+/// either as CSV (with a header row),
+/// or as JSON Lines (one JSON object per line).
+/// The format is chosen by the file-extension
+/// (`.csv`, or `.jsonl`/`.ndjson`).
+///
+/// # Errors
+///
+/// If the file-extension is not supported.
+///
+/// If reading or parsing the file fails.
+///
+/// If any of the recipients has not exactly one identity set.
+pub fn read_recipients(recipients_loc: &Path) -> BoxResult<Vec<Recipient>> {
+    log::info!(
+        "Reading recipients from file '{}' ...",
+        recipients_loc.display()
+    );
+    let ext = recipients_loc
+        .extension()
+        .and_then(std::ffi::OsStr::to_str)
+        .map(str::to_lowercase);
+    let recipients = match ext.as_deref() {
+        Some("csv") => csv::Reader::from_path(recipients_loc)?
+            .deserialize()
+            .collect::<Result<Vec<Recipient>, _>>()?,
+        Some("jsonl" | "ndjson") => {
+            let mut recipients = vec![];
+            for line_res in BufReader::new(File::open(recipients_loc)?).lines() {
+                let line = line_res?;
+                if !line.trim().is_empty() {
+                    recipients.push(serde_json::from_str(&line)?);
+                }
+            }
+            recipients
+        }
+        _ => {
+            return Err(recipients_err(format!(
+                "Unsupported file-extension (supported: csv, jsonl, ndjson): '{}'",
+                recipients_loc.display()
+            ))
+            .into());
+        }
+    };
+
+    for (index, recipient) in recipients.iter().enumerate() {
+        let invalid = |err: &dyn std::error::Error| {
+            recipients_err(format!("Invalid recipient (#{}): {err}", index + 1))
+        };
+        let (r#type, value) = recipient.identity().map_err(|err| invalid(&err))?;
+        r#type.normalize(value).map_err(|err| invalid(&err))?;
+    }
+
+    Ok(recipients)
+}
+
+/// Bakes one badge per recipient.
+///
+/// The baked images (and for hosted assertions,
+/// the assertions to be hosted) are written into the output directory,
+/// together with a manifest (see [`MANIFEST_FILE_NAME`])
+/// that maps the recipients to these files.
+///
+/// The assertion from `settings.assertion_loc` serves as template;
+/// for each recipient, it gets a fresh `id`,
+/// a hashed and salted `recipient` identity,
+/// and `badge` is set to `batch.badge_id`.
+///
+/// # Errors
+///
+/// If the settings are incomplete or inconsistent.
+///
+/// If reading any of the inputs fails.
+///
+/// If signing or baking any of the badges fails.
+pub fn run(settings: &Settings, batch: &BatchSettings) -> BoxResult<Vec<ManifestEntry>> {
+    let (Some(assertion_loc), Some(source_image_loc)) = (
+        settings.assertion_loc.as_ref(),
+        settings.source_image_loc.as_ref(),
+    ) else {
+        return Err(Error::InvalidSettings {
+            msg: "Batch baking requires a template assertion and a source image".to_owned(),
+        }
+        .into());
+    };
+    if let HostedPayload::Url(url) = &settings.hosted_payload {
+        return Err(Error::InvalidSettings {
+            msg: format!("The single URL '{url}' can not be baked into multiple badges"),
+        }
+        .into());
+    }
+
+    let template = process::read_assertion(assertion_loc)?;
+    let hosted = template.verification.r#type == VerificationType::HostedBadge;
+    if hosted && batch.id_base.is_none() {
+        return Err(Error::InvalidSettings {
+            msg: "Hosted assertions need a base IRI to be hosted under".to_owned(),
+        }
+        .into());
+    }
+
+    let signer_opt = process::create_signer_opt(settings)?;
+    let x509_chain_opt = process::read_cert_chain_opt(&settings.cert_locs, signer_opt.as_deref())?;
+
+    let image_type = process::source_image_type(source_image_loc, settings.image_format)?;
+    let image_ext = image_type.extension();
+
+    let recipients = read_recipients(&batch.recipients_loc)?;
+
+    fs::create_dir_all(&batch.out_dir)?;
+    let mut manifest = Vec::with_capacity(recipients.len());
+    for recipient in recipients {
+        let name = Uuid::new_v4().to_string();
+        let mut assertion = template.clone();
+        assertion.id = batch.id_base.as_ref().map_or_else(
+            || format!("urn:uuid:{name}"),
+            |id_base| format!("{}/{name}.json", id_base.trim_end_matches('/')),
+        );
+        assertion.badge.clone_from(&batch.badge_id);
+        assertion.recipient = recipient.to_hashed_identity()?;
+        log::info!("Baking badge '{}' ...", assertion.id);
+
+        let assertion_file = if hosted {
+            let assertion_file = PathBuf::from(format!("{name}.json"));
+            fs::write(batch.out_dir.join(&assertion_file), assertion.to_json_ld()?)?;
+            Some(assertion_file)
+        } else {
+            None
+        };
+        let assertion_id = assertion.id.clone();
+
+        let baking_content = process::create_baking_content(
+            &assertion,
+            signer_opt.as_deref(),
+            x509_chain_opt.clone(),
+            &settings.hosted_payload,
+            &settings.jws_header,
+        )?;
+        let image_file = PathBuf::from(format!("{name}.{image_ext}"));
+        process::bake(
+            image_type,
+            source_image_loc,
+            &batch.out_dir.join(&image_file),
+            &baking_content,
+            true,
+            settings,
+        )?;
+        if settings.sidecars {
+            process::write_sidecars(
+                &batch.out_dir.join(&image_file),
+                &assertion,
+                &baking_content,
+            )?;
+        }
+
+        manifest.push(ManifestEntry {
+            recipient,
+            assertion_id,
+            image: image_file,
+            assertion: assertion_file,
+        });
+    }
+
+    fs::write(
+        batch.out_dir.join(MANIFEST_FILE_NAME),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+    log::info!(
+        "Baked {} badges into '{}'.",
+        manifest.len(),
+        batch.out_dir.display()
+    );
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants;
+    use crate::patcher::PayloadKind;
+
+    #[test]
+    fn recipient_identity() {
+        let email = Recipient {
+            email: Some("me@example.org".to_owned()),
+            ..Recipient::default()
+        };
+        assert_eq!(
+            email.identity().ok(),
+            Some((IdentityType::EMail, "me@example.org"))
+        );
+        assert!(Recipient::default().identity().is_err());
+        let both = Recipient {
+            url: Some("https://example.org".to_owned()),
+            telephone: Some("+41000000000".to_owned()),
+            ..Recipient::default()
+        };
+        assert!(both.identity().is_err());
+    }
+
+    #[test]
+    fn hashed_identity() -> BoxResult<()> {
+        let recipient = Recipient {
+            email: Some("Me@Example.org".to_owned()),
+            ..Recipient::default()
+        };
+        let identity = recipient.to_hashed_identity()?;
+        assert!(identity.hashed);
+        assert!(identity.salt.is_some());
+        assert!(identity.matches("me@example.org")?);
+        Ok(())
+    }
+
+    #[test]
+    fn read_csv_and_jsonl() -> BoxResult<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let csv_loc = tmp_dir.path().join("recipients.csv");
+        fs::write(
+            &csv_loc,
+            "name,email,url,telephone\nA,a@example.org,,\nB,,https://b.example.org,\n",
+        )?;
+        let jsonl_loc = tmp_dir.path().join("recipients.jsonl");
+        fs::write(
+            &jsonl_loc,
+            "{\"email\": \"a@example.org\"}\n\n{\"url\": \"https://b.example.org\"}\n",
+        )?;
+        let expected = vec![
+            Recipient {
+                email: Some("a@example.org".to_owned()),
+                ..Recipient::default()
+            },
+            Recipient {
+                url: Some("https://b.example.org".to_owned()),
+                ..Recipient::default()
+            },
+        ];
+        assert_eq!(read_recipients(&csv_loc)?, expected);
+        assert_eq!(read_recipients(&jsonl_loc)?, expected);
+
+        fs::write(&jsonl_loc, "{}\n")?;
+        assert!(read_recipients(&jsonl_loc).is_err());
+        fs::write(&jsonl_loc, "{\"email\": \"not an email\"}\n")?;
+        assert!(read_recipients(&jsonl_loc).is_err());
+        assert!(read_recipients(&tmp_dir.path().join("recipients.txt")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn bake_hosted_batch() -> BoxResult<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let recipients_loc = tmp_dir.path().join("recipients.jsonl");
+        fs::write(
+            &recipients_loc,
+            "{\"email\": \"a@example.org\"}\n{\"telephone\": \"+41000000000\"}\n",
+        )?;
+        let out_dir = tmp_dir.path().join("out");
+        let settings = Settings {
+            assertion_loc: Some(PathBuf::from(constants::BADGE_ASSERTION_SIMPLE_PATH)),
+            hosted_payload: HostedPayload::Id,
+            source_image_loc: Some(PathBuf::from("res/media/img/test.svg")),
+            ..Settings::default()
+        };
+        let batch = BatchSettings {
+            badge_id: constants::BADGE_DEFINITION_SIMPLE_ID.to_owned(),
+            recipients_loc,
+            out_dir: out_dir.clone(),
+            id_base: Some("https://example.org/badges/".to_owned()),
+        };
+        let manifest = run(&settings, &batch)?;
+        assert_eq!(manifest.len(), 2);
+        for entry in &manifest {
+            assert!(entry
+                .assertion_id
+                .starts_with("https://example.org/badges/"));
+            let payload = process::extract(&out_dir.join(&entry.image), None)?;
+            assert_eq!(
+                payload.map(|payload| (payload.kind, payload.content)),
+                Some((PayloadKind::Url, entry.assertion_id.clone()))
+            );
+            assert!(entry
+                .assertion
+                .as_ref()
+                .is_some_and(|file| out_dir.join(file).is_file()));
+        }
+        let written: Vec<ManifestEntry> =
+            serde_json::from_str(&fs::read_to_string(out_dir.join(MANIFEST_FILE_NAME))?)?;
+        assert_eq!(written, manifest);
+
+        // Hosted assertions need somewhere to be hosted
+        let batch = BatchSettings {
+            id_base: None,
+            ..batch
+        };
+        assert!(run(&settings, &batch).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+// SPDX-FileCopyrightText: 2026 Robin Vobruba <hoijui.quaero@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Reading of x.509 certificate chains,
+//! for inclusion into the `x5c` header of a JWS; see
+//! [RFC 7515, section 4.1.6](https://datatracker.ietf.org/doc/html/rfc7515#section-4.1.6).
+
+use std::fs;
+use std::path::Path;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use x509_cert::der::{Decode, Encode};
+use x509_cert::spki::SubjectPublicKeyInfoOwned;
+use x509_cert::Certificate;
+
+use crate::box_err::BoxResult;
+use crate::key;
+use crate::process::Error;
+
+fn cert_err<S: Into<String>>(msg: S) -> Error {
+    Error::InvalidCertificate { msg: msg.into() }
+}
+
+fn subject_name(cert: &Certificate) -> String {
+    cert.tbs_certificate.subject.to_string()
+}
+
+/// Whether `cert` names `issuer` as its issuer.
+fn is_issued_by(cert: &Certificate, issuer: &Certificate) -> bool {
+    cert.tbs_certificate.issuer == issuer.tbs_certificate.subject
+}
+
+/// Reads all certificates from a file,
+/// which is either a bundle of PEM certificates,
+/// or a single DER certificate.
+///
+/// # Errors
+///
+/// If the file could not be read,
+/// or does not contain valid certificates.
+pub fn read_file<P: AsRef<Path>>(cert_file: P) -> Result<Vec<Certificate>, Error> {
+    let cert_file = cert_file.as_ref();
+    let bytes = fs::read(cert_file)
+        .map_err(|err| cert_err(format!("Failed to read '{}': {err}", cert_file.display())))?;
+    let certs = if key::is_pem(&bytes) {
+        Certificate::load_pem_chain(&bytes)
+    } else {
+        Certificate::from_der(&bytes).map(|cert| vec![cert])
+    }
+    .map_err(|err| cert_err(format!("Failed to decode '{}': {err}", cert_file.display())))?;
+    if certs.is_empty() {
+        return Err(cert_err(format!(
+            "No certificates found in '{}'",
+            cert_file.display()
+        )));
+    }
+    Ok(certs)
+}
+
+/// Orders the certificates of a chain leaf-first,
+/// each followed by the one that issued it.
+///
+/// # Errors
+///
+/// If the certificates do not form a single, linear chain.
+pub fn order_leaf_first(mut certs: Vec<Certificate>) -> Result<Vec<Certificate>, Error> {
+    let issues_another = |cert: &Certificate, all: &[Certificate]| {
+        all.iter()
+            .any(|other| other != cert && is_issued_by(other, cert))
+    };
+    let leaf_idxs: Vec<usize> = certs
+        .iter()
+        .enumerate()
+        .filter(|(_idx, cert)| !issues_another(cert, &certs))
+        .map(|(idx, _cert)| idx)
+        .collect();
+    let [leaf_idx] = leaf_idxs.as_slice() else {
+        return Err(cert_err(format!(
+            "The certificates do not form a single chain; found {} leaf certificates",
+            leaf_idxs.len()
+        )));
+    };
+    let mut chain = vec![certs.swap_remove(*leaf_idx)];
+    while let Some(current) = chain.last() {
+        if is_issued_by(current, current) {
+            break;
+        }
+        let Some(issuer_idx) = certs.iter().position(|cert| is_issued_by(current, cert)) else {
+            // The chain may end without the root certificate
+            break;
+        };
+        chain.push(certs.swap_remove(issuer_idx));
+    }
+    if !certs.is_empty() {
+        return Err(cert_err(format!(
+            "These certificates are not part of the chain: {}",
+            certs
+                .iter()
+                .map(subject_name)
+                .collect::<Vec<_>>()
+                .join("; ")
+        )));
+    }
+    Ok(chain)
+}
+
+/// Checks that the public key of the (leaf) certificate
+/// is the given one (DER encoded `SubjectPublicKeyInfo`),
+/// which belongs to the signing key.
+///
+/// # Errors
+///
+/// If the keys do not match.
+pub fn check_key(leaf: &Certificate, public_key_der: &[u8]) -> BoxResult<()> {
+    let key_spki = SubjectPublicKeyInfoOwned::from_der(public_key_der)?;
+    let cert_spki = &leaf.tbs_certificate.subject_public_key_info;
+    if cert_spki.algorithm.oid != key_spki.algorithm.oid
+        || cert_spki.subject_public_key != key_spki.subject_public_key
+    {
+        return Err(cert_err(format!(
+            "The public key of the leaf certificate '{}' does not match the signing key",
+            subject_name(leaf)
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Encodes the chain as the value of a JWS `x5c` header;
+/// base64 (not base64url) encoded DER, leaf-first.
+///
+/// # Errors
+///
+/// If DER encoding fails, which should never happen.
+pub fn to_x5c(chain: &[Certificate]) -> BoxResult<Vec<String>> {
+    chain
+        .iter()
+        .map(|cert| Ok(BASE64.encode(cert.to_der()?)))
+        .collect()
+}
+
+/// Reads a certificate chain from one or more files
+/// (PEM bundles or DER certificates),
+/// orders it leaf-first and checks that the leaf fits the signing key.
+///
+/// The signing key is given by its public key
+/// (DER encoded `SubjectPublicKeyInfo`).
+///
+/// # Errors
+///
+/// If reading any of the files fails.
+///
+/// If the certificates do not form a single chain.
+///
+/// If the leaf certificate does not match the signing key.
+pub fn read<P: AsRef<Path>>(
+    cert_files: &[P],
+    public_key_der: &[u8],
+) -> BoxResult<Vec<Certificate>> {
+    let mut certs = Vec::new();
+    for cert_file in cert_files {
+        certs.extend(read_file(cert_file)?);
+    }
+    let chain = order_leaf_first(certs)?;
+    if let Some(leaf) = chain.first() {
+        check_key(leaf, public_key_der)?;
+        log::info!(
+            "Using certificate chain of length {}, with leaf '{}'.",
+            chain.len(),
+            subject_name(leaf)
+        );
+    }
+    Ok(chain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants;
+    use crate::key::PrivateKey;
+
+    const CHAIN_PEM: &str = "res/keys/rsa-2048.chain.pem";
+    const KEY_RSA_PKCS1_PEM: &str = "res/keys/rsa-2048.pkcs1.pem";
+
+    fn subjects(chain: &[Certificate]) -> Vec<String> {
+        chain.iter().map(subject_name).collect()
+    }
+
+    #[test]
+    fn pem_bundle() -> BoxResult<()> {
+        let key = PrivateKey::from_file(KEY_RSA_PKCS1_PEM)?;
+        let chain = read(&[CHAIN_PEM], &key.public_key_der()?)?;
+        assert_eq!(
+            subjects(&chain),
+            [
+                "CN=obadgen Test Badge Signer",
+                "CN=obadgen Test Intermediate CA",
+                "CN=obadgen Test Root CA",
+            ]
+        );
+        let x5c_decoded = to_x5c(&chain)?
+            .iter()
+            .map(|cert| Ok(Certificate::from_der(&BASE64.decode(cert)?)?))
+            .collect::<BoxResult<Vec<_>>>()?;
+        assert_eq!(x5c_decoded, chain);
+        Ok(())
+    }
+
+    #[test]
+    fn der_files() -> BoxResult<()> {
+        let key = PrivateKey::from_file(KEY_RSA_PKCS1_PEM)?;
+        let dir = tempfile::tempdir()?;
+        let mut files = Vec::new();
+        for (idx, cert) in read_file(CHAIN_PEM)?.iter().enumerate() {
+            let file = dir.path().join(format!("cert-{idx}.der"));
+            fs::write(&file, cert.to_der()?)?;
+            files.push(file);
+        }
+        let chain = read(&files, &key.public_key_der()?)?;
+        assert_eq!(
+            subjects(&chain).first().map(String::as_str),
+            Some("CN=obadgen Test Badge Signer")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_key() -> BoxResult<()> {
+        let key = PrivateKey::from_file(constants::ISSUER_KEY_PATH_PRIV)?;
+        assert!(read(&[CHAIN_PEM], &key.public_key_der()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn broken_chain() -> BoxResult<()> {
+        let mut certs = read_file(CHAIN_PEM)?;
+        certs.extend(read_file(constants::ISSUER_CERT_PATH_PUB)?);
+        assert!(order_leaf_first(certs).is_err());
+        Ok(())
+    }
+}
@@ -2,18 +2,28 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
-use std::fs;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::path::Path;
 use std::path::PathBuf;
 
-use rcgen::CertifiedKey;
-// use rcgen::CertificateParams;
+use p256::pkcs8::LineEnding;
+use rcgen::CertificateParams;
+use rcgen::CertificateSigningRequest;
+use rcgen::DistinguishedName;
+use rcgen::DnType;
 use rcgen::Error as RcgenError;
-// use rcgen::PKCS_RSA_SHA256;
-
 use rcgen::KeyPair;
+use rcgen::SignatureAlgorithm;
 use thiserror::Error;
 
+use crate::box_err::BoxResult;
+use crate::key::PrivateKey;
+use crate::settings::{CertKind, KeygenSettings};
+use crate::signature::Algorithm;
+
 #[derive(Error, Debug)]
 pub enum Error {
     /// Represents all cases of `std::io::Error`.
@@ -23,23 +33,176 @@ pub enum Error {
     /// Represents all other cases of `rcgen::RcgenError`.
     #[error(transparent)]
     Rcgen(#[from] RcgenError),
+
+    /// The key could not be encoded.
+    #[error("Failed to encode the key: {0}")]
+    KeyEncoding(String),
+
+    /// *rcgen* can not create certificates for this kind of key.
+    #[error("Certificates are not supported for signing algorithm {0}")]
+    UnsupportedAlgorithm(&'static str),
+
+    /// The subject distinguished name could not be parsed.
+    #[error("Invalid subject '{subject}': {msg}")]
+    InvalidSubject { subject: String, msg: String },
+
+    /// A file to be written exists already.
+    #[error("Refusing to overwrite the existing file '{}'", .0.display())]
+    FileExists(PathBuf),
+
+    /// The end of the validity period is not a supported date.
+    #[error("A certificate valid for {0} days from now is out of the supported date range")]
+    ValidityOutOfRange(u32),
 }
 
-// pub fn create_rsa_cert(
-//     subject_alt_names: impl Into<Vec<String>>,
-// ) -> Result<Certificate, RcgenError> {
-//     let mut params = CertificateParams::new(subject_alt_names);
-//     params.alg = &PKCS_RSA_SHA256;
-//     // *ZONK* RSA key-gen not supported!
-//     Certificate::from_params(params)
-// }
+/// The maximum validity period of a self-signed certificate, in days;
+/// about 100 years.
+pub const MAX_VALIDITY_DAYS: u32 = 36_500;
+
+/// The signature algorithm of *rcgen* matching ours, if it supports it.
+const fn rcgen_algorithm(alg: Algorithm) -> Option<&'static SignatureAlgorithm> {
+    match alg {
+        Algorithm::RS256 => Some(&rcgen::PKCS_RSA_SHA256),
+        Algorithm::RS384 => Some(&rcgen::PKCS_RSA_SHA384),
+        Algorithm::RS512 => Some(&rcgen::PKCS_RSA_SHA512),
+        Algorithm::ES256 => Some(&rcgen::PKCS_ECDSA_P256_SHA256),
+        Algorithm::ES384 => Some(&rcgen::PKCS_ECDSA_P384_SHA384),
+        Algorithm::EdDSA => Some(&rcgen::PKCS_ED25519),
+        Algorithm::None | Algorithm::ES256K => None,
+    }
+}
+
+/// Parses a distinguished name like "CN=Example,O=Example Org".
+/// Supported are the attributes CN, O, OU, C, ST and L;
+/// values may not contain ','.
+///
+/// # Errors
+///
+/// If a part is not of the form "<ATTRIBUTE>=<VALUE>",
+/// or uses an unsupported attribute.
+pub fn parse_distinguished_name(subject: &str) -> Result<DistinguishedName, Error> {
+    let invalid = |msg: String| Error::InvalidSubject {
+        subject: subject.to_owned(),
+        msg,
+    };
+    let mut name = DistinguishedName::new();
+    for part in subject.split(',') {
+        let (attr, value) = part
+            .split_once('=')
+            .ok_or_else(|| invalid(format!("'{part}' is not of the form <ATTRIBUTE>=<VALUE>")))?;
+        let dn_type = match attr.trim() {
+            "CN" => DnType::CommonName,
+            "O" => DnType::OrganizationName,
+            "OU" => DnType::OrganizationalUnitName,
+            "C" => DnType::CountryName,
+            "ST" => DnType::StateOrProvinceName,
+            "L" => DnType::LocalityName,
+            other => {
+                return Err(invalid(format!(
+                    "Unsupported attribute '{other}'; use one of CN, O, OU, C, ST, L"
+                )))
+            }
+        };
+        name.push(dn_type, value.trim());
+    }
+    Ok(name)
+}
+
+/// What is stored about the public part of the key.
+pub enum Public {
+    /// Only the public key
+    Key,
+    /// A (self-signed) certificate
+    Cert(rcgen::Certificate),
+    /// A certificate signing request
+    Request(CertificateSigningRequest),
+}
 
 pub struct Container {
-    pub certified_key: CertifiedKey<KeyPair>,
+    pub key: PrivateKey,
+    pub public: Public,
     pub file_base: PathBuf,
 }
 
+/// Generates a new key,
+/// and if requested, a self-signed certificate or a certificate signing request for it.
+///
+/// # Errors
+///
+/// If key generation failed.
+///
+/// If the certificate (request) could not be created.
+pub fn create(settings: &KeygenSettings) -> BoxResult<Container> {
+    let key = PrivateKey::generate(settings.algorithm, settings.rsa_bits)?;
+    log::info!("Generated a new {} private key.", key.name());
+    Ok(Container::new(key, settings)?)
+}
+
 impl Container {
+    /// Creates a certificate (request) for `key`,
+    /// as requested in `settings`,
+    /// but does not write anything yet.
+    ///
+    /// # Errors
+    ///
+    /// If the key does not fit the signing algorithm,
+    /// or *rcgen* does not support certificates for it.
+    ///
+    /// If the subject or the subject alternative names are invalid.
+    ///
+    /// If the validity period ends out of the supported date range.
+    pub fn new(key: PrivateKey, settings: &KeygenSettings) -> Result<Self, Error> {
+        let public = match settings.cert {
+            CertKind::None => Public::Key,
+            CertKind::SelfSigned | CertKind::Request => {
+                let key_pair = Self::key_pair(&key, settings.algorithm)?;
+                let mut params = CertificateParams::new(settings.subject_alt_names.clone())?;
+                params.distinguished_name = if let Some(subject) = &settings.subject {
+                    parse_distinguished_name(subject)?
+                } else {
+                    let mut name = DistinguishedName::new();
+                    if let Some(first_san) = settings.subject_alt_names.first() {
+                        name.push(DnType::CommonName, first_san.as_str());
+                    }
+                    name
+                };
+                if settings.cert == CertKind::SelfSigned {
+                    params.not_before = time::OffsetDateTime::now_utc();
+                    params.not_after = params
+                        .not_before
+                        .checked_add(time::Duration::days(i64::from(settings.validity_days)))
+                        .ok_or(Error::ValidityOutOfRange(settings.validity_days))?;
+                    Public::Cert(params.self_signed(&key_pair)?)
+                } else {
+                    Public::Request(params.serialize_request(&key_pair)?)
+                }
+            }
+        };
+        Ok(Self {
+            key,
+            public,
+            file_base: settings.file_base.clone(),
+        })
+    }
+
+    /// Converts our key into one *rcgen* can sign certificates with.
+    fn key_pair(key: &PrivateKey, alg: Algorithm) -> Result<KeyPair, Error> {
+        let rcgen_alg = rcgen_algorithm(alg)
+            .filter(|_| key.supports(alg))
+            .ok_or_else(|| Error::UnsupportedAlgorithm(alg.into()))?;
+        let pem = Self::to_pem("PRIVATE KEY", &Self::encoded(key.to_pkcs8_der())?)?;
+        Ok(KeyPair::from_pem_and_sign_algo(&pem, rcgen_alg)?)
+    }
+
+    fn encoded<T>(result: BoxResult<T>) -> Result<T, Error> {
+        result.map_err(|err| Error::KeyEncoding(err.to_string()))
+    }
+
+    fn to_pem(label: &str, der: &[u8]) -> Result<String, Error> {
+        pem_rfc7468::encode_string(label, LineEnding::LF, der)
+            .map_err(|err| Error::KeyEncoding(err.to_string()))
+    }
+
     fn file_name_add<P: AsRef<Path>>(base: P, addition: &str) -> PathBuf {
         let mut file_name = base
             .as_ref()
@@ -54,9 +217,13 @@ impl Container {
         Self::file_name_add(&self.file_base, addition)
     }
 
-    #[must_use]
-    pub fn priv_der(&self) -> Vec<u8> {
-        self.certified_key.signing_key.serialize_der()
+    /// Returns the private key, DER encoded as PKCS#8.
+    ///
+    /// # Errors
+    ///
+    /// If encoding failed.
+    pub fn priv_der(&self) -> Result<Vec<u8>, Error> {
+        Self::encoded(self.key.to_pkcs8_der())
     }
 
     #[must_use]
@@ -64,9 +231,13 @@ impl Container {
         self.file_add(".priv.der")
     }
 
-    #[must_use]
-    pub fn priv_pem(&self) -> String {
-        self.certified_key.signing_key.serialize_pem()
+    /// Returns the private key, PEM encoded as PKCS#8.
+    ///
+    /// # Errors
+    ///
+    /// If encoding failed.
+    pub fn priv_pem(&self) -> Result<String, Error> {
+        Self::to_pem("PRIVATE KEY", &self.priv_der()?)
     }
 
     #[must_use]
@@ -74,27 +245,57 @@ impl Container {
         self.file_add(".priv.pem")
     }
 
-    /// Returns the public certificate (including the public key), DER encoded.
+    /// Returns the public key, DER encoded as `SubjectPublicKeyInfo`.
     ///
     /// # Errors
     ///
     /// If encoding failed.
-    pub fn cert_der(&self) -> Result<Vec<u8>, RcgenError> {
-        Ok(self.certified_key.cert.der().as_ref().into())
+    pub fn pub_der(&self) -> Result<Vec<u8>, Error> {
+        Self::encoded(self.key.public_key_der())
     }
 
     #[must_use]
-    pub fn cert_der_file(&self) -> PathBuf {
-        self.file_add(".cert.der")
+    pub fn pub_der_file(&self) -> PathBuf {
+        self.file_add(".pub.der")
     }
 
-    /// Returns the public certificate (including the public key), PEM encoded.
+    /// Returns the public key, PEM encoded as `SubjectPublicKeyInfo`.
     ///
     /// # Errors
     ///
     /// If encoding failed.
-    pub fn cert_pem(&self) -> Result<String, RcgenError> {
-        Ok(self.certified_key.cert.pem())
+    pub fn pub_pem(&self) -> Result<String, Error> {
+        Self::encoded(self.key.public_key_pem())
+    }
+
+    #[must_use]
+    pub fn pub_pem_file(&self) -> PathBuf {
+        self.file_add(".pub.pem")
+    }
+
+    /// Returns the public certificate (including the public key), DER encoded,
+    /// if there is one.
+    #[must_use]
+    pub fn cert_der(&self) -> Option<Vec<u8>> {
+        match &self.public {
+            Public::Cert(cert) => Some(cert.der().as_ref().into()),
+            Public::Key | Public::Request(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn cert_der_file(&self) -> PathBuf {
+        self.file_add(".cert.der")
+    }
+
+    /// Returns the public certificate (including the public key), PEM encoded,
+    /// if there is one.
+    #[must_use]
+    pub fn cert_pem(&self) -> Option<String> {
+        match &self.public {
+            Public::Cert(cert) => Some(cert.pem()),
+            Public::Key | Public::Request(_) => None,
+        }
     }
 
     #[must_use]
@@ -102,44 +303,134 @@ impl Container {
         self.file_add(".cert.pem")
     }
 
-    /// Writes all the versions of the certificates files.
+    /// Returns the certificate signing request, DER encoded,
+    /// if there is one.
+    #[must_use]
+    pub fn csr_der(&self) -> Option<Vec<u8>> {
+        match &self.public {
+            Public::Request(csr) => Some(csr.der().as_ref().into()),
+            Public::Key | Public::Cert(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn csr_der_file(&self) -> PathBuf {
+        self.file_add(".csr.der")
+    }
+
+    /// Returns the certificate signing request, PEM encoded,
+    /// if there is one.
     ///
     /// # Errors
     ///
-    /// If encoding failed of the public certificates failed.
+    /// If encoding failed.
+    pub fn csr_pem(&self) -> Result<Option<String>, Error> {
+        Ok(match &self.public {
+            Public::Request(csr) => Some(csr.pem()?),
+            Public::Key | Public::Cert(_) => None,
+        })
+    }
+
+    #[must_use]
+    pub fn csr_pem_file(&self) -> PathBuf {
+        self.file_add(".csr.pem")
+    }
+
+    /// The contents of all the files to be written,
+    /// together with their paths.
+    fn files(&self) -> Result<Vec<(PathBuf, Vec<u8>)>, Error> {
+        let mut files = vec![
+            (self.priv_der_file(), self.priv_der()?),
+            (self.priv_pem_file(), self.priv_pem()?.into_bytes()),
+            (self.pub_der_file(), self.pub_der()?),
+            (self.pub_pem_file(), self.pub_pem()?.into_bytes()),
+        ];
+        if let (Some(der), Some(pem)) = (self.cert_der(), self.cert_pem()) {
+            files.push((self.cert_der_file(), der));
+            files.push((self.cert_pem_file(), pem.into_bytes()));
+        }
+        if let (Some(der), Some(pem)) = (self.csr_der(), self.csr_pem()?) {
+            files.push((self.csr_der_file(), der));
+            files.push((self.csr_pem_file(), pem.into_bytes()));
+        }
+        Ok(files)
+    }
+
+    /// Writes all the versions of the key and certificate (request) files,
+    /// and returns their paths.
+    ///
+    /// Unless `overwrite` is set, nothing is written
+    /// if any of the files exists already.
+    /// On unix, the private key files are only accessible by the owner.
+    ///
+    /// # Errors
+    ///
+    /// If encoding of any of them failed.
+    ///
+    /// If any of the files exists already, and `overwrite` is not set.
     ///
     /// If writing any of the files failed (I/O-Error).
-    pub fn write_files(&self) -> Result<(), Error> {
-        fs::write(self.priv_der_file(), self.priv_der())?;
-        fs::write(self.priv_pem_file(), self.priv_pem())?;
-        fs::write(self.cert_der_file(), self.cert_der()?)?;
-        fs::write(self.cert_pem_file(), self.cert_pem()?)?;
+    pub fn write_files(&self, overwrite: bool) -> Result<Vec<PathBuf>, Error> {
+        let files = self.files()?;
+        if !overwrite {
+            if let Some((existing, _content)) = files.iter().find(|(file, _content)| file.exists())
+            {
+                return Err(Error::FileExists(existing.clone()));
+            }
+        }
+        let private_files = [self.priv_der_file(), self.priv_pem_file()];
+        for (file, content) in &files {
+            Self::write_file(file, content, overwrite, private_files.contains(file))?;
+        }
+        Ok(files.into_iter().map(|(file, _content)| file).collect())
+    }
+
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn write_file(
+        file: &Path,
+        content: &[u8],
+        overwrite: bool,
+        private: bool,
+    ) -> Result<(), Error> {
+        let mut options = OpenOptions::new();
+        options.write(true);
+        if overwrite {
+            options.create(true).truncate(true);
+        } else {
+            options.create_new(true);
+        }
+        #[cfg(unix)]
+        if private {
+            options.mode(0o600);
+        }
+        let mut output = options.open(file).map_err(|err| {
+            if err.kind() == std::io::ErrorKind::AlreadyExists {
+                Error::FileExists(file.to_path_buf())
+            } else {
+                err.into()
+            }
+        })?;
+        #[cfg(unix)]
+        if private {
+            // The mode above is only applied to newly created files
+            output.set_permissions(fs::Permissions::from_mode(0o600))?;
+        }
+        output.write_all(content)?;
         Ok(())
     }
 
     /// Writes the REUSE/deb5 compatible `*.license` files
-    /// for all the versions of the certificates files.
+    /// for all the versions of the key and certificate (request) files.
     ///
     /// # Errors
     ///
+    /// If encoding of any of them failed.
+    ///
     /// If writing any of the files failed (I/O-Error).
-    pub fn write_license_files(&self, content: &str) -> std::io::Result<()> {
-        fs::write(
-            Self::file_name_add(self.priv_der_file(), ".license"),
-            content,
-        )?;
-        fs::write(
-            Self::file_name_add(self.priv_pem_file(), ".license"),
-            content,
-        )?;
-        fs::write(
-            Self::file_name_add(self.cert_der_file(), ".license"),
-            content,
-        )?;
-        fs::write(
-            Self::file_name_add(self.cert_pem_file(), ".license"),
-            content,
-        )?;
+    pub fn write_license_files(&self, content: &str) -> Result<(), Error> {
+        for (file, _content) in self.files()? {
+            fs::write(Self::file_name_add(file, ".license"), content)?;
+        }
         Ok(())
     }
 }
@@ -147,10 +438,146 @@ impl Container {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cert_chain;
     use crate::util::test::is_good_error;
 
+    const KEY_RSA_PKCS1_PEM: &str = "res/keys/rsa-2048.pkcs1.pem";
+
+    fn settings(algorithm: Algorithm, cert: CertKind, file_base: &Path) -> KeygenSettings {
+        KeygenSettings {
+            algorithm,
+            rsa_bits: 2048,
+            cert,
+            subject: None,
+            subject_alt_names: vec!["badges.example.org".to_owned()],
+            validity_days: 365,
+            file_base: file_base.to_path_buf(),
+        }
+    }
+
     #[test]
     fn normal_types() {
         is_good_error::<Error>();
     }
+
+    #[test]
+    fn subject() -> Result<(), Error> {
+        let name = parse_distinguished_name("CN=Example, O=Example Org,C=DE")?;
+        assert_eq!(name.iter().count(), 3);
+        assert!(parse_distinguished_name("CN").is_err());
+        assert!(parse_distinguished_name("XY=abc").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn self_signed() -> BoxResult<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let mut settings = settings(
+            Algorithm::ES256,
+            CertKind::SelfSigned,
+            &tmp_dir.path().join("issuer"),
+        );
+        settings.subject = Some("CN=Example Issuer,O=Example Org".to_owned());
+        let container = create(&settings)?;
+        let files = container.write_files(false)?;
+        assert_eq!(files.len(), 6);
+        assert!(files.iter().all(|file| file.exists()));
+
+        let certs = cert_chain::read_file(container.cert_pem_file())?;
+        let cert = certs.first().ok_or("No certificate written")?;
+        // RFC 4514 string representation, which is in reverse order
+        assert_eq!(
+            cert.tbs_certificate.subject.to_string(),
+            "O=Example Org,CN=Example Issuer"
+        );
+        cert_chain::check_key(cert, &container.pub_der()?)?;
+        let reloaded = PrivateKey::from_file(container.priv_pem_file())?;
+        assert_eq!(reloaded.public_key_der()?, container.pub_der()?);
+        Ok(())
+    }
+
+    #[test]
+    fn validity_out_of_range() -> BoxResult<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let mut settings = settings(
+            Algorithm::ES256,
+            CertKind::SelfSigned,
+            &tmp_dir.path().join("issuer"),
+        );
+        settings.validity_days = 4_000_000;
+        let key = PrivateKey::generate(Algorithm::ES256, settings.rsa_bits)?;
+        assert!(matches!(
+            Container::new(key, &settings),
+            Err(Error::ValidityOutOfRange(4_000_000))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn no_accidental_overwrite() -> BoxResult<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let settings = settings(Algorithm::ES256, CertKind::None, &tmp_dir.path().join("k"));
+        let container = create(&settings)?;
+        container.write_files(false)?;
+        let priv_pem = fs::read(container.priv_pem_file())?;
+
+        let other = create(&settings)?;
+        assert!(matches!(
+            other.write_files(false),
+            Err(Error::FileExists(_))
+        ));
+        assert_eq!(fs::read(container.priv_pem_file())?, priv_pem);
+        other.write_files(true)?;
+        assert_ne!(fs::read(container.priv_pem_file())?, priv_pem);
+
+        #[cfg(unix)]
+        for file in [container.priv_pem_file(), container.priv_der_file()] {
+            assert_eq!(fs::metadata(&file)?.permissions().mode() & 0o777, 0o600);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn request_rsa() -> BoxResult<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let settings = settings(
+            Algorithm::RS384,
+            CertKind::Request,
+            &tmp_dir.path().join("issuer"),
+        );
+        let key = PrivateKey::from_file(KEY_RSA_PKCS1_PEM)?;
+        let container = Container::new(key, &settings)?;
+        assert!(container.cert_der().is_none());
+        assert!(container
+            .csr_pem()?
+            .is_some_and(|pem| pem.starts_with("-----BEGIN CERTIFICATE REQUEST-----")));
+        container.write_files(false)?;
+        container.write_license_files("SPDX-License-Identifier: CC0-1.0\n")?;
+        assert!(container
+            .file_add(".csr.der.license")
+            .try_exists()
+            .is_ok_and(|exists| exists));
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported() -> BoxResult<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let file_base = tmp_dir.path().join("issuer");
+        let key_only = create(&settings(Algorithm::ES256K, CertKind::None, &file_base))?;
+        assert_eq!(key_only.write_files(false)?.len(), 4);
+        assert!(create(&settings(
+            Algorithm::ES256K,
+            CertKind::SelfSigned,
+            &file_base
+        ))
+        .is_err());
+        let key = PrivateKey::from_file(KEY_RSA_PKCS1_PEM)?;
+        assert!(Container::new(
+            key,
+            &settings(Algorithm::ES256, CertKind::SelfSigned, &file_base)
+        )
+        .is_err());
+        Ok(())
+    }
 }
@@ -5,6 +5,7 @@
 use std::fmt::Display;
 
 // use hex_literal::hex;
+use md5::Md5;
 use sha2::{Digest, Sha256};
 
 pub fn sha256<S: AsRef<str>>(input: S) -> String {
@@ -23,9 +24,28 @@ pub fn sha256<S: AsRef<str>>(input: S) -> String {
     format!("sha256${result:x}")
 }
 
+/// The hex encoded SHA-256 digest of binary data,
+/// without the `sha256$` prefix used for identities.
+#[must_use]
+pub fn sha256_hex(input: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(input))
+}
+
 pub fn sha256_with_salt<S1: AsRef<str> + Display, S2: AsRef<str> + Display>(
     input: S1,
     salt: S2,
 ) -> String {
     sha256(format!("{input}{salt}"))
 }
+
+pub fn md5<S: AsRef<str>>(input: S) -> String {
+    let result = Md5::digest(input.as_ref().as_bytes());
+    format!("md5${result:x}")
+}
+
+pub fn md5_with_salt<S1: AsRef<str> + Display, S2: AsRef<str> + Display>(
+    input: S1,
+    salt: S2,
+) -> String {
+    md5(format!("{input}{salt}"))
+}
@@ -0,0 +1,242 @@
+// SPDX-FileCopyrightText: 2026 Robin Vobruba <hoijui.quaero@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Publishing of the public key of an issuer,
+//! as a `CryptographicKey` document,
+//! which verifiers of signed badges fetch.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use p256::pkcs8::LineEnding;
+use serde_json::Value;
+use x509_cert::der::Encode;
+
+use crate::box_err::BoxResult;
+use crate::cert_chain;
+use crate::key::PrivateKey;
+use crate::process::Error;
+use crate::settings::PublishKeySettings;
+use crate::signature;
+use crate::CryptographicKey;
+
+/// Reads the public key as PEM encoded `SubjectPublicKeyInfo`.
+///
+/// The file is either a private key (see [`PrivateKey::from_file`])
+/// or a certificate (chain) (see [`cert_chain::read_file`]),
+/// in which case the key of the leaf certificate is used.
+///
+/// # Errors
+///
+/// If the file is neither a supported private key,
+/// nor a certificate with a supported public key.
+pub fn read_public_key_pem<P: AsRef<Path>>(key_or_cert_file: P) -> BoxResult<String> {
+    let key_or_cert_file = key_or_cert_file.as_ref();
+    let key_err = match PrivateKey::from_file(key_or_cert_file) {
+        Ok(key) => return key.public_key_pem(),
+        Err(err) => err,
+    };
+    let certs = cert_chain::read_file(key_or_cert_file).map_err(|cert_err| {
+        Error::InvalidSettings {
+            msg: format!(
+                "'{}' is neither a supported private key ({key_err}), nor a certificate ({cert_err})",
+                key_or_cert_file.display()
+            ),
+        }
+    })?;
+    let leaf = cert_chain::order_leaf_first(certs)?.swap_remove(0);
+    let public_key_der = leaf.tbs_certificate.subject_public_key_info.to_der()?;
+    // Makes sure we will be able to verify with it
+    signature::load_public_key_der(&public_key_der)?;
+    Ok(
+        pem_rfc7468::encode_string("PUBLIC KEY", LineEnding::LF, &public_key_der)
+            .map_err(|err| format!("Failed to PEM encode the public key: {err}"))?,
+    )
+}
+
+/// Returns the `id` of an `Issuer` JSON-LD document.
+///
+/// # Errors
+///
+/// If `issuer` is not a JSON object with a string `id`.
+pub fn issuer_id(issuer: &Value) -> Result<&str, Error> {
+    issuer
+        .get("id")
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::InvalidSettings {
+            msg: "The issuer has no (string) 'id'".to_owned(),
+        })
+}
+
+/// Adds a key to the `publicKey`s of an `Issuer` JSON-LD document,
+/// leaving everything else untouched.
+///
+/// Keys already listed are kept, so old badges remain verifiable
+/// after a key rotation.
+///
+/// # Errors
+///
+/// If `issuer` is not a JSON object,
+/// or its `publicKey` is neither a string nor an array.
+pub fn link_issuer(issuer: &mut Value, key_id: &str) -> Result<(), Error> {
+    let issuer = issuer
+        .as_object_mut()
+        .ok_or_else(|| Error::InvalidSettings {
+            msg: "The issuer is not a JSON object".to_owned(),
+        })?;
+    let new_key = Value::String(key_id.to_owned());
+    match issuer.get_mut("publicKey") {
+        None => {
+            issuer.insert("publicKey".to_owned(), new_key);
+        }
+        Some(Value::String(existing)) if existing == key_id => {}
+        Some(existing @ Value::String(_)) => {
+            *existing = Value::Array(vec![existing.take(), new_key]);
+        }
+        Some(Value::Array(keys)) => {
+            if !keys.contains(&new_key) {
+                keys.push(new_key);
+            }
+        }
+        Some(_) => {
+            return Err(Error::InvalidSettings {
+                msg: "The 'publicKey' of the issuer is neither a string nor an array".to_owned(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Creates the `CryptographicKey` document,
+/// and links it from the issuer file, if one is given.
+///
+/// # Errors
+///
+/// If the key could not be read (see [`read_public_key_pem`]).
+///
+/// If the issuer file could not be read, parsed or written,
+/// or its `id` is not the given owner.
+///
+/// If neither an owner nor an issuer file is given.
+pub fn run(settings: &PublishKeySettings) -> BoxResult<CryptographicKey> {
+    let public_key_pem = read_public_key_pem(&settings.key_loc)?;
+
+    let issuer_opt = settings
+        .issuer_loc
+        .as_ref()
+        .map(|issuer_loc| -> BoxResult<(&PathBuf, Value)> {
+            let issuer = serde_json::from_str(&fs::read_to_string(issuer_loc)?)?;
+            Ok((issuer_loc, issuer))
+        })
+        .transpose()?;
+    let owner = match (&settings.owner, &issuer_opt) {
+        (Some(owner), Some((_loc, issuer))) if owner != issuer_id(issuer)? => {
+            return Err(Error::InvalidSettings {
+                msg: format!(
+                    "The owner of the key '{owner}' is not the issuer '{}'",
+                    issuer_id(issuer)?
+                ),
+            }
+            .into());
+        }
+        (Some(owner), _) => owner.clone(),
+        (None, Some((_loc, issuer))) => issuer_id(issuer)?.to_owned(),
+        (None, None) => {
+            return Err(Error::InvalidSettings {
+                msg: "Either the owner of the key or the issuer file is required".to_owned(),
+            }
+            .into());
+        }
+    };
+    let key = CryptographicKey::new(settings.key_id.clone(), owner, public_key_pem);
+
+    if let Some((issuer_loc, mut issuer)) = issuer_opt {
+        link_issuer(&mut issuer, &key.id)?;
+        fs::write(issuer_loc, serde_json::to_string_pretty(&issuer)?)?;
+        log::info!(
+            "Added the public key '{}' to the issuer in '{}'.",
+            key.id,
+            issuer_loc.display()
+        );
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants;
+
+    const KEY_ID: &str = "https://example.org/key.json";
+
+    #[test]
+    fn from_key_or_cert() -> BoxResult<()> {
+        let from_key = read_public_key_pem(constants::ISSUER_KEY_PATH_PRIV)?;
+        assert!(from_key.starts_with("-----BEGIN PUBLIC KEY-----\n"));
+        assert!(from_key.ends_with("-----END PUBLIC KEY-----\n"));
+        let from_cert = read_public_key_pem(constants::ISSUER_CERT_PATH_PUB)?;
+        assert_eq!(from_key, from_cert);
+        assert!(read_public_key_pem(constants::ISSUER_KEY_PATH).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn owner_from_issuer() -> BoxResult<()> {
+        let issuer_file = tempfile::NamedTempFile::new()?;
+        fs::write(
+            issuer_file.path(),
+            fs::read_to_string(constants::ISSUER_SIMPLE_PATH)?,
+        )?;
+        let mut settings = PublishKeySettings {
+            key_loc: constants::ISSUER_KEY_PATH_PRIV.into(),
+            key_id: KEY_ID.to_owned(),
+            owner: None,
+            issuer_loc: Some(issuer_file.path().to_path_buf()),
+        };
+        let key = run(&settings)?;
+        assert_eq!(key.owner, constants::ISSUER_SIMPLE_ID);
+
+        let issuer: Value = serde_json::from_str(&fs::read_to_string(issuer_file.path())?)?;
+        assert_eq!(issuer.get("publicKey"), Some(&serde_json::json!(KEY_ID)));
+        assert_eq!(
+            issuer.get("name"),
+            Some(&serde_json::json!("Issuer - simple"))
+        );
+
+        settings.owner = Some("https://example.org/other-issuer.json".to_owned());
+        assert!(run(&settings).is_err());
+        settings.issuer_loc = None;
+        assert_eq!(
+            run(&settings)?.owner,
+            "https://example.org/other-issuer.json"
+        );
+        settings.owner = None;
+        assert!(run(&settings).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn link_rotated_keys() -> Result<(), Error> {
+        const KEY_ID_NEW: &str = "https://example.org/key-2.json";
+        let mut issuer = serde_json::json!({ "id": constants::ISSUER_SIMPLE_ID });
+        link_issuer(&mut issuer, KEY_ID)?;
+        assert_eq!(issuer.get("publicKey"), Some(&serde_json::json!(KEY_ID)));
+        link_issuer(&mut issuer, KEY_ID)?;
+        assert_eq!(issuer.get("publicKey"), Some(&serde_json::json!(KEY_ID)));
+        link_issuer(&mut issuer, KEY_ID_NEW)?;
+        assert_eq!(
+            issuer.get("publicKey"),
+            Some(&serde_json::json!([KEY_ID, KEY_ID_NEW]))
+        );
+        link_issuer(&mut issuer, KEY_ID_NEW)?;
+        assert_eq!(
+            issuer.get("publicKey"),
+            Some(&serde_json::json!([KEY_ID, KEY_ID_NEW]))
+        );
+        let mut invalid =
+            serde_json::json!({ "id": constants::ISSUER_SIMPLE_ID, "publicKey": true });
+        assert!(link_issuer(&mut invalid, KEY_ID).is_err());
+        Ok(())
+    }
+}
@@ -2,22 +2,32 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
+pub mod assertion_gen;
+pub mod batch;
 pub mod box_err;
+pub mod cert_chain;
 pub mod cert_gen;
 pub mod constants;
 pub mod hash;
+pub mod key;
+pub mod key_publish;
 mod objects;
 pub mod patcher;
 pub mod process;
 pub mod settings;
 pub mod signature;
+#[cfg(feature = "jsonwebtoken")]
+pub mod signature_jsonwebtoken;
+pub mod signer;
 pub mod std_error;
 pub mod util;
+pub mod verify;
 
 pub use objects::assertion::Obj as Assertion;
 pub use objects::badge_class::Obj as BadgeClass;
 pub use objects::cryptographic_key::Obj as CryptographicKey;
 pub use objects::evidence::Obj as Evidence;
+pub use objects::identity::Error as IdentityError;
 pub use objects::identity::Obj as Identity;
 pub use objects::identity::ObjType as IdentityType;
 pub use objects::issuer::Obj as Issuer;
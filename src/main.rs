@@ -2,19 +2,28 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
+use chrono::{DateTime, FixedOffset};
 use clap::{command, value_parser, Arg, ArgAction, ArgMatches, Command, ValueHint};
 use const_format::formatcp;
 use obadgen::box_err::BoxResult;
+use obadgen::cert_gen::MAX_VALIDITY_DAYS;
 use obadgen::constants::BADGE_ASSERTION_SIMPLE_ID;
 use obadgen::constants::BADGE_ASSERTION_WITH_KEY_ID;
-use obadgen::signature::Algorithm;
+use obadgen::key::{MAX_RSA_BITS, MIN_RSA_BITS};
+use obadgen::patcher::ImageType;
+use obadgen::signature::{self, Algorithm, HeaderOptions, Serialization};
+use obadgen::verify::{DirFetcher, Fetcher, HttpFetcher, Verified};
+use obadgen::{Identity, IdentityType, ToJsonLd};
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::LazyLock;
 
 mod logger;
 
-use obadgen::settings::{self, Settings, Verbosity};
+use obadgen::settings::{
+    self, BatchSettings, CertKind, HostedPayload, IdentityHashing, KeygenSettings,
+    NewAssertionSettings, PublishKeySettings, Settings, Verbosity, VerificationKind,
+};
 
 pub const A_L_VERSION: &str = "version";
 pub const A_S_VERSION: char = 'V';
@@ -25,18 +34,75 @@ const A_S_ASSERTION: char = 'a';
 const A_L_ASSERTION: &str = "assertion";
 // const A_S_SIGNING_ALGORITHM: char = 'A';
 const A_L_SIGNING_ALGORITHM: &str = "signing-algorithm";
+const A_L_INFER_SIGNING_ALGORITHM: &str = "infer-signing-algorithm";
 const A_S_SIGNING_PRIVATE_KEY: char = 'k';
 const A_L_SIGNING_PRIVATE_KEY: &str = "key";
+const A_L_SIGNING_COMMAND: &str = "signing-command";
+const A_L_SIGNING_PUBLIC_KEY: &str = "signing-public-key";
+const A_L_SIGNING_KEY_ID: &str = "key-id";
+const A_L_JWS_TYPE: &str = "jws-type";
+const A_L_JWS_CONTENT_TYPE: &str = "jws-content-type";
+const A_L_JWS_EMBED_JWK: &str = "jws-embed-jwk";
+const A_L_JWS_OUTPUT: &str = "jws-output";
+const A_L_JWS_SERIALIZATION: &str = "jws-serialization";
+const A_L_SIDECARS: &str = "sidecars";
+const A_L_PNG_COMPRESS: &str = "png-compress";
+const A_L_PAYLOAD_WARN_SIZE: &str = "payload-warn-size";
+const A_S_CERT: char = 'c';
+const A_L_CERT: &str = "cert";
 const A_S_SOURCE_IMAGE: char = 's';
 const A_L_SOURCE_IMAGE: &str = "source-image";
 const A_S_BAKED_IMAGE: char = 'b';
 const A_L_BAKED_IMAGE: &str = "baked";
+const A_L_FORMAT: &str = "format";
+const A_S_BAKE_URL: char = 'u';
+const A_L_BAKE_URL: &str = "bake-url";
 const A_S_VERBOSE: char = 'v';
 const A_L_VERBOSE: &str = "verbose";
 const A_S_LOG_LEVEL: char = 'F';
 const A_L_LOG_LEVEL: &str = "log-level";
 const A_S_QUIET: char = 'q';
 const A_L_QUIET: &str = "quiet";
+const A_L_IMAGE: &str = "image";
+const A_L_INPUT: &str = "input";
+const A_L_RESOLVE_DIR: &str = "resolve-dir";
+const A_L_RESOLVE_BASE: &str = "resolve-base";
+const A_L_BADGE: &str = "badge";
+const A_S_RECIPIENTS: char = 'r';
+const A_L_RECIPIENTS: &str = "recipients";
+const A_S_OUT_DIR: char = 'o';
+const A_L_OUT_DIR: &str = "out-dir";
+const A_L_ID_BASE: &str = "id-base";
+const SC_EXTRACT: &str = "extract";
+const SC_VERIFY: &str = "verify";
+const A_L_ID: &str = "id";
+const A_L_RECIPIENT: &str = "recipient";
+const A_L_IDENTITY_TYPE: &str = "identity-type";
+const A_L_PLAIN_IDENTITY: &str = "plain-identity";
+const A_L_SALT: &str = "salt";
+const A_L_ISSUED_ON: &str = "issued-on";
+const A_L_EXPIRES: &str = "expires";
+const A_L_EVIDENCE: &str = "evidence";
+const A_L_NARRATIVE: &str = "narrative";
+const A_L_VERIFICATION: &str = "verification";
+const A_L_CREATOR: &str = "creator";
+const A_S_OUTPUT: char = 'o';
+const A_L_OUTPUT: &str = "output";
+const SC_BATCH: &str = "batch";
+const SC_NEW_ASSERTION: &str = "new-assertion";
+const SC_CHECK_RECIPIENT: &str = "check-recipient";
+const A_L_OWNER: &str = "owner";
+const A_L_ISSUER: &str = "issuer";
+const SC_PUBLISH_KEY: &str = "publish-key";
+const A_L_RSA_BITS: &str = "rsa-bits";
+const A_L_CERT_KIND: &str = "cert-kind";
+const A_L_SUBJECT: &str = "subject";
+const A_L_SAN: &str = "san";
+const A_L_VALIDITY_DAYS: &str = "validity-days";
+const A_L_FILE_BASE: &str = "file-base";
+const A_L_FORCE: &str = "force";
+const SC_KEYGEN: &str = "keygen";
+const SC_CHECK_KEY: &str = "check-key";
 // const A_S_OVERWRITE: char = 'o';
 // const A_L_OVERWRITE: &str = "overwrite";
 // const A_S_LIST: char = 'l';
@@ -114,7 +180,8 @@ fn arg_signing_algorithm() -> Arg {
         .long_help(formatcp!(
             "Which signing algorithm to use. \
             This has to correspond to the private key type you supply, \
-            see -{A_S_SIGNING_PRIVATE_KEY}, --{A_L_SIGNING_PRIVATE_KEY}.",
+            see -{A_S_SIGNING_PRIVATE_KEY}, --{A_L_SIGNING_PRIVATE_KEY}. \
+            Alternatively, see --{A_L_INFER_SIGNING_ALGORITHM}.",
         ))
         .num_args(1)
         .value_parser(value_parser!(Algorithm))
@@ -126,13 +193,32 @@ fn arg_signing_algorithm() -> Arg {
         .required(false)
 }
 
+fn arg_infer_signing_algorithm() -> Arg {
+    Arg::new(A_L_INFER_SIGNING_ALGORITHM)
+        .help("Infer the signing algorithm from the private key.")
+        .long_help(formatcp!(
+            "Infer the signing algorithm from the private key type, \
+            instead of requiring --{A_L_SIGNING_ALGORITHM}. \
+            This uses RS256 for RSA keys, \
+            ES256 for ECDSA P-256 keys, ES384 for ECDSA P-384 keys, \
+            ES256K for ECDSA secp256k1 keys and EdDSA for Ed25519 keys.",
+        ))
+        .action(ArgAction::SetTrue)
+        .long(A_L_INFER_SIGNING_ALGORITHM)
+        .conflicts_with(A_L_SIGNING_ALGORITHM)
+        .requires(A_L_SIGNING_PRIVATE_KEY)
+        .required(false)
+}
+
 fn arg_key_file() -> Arg {
     Arg::new(A_L_SIGNING_PRIVATE_KEY)
         .help("Read a cryptographic private-key from this file.")
         .long_help(
             "Read a cryptographic private-key from this file. \
-            Currently only this is supported: \
-            * DER encoding of RSA keys",
+            Supported are RSA, ECDSA P-256, ECDSA P-384, ECDSA secp256k1 and Ed25519 keys, \
+            in PEM or DER format, \
+            encoded as PKCS#8, PKCS#1 (RSA) or SEC1 (ECDSA); \
+            the format and encoding are detected automatically.",
         )
         .num_args(1)
         .value_parser(value_parser!(std::path::PathBuf))
@@ -145,18 +231,276 @@ fn arg_key_file() -> Arg {
         .required(false)
 }
 
+fn arg_signing_command() -> Arg {
+    Arg::new(A_L_SIGNING_COMMAND)
+        .help("Sign with this external command, instead of with a private-key file.")
+        .long_help(formatcp!(
+            "Sign by running this command (the program followed by its arguments, \
+            each as a separate value, terminated by a ';' \
+            unless this is the last option), \
+            instead of reading a private-key file. \
+            The JWS signing input is written to its standard input, \
+            and it has to write the signature to its standard output; \
+            for ECDSA, both the JWS (R and S concatenated) \
+            and the DER format are accepted. \
+            This allows to keep the private-key in an HSM or an agent, \
+            for example through a PKCS#11 wrapper. \
+            Requires --{A_L_SIGNING_ALGORITHM} and --{A_L_SIGNING_PUBLIC_KEY}.",
+        ))
+        .num_args(1..)
+        .allow_hyphen_values(true)
+        .value_terminator(";")
+        .value_name("PROGRAM-AND-ARGS")
+        .value_hint(ValueHint::CommandName)
+        .long(A_L_SIGNING_COMMAND)
+        .action(ArgAction::Set)
+        .conflicts_with_all([A_L_SIGNING_PRIVATE_KEY, A_L_INFER_SIGNING_ALGORITHM])
+        .requires(A_L_SIGNING_ALGORITHM)
+        .requires(A_L_SIGNING_PUBLIC_KEY)
+        .required(false)
+}
+
+fn arg_signing_public_key() -> Arg {
+    Arg::new(A_L_SIGNING_PUBLIC_KEY)
+        .help("The public-key belonging to the key used by the signing command.")
+        .long_help(formatcp!(
+            "Read the public-key belonging to the key used by --{A_L_SIGNING_COMMAND} \
+            from this file, in PEM or DER format (SubjectPublicKeyInfo).",
+        ))
+        .num_args(1)
+        .value_parser(value_parser!(std::path::PathBuf))
+        .value_name("PUBLIC-KEY-FILE")
+        .value_hint(ValueHint::FilePath)
+        .long(A_L_SIGNING_PUBLIC_KEY)
+        .action(ArgAction::Set)
+        .requires(A_L_SIGNING_COMMAND)
+        .required(false)
+}
+
+fn arg_signing_key_id() -> Arg {
+    Arg::new(A_L_SIGNING_KEY_ID)
+        .help("The IRI of the CryptographicKey of the signing key")
+        .long_help(formatcp!(
+            "The IRI of the (hosted) CryptographicKey \
+            belonging to the signing key. \
+            It is written into the JWS header ('kid'), \
+            and as the 'verification.creator' of signed assertions, \
+            so verifiers know which of the keys of the issuer to use. \
+            This allows to rotate keys, \
+            while badges signed with older keys remain verifiable. \
+            See also the '{SC_PUBLISH_KEY}' and '{SC_CHECK_KEY}' subcommands.",
+        ))
+        .num_args(1)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .value_name("IRI")
+        .value_hint(ValueHint::Url)
+        .long(A_L_SIGNING_KEY_ID)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
+fn arg_jws_type() -> Arg {
+    Arg::new(A_L_JWS_TYPE)
+        .help("The media type of the signature (JWS 'typ' header)")
+        .long_help(formatcp!(
+            "The media type of the whole signature \
+            (the JWS 'typ' header). \
+            An empty value omits the header. \
+            Defaults to '{}', \
+            or '{}' for --{A_L_JWS_OUTPUT} in a JSON serialization \
+            (see --{A_L_JWS_SERIALIZATION}).",
+            signature::MEDIA_TYPE_COMPACT,
+            signature::MEDIA_TYPE_JSON
+        ))
+        .num_args(1)
+        .value_name("TYPE")
+        .value_hint(ValueHint::Other)
+        .long(A_L_JWS_TYPE)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
+fn arg_jws_content_type() -> Arg {
+    Arg::new(A_L_JWS_CONTENT_TYPE)
+        .help("The media type of the signed assertion (JWS 'cty' header)")
+        .long_help(formatcp!(
+            "The media type of the signed assertion \
+            (the JWS 'cty' header); \
+            by default, the header is omitted.",
+        ))
+        .num_args(1)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .value_name("TYPE")
+        .value_hint(ValueHint::Other)
+        .long(A_L_JWS_CONTENT_TYPE)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
+fn arg_jws_embed_jwk() -> Arg {
+    Arg::new(A_L_JWS_EMBED_JWK)
+        .help("Embed the public key into the signature (JWS 'jwk' header)")
+        .long_help(formatcp!(
+            "Embed the public key into the signature, \
+            as JSON Web Key (the JWS 'jwk' header). \
+            Note that verifiers still have to use the key of the issuer \
+            (see --{A_L_SIGNING_KEY_ID}), \
+            as anyone can embed any key.",
+        ))
+        .num_args(0)
+        .long(A_L_JWS_EMBED_JWK)
+        .action(ArgAction::SetTrue)
+        .required(false)
+}
+
+fn arg_sidecars() -> Arg {
+    Arg::new(A_L_SIDECARS)
+        .help("Write copies of the baked content next to each baked image")
+        .long_help(
+            "Write copies of the baked content next to each baked image, \
+            for auditing without having to unbake it: \
+            the signed compact JWS ('<IMAGE-FILE>.jws', only if signed), \
+            the plain JSON assertion ('<IMAGE-FILE>.json') \
+            and the SHA-256 digest of the baked image \
+            ('<IMAGE-FILE>.sha256', as written by sha256sum).",
+        )
+        .num_args(0)
+        .long(A_L_SIDECARS)
+        .action(ArgAction::SetTrue)
+        .required(false)
+}
+
+fn arg_png_compress() -> Arg {
+    Arg::new(A_L_PNG_COMPRESS)
+        .help("Compress the content baked into PNG images")
+        .long_help(
+            "Compress the content baked into PNG images \
+            (zlib compressed iTXt chunk). \
+            This makes a big difference for signed assertions \
+            with certificate chains or long narratives. \
+            Note that some older tools only read uncompressed content.",
+        )
+        .num_args(0)
+        .long(A_L_PNG_COMPRESS)
+        .action(ArgAction::SetTrue)
+        .required(false)
+}
+
+fn arg_payload_warn_size() -> Arg {
+    Arg::new(A_L_PAYLOAD_WARN_SIZE)
+        .help("Warn if the content to bake is larger than this many bytes")
+        .long_help(
+            "Warn if the content to bake is larger than this many bytes; \
+            0 disables the warning.",
+        )
+        .num_args(1)
+        .value_parser(value_parser!(usize))
+        .value_name("BYTES")
+        .long(A_L_PAYLOAD_WARN_SIZE)
+        .action(ArgAction::Set)
+        .default_value(formatcp!("{}", obadgen::process::DEFAULT_PAYLOAD_WARN_SIZE))
+        .required(false)
+}
+
+fn arg_jws_output() -> Arg {
+    Arg::new(A_L_JWS_OUTPUT)
+        .help("Write the signed assertion (JWS) to this file")
+        .long_help(formatcp!(
+            "Write the signed assertion (JWS) to this file, \
+            in the serialization given with --{A_L_JWS_SERIALIZATION}. \
+            This works with or without baking it into an image \
+            (see --{A_L_BAKED_IMAGE}).",
+        ))
+        .num_args(1)
+        .value_parser(value_parser!(std::path::PathBuf))
+        .value_name("JWS-FILE")
+        .value_hint(ValueHint::FilePath)
+        .long(A_L_JWS_OUTPUT)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
+fn arg_jws_serialization() -> Arg {
+    Arg::new(A_L_JWS_SERIALIZATION)
+        .help("The serialization of the JWS written with --jws-output")
+        .long_help(formatcp!(
+            "The serialization of the JWS written with --{A_L_JWS_OUTPUT}. \
+            Images always get the compact serialization, \
+            as required by the Open Badges baking specification.",
+        ))
+        .num_args(1)
+        .value_parser(value_parser!(Serialization))
+        .value_name("SERIALIZATION")
+        .value_hint(ValueHint::Other)
+        .long(A_L_JWS_SERIALIZATION)
+        .action(ArgAction::Set)
+        .requires(A_L_JWS_OUTPUT)
+        .required(false)
+}
+
+fn arg_cert() -> Arg {
+    Arg::new(A_L_CERT)
+        .help("Include this x.509 certificate chain in the signature (x5c).")
+        .long_help(formatcp!(
+            "Read an x.509 certificate chain from these files, \
+            and include it in the signature (the JWS x5c header). \
+            Each file may be a bundle of PEM certificates, \
+            or a single DER certificate. \
+            The certificates may be given in any order; \
+            the public key of the leaf certificate \
+            has to match the private-key given with \
+            -{A_S_SIGNING_PRIVATE_KEY}, --{A_L_SIGNING_PRIVATE_KEY}, \
+            or the public-key given with --{A_L_SIGNING_PUBLIC_KEY}.",
+        ))
+        .num_args(1..)
+        .value_parser(value_parser!(std::path::PathBuf))
+        .value_name("CERT-FILE")
+        .value_hint(ValueHint::FilePath)
+        .short(A_S_CERT)
+        .long(A_L_CERT)
+        .action(ArgAction::Append)
+        .required(false)
+}
+
+fn arg_bake_url() -> Arg {
+    Arg::new(A_L_BAKE_URL)
+        .help("For hosted assertions, bake the URL instead of the JSON-LD.")
+        .long_help(formatcp!(
+            "For hosted assertions, bake only their URL, \
+            instead of the whole (JSON-LD) assertion. \
+            Without a value, the assertions id is used. \
+            This keeps the image small, \
+            and allows to later change the hosted data. \
+            See also -{A_S_ASSERTION}, --{A_L_ASSERTION}.",
+        ))
+        .num_args(0..=1)
+        .require_equals(true)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .value_name("URL")
+        .value_hint(ValueHint::Url)
+        .short(A_S_BAKE_URL)
+        .long(A_L_BAKE_URL)
+        .action(ArgAction::Set)
+        .conflicts_with(A_L_SIGNING_PRIVATE_KEY)
+        .required(false)
+}
+
 fn arg_source_image() -> Arg {
     Arg::new(A_L_SOURCE_IMAGE)
-        .help("Reads source (unbaked) image from this file; .svg or .png")
-        .long_help(
-            "Reads source (unbaked) image from this file path (.svg or .png). \
-            Two file formats are supported: \
-            * Scalable Vector Graphics - `*.svg` \
-            * Portable Network Graphics - `*.png` \
-            You can choose which format is used by the file-extension.
+        .help("Reads source (unbaked) image from this file; SVG, SVGZ, PNG, JPEG or WebP")
+        .long_help(formatcp!(
+            "Reads source (unbaked) image from this file path. \
+            These file formats are supported: \
+            * Scalable Vector Graphics - `*.svg`, `*.svgz` \
+            * Portable Network Graphics - `*.png`, `*.apng` \
+            * JPEG - `*.jpg`, `*.jpeg` \
+            * WebP - `*.webp` \
+            The format is detected from the file content, \
+            no matter the file-extension, \
+            unless it is set with --{A_L_FORMAT}. \
             Note that \"-\" has no special meaning here; \
             it does not mean stdin, but rather the file \"./-\".",
-        )
+        ))
         .num_args(1)
         .value_parser(value_parser!(std::path::PathBuf))
         .value_name("IMAGE-FILE")
@@ -170,16 +514,15 @@ fn arg_source_image() -> Arg {
 
 fn arg_baked() -> Arg {
     Arg::new(A_L_BAKED_IMAGE)
-        .help("Write baked image into this file; .svg or .png")
-        .long_help(
-            "Write baked Open Badge image at this file path (.svg or .png). \
-            Two file formats are supported: \
-            * Scalable Vector Graphics - `*.svg` \
-            * Portable Network Graphics - `*.png` \
-            You can choose which format is used by the file-extension.
+        .help("Write baked image into this file; same format as the source image")
+        .long_help(formatcp!(
+            "Write baked Open Badge image at this file path. \
+            It has the same format as the source image \
+            (see --{A_L_SOURCE_IMAGE}), \
+            so its file-extension should match that. \
             Note that \"-\" has no special meaning here; \
             it does not mean stdout, but rather the file \"./-\".",
-        )
+        ))
         .num_args(1)
         .value_parser(value_parser!(std::path::PathBuf))
         .value_name("IMAGE-FILE")
@@ -191,6 +534,22 @@ fn arg_baked() -> Arg {
         .required(false)
 }
 
+fn arg_format() -> Arg {
+    Arg::new(A_L_FORMAT)
+        .help("The format of the image; detected from the file content by default")
+        .long_help(
+            "The format of the image. \
+            By default, it is detected from the file content (magic bytes), \
+            which works with wrong or missing file-extensions.",
+        )
+        .num_args(1)
+        .value_parser(value_parser!(ImageType))
+        .value_name("FORMAT")
+        .long(A_L_FORMAT)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
 fn arg_verbose() -> Arg {
     Arg::new(A_L_VERBOSE)
         .help("More verbose log output")
@@ -202,6 +561,7 @@ fn arg_verbose() -> Arg {
         .long(A_L_VERBOSE)
         .action(ArgAction::Count)
         .required(false)
+        .global(true)
 }
 
 fn arg_log_level() -> Arg {
@@ -212,6 +572,7 @@ fn arg_log_level() -> Arg {
         .long(A_L_LOG_LEVEL)
         .action(ArgAction::Set)
         .required(false)
+        .global(true)
         .conflicts_with(A_L_VERBOSE)
         .conflicts_with(A_L_QUIET)
 }
@@ -229,9 +590,683 @@ This does not affect the log level for the log-file.",
         .short(A_S_QUIET)
         .long(A_L_QUIET)
         .required(false)
+        .global(true)
         .conflicts_with(A_L_VERBOSE)
 }
 
+fn arg_image() -> Arg {
+    Arg::new(A_L_IMAGE)
+        .help("Reads the baked image from this file")
+        .long_help(formatcp!(
+            "Reads the baked Open Badge image from this file path \
+            (SVG, SVGZ, PNG, JPEG or WebP). \
+            The format is detected from the file content, \
+            unless it is set with --{A_L_FORMAT}.",
+        ))
+        .num_args(1)
+        .value_parser(value_parser!(std::path::PathBuf))
+        .value_name("IMAGE-FILE")
+        .value_hint(ValueHint::FilePath)
+        .action(ArgAction::Set)
+        .required(true)
+}
+
+fn subcommand_extract() -> Command {
+    Command::new(SC_EXTRACT)
+        .about("Extracts the Open Badge content from a baked image")
+        .long_about(formatcp!(
+            "Extracts (\"unbakes\") the Open Badge content from a baked image, \
+            and prints it to stdout. \
+            Unless -{A_S_QUIET},--{A_L_QUIET} is given, \
+            the kind of the content is printed first, \
+            which is one of: \
+            * Url - the IRI of a hosted Assertion \
+            * Assertion - the JSON-LD Assertion itsself \
+            * Jws - a signed Assertion (compact JWS) \
+            * Unknown - anything else",
+        ))
+        .visible_alias("unbake")
+        .arg(arg_image())
+        .arg(arg_format())
+}
+
+fn arg_input() -> Arg {
+    Arg::new(A_L_INPUT)
+        .help("Reads the badge from this file; a baked image or an assertion")
+        .long_help(
+            "Reads the badge from this file path. \
+            This may either be a baked image \
+            (SVG, SVGZ, PNG, JPEG or WebP; detected from the file content), \
+            or a file containing the Open Badge content directly, \
+            which is one of: \
+            * the IRI of a hosted Assertion \
+            * a JSON-LD Assertion \
+            * a signed Assertion (compact JWS)",
+        )
+        .num_args(1)
+        .value_parser(value_parser!(std::path::PathBuf))
+        .value_name("FILE")
+        .value_hint(ValueHint::FilePath)
+        .action(ArgAction::Set)
+        .required(true)
+}
+
+fn arg_resolve_dir() -> Arg {
+    Arg::new(A_L_RESOLVE_DIR)
+        .help("Resolve IRIs from this local directory, instead of the web")
+        .long_help(formatcp!(
+            "Resolve IRIs located under --{A_L_RESOLVE_BASE} \
+            from this local directory, instead of fetching them from the web. \
+            This is useful for testing, or when verifying offline.",
+        ))
+        .num_args(1)
+        .value_parser(value_parser!(std::path::PathBuf))
+        .value_name("DIR")
+        .value_hint(ValueHint::DirPath)
+        .long(A_L_RESOLVE_DIR)
+        .action(ArgAction::Set)
+        .required(false)
+        .requires(A_L_RESOLVE_BASE)
+}
+
+fn arg_resolve_base() -> Arg {
+    Arg::new(A_L_RESOLVE_BASE)
+        .help("The base IRI that --resolve-dir mirrors")
+        .long_help(formatcp!(
+            "The base IRI that --{A_L_RESOLVE_DIR} mirrors; \
+            for example, with this set to 'https://example.org/badges', \
+            'https://example.org/badges/issuer.json' \
+            is read from '<DIR>/issuer.json'.",
+        ))
+        .num_args(1)
+        .value_name("IRI")
+        .value_hint(ValueHint::Url)
+        .long(A_L_RESOLVE_BASE)
+        .action(ArgAction::Set)
+        .required(false)
+        .requires(A_L_RESOLVE_DIR)
+}
+
+fn subcommand_verify() -> Command {
+    Command::new(SC_VERIFY)
+        .about("Verifies an Open Badge")
+        .long_about(
+            "Verifies an Open Badge (hosted or signed), \
+            following the Open Badges 2.0 verification procedure. \
+            This fetches the Assertion (if hosted), BadgeClass, Issuer \
+            and CryptographicKey (if signed), \
+            checks the signature or the hosting scope, \
+            and whether the badge is expired or revoked. \
+            On success, the verified Assertion is printed to stdout.",
+        )
+        .arg(arg_input())
+        .arg(arg_resolve_dir())
+        .arg(arg_resolve_base())
+}
+
+fn arg_badge() -> Arg {
+    Arg::new(A_L_BADGE)
+        .help("The IRI of the BadgeClass to award")
+        .long_help(
+            "The IRI of the BadgeClass to award; \
+            this replaces the 'badge' of the template assertion.",
+        )
+        .num_args(1)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .value_name("IRI")
+        .value_hint(ValueHint::Url)
+        .long(A_L_BADGE)
+        .action(ArgAction::Set)
+        .required(true)
+}
+
+fn arg_recipients() -> Arg {
+    Arg::new(A_L_RECIPIENTS)
+        .help("Reads the recipients from this file; .csv or .jsonl")
+        .long_help(
+            "Reads the recipients from this file path. \
+            Two file formats are supported: \
+            * CSV with a header row - `*.csv` \
+            * JSON Lines, one object per line - `*.jsonl` or `*.ndjson` \
+            Each recipient has exactly one of the fields \
+            'email', 'url' or 'telephone' set; \
+            other fields are ignored.",
+        )
+        .num_args(1)
+        .value_parser(value_parser!(std::path::PathBuf))
+        .value_name("FILE")
+        .value_hint(ValueHint::FilePath)
+        .short(A_S_RECIPIENTS)
+        .long(A_L_RECIPIENTS)
+        .action(ArgAction::Set)
+        .required(true)
+}
+
+fn arg_out_dir() -> Arg {
+    Arg::new(A_L_OUT_DIR)
+        .help("Writes the baked images and the manifest into this directory")
+        .long_help(formatcp!(
+            "Writes the baked images into this directory, \
+            together with a manifest ({}) \
+            that maps the recipients to the created files. \
+            For hosted assertions, \
+            the assertions to be hosted are written there too.",
+            obadgen::batch::MANIFEST_FILE_NAME,
+        ))
+        .num_args(1)
+        .value_parser(value_parser!(std::path::PathBuf))
+        .value_name("DIR")
+        .value_hint(ValueHint::DirPath)
+        .short(A_S_OUT_DIR)
+        .long(A_L_OUT_DIR)
+        .action(ArgAction::Set)
+        .required(true)
+}
+
+fn arg_id_base() -> Arg {
+    Arg::new(A_L_ID_BASE)
+        .help("The base IRI under which the assertions are hosted")
+        .long_help(formatcp!(
+            "The base IRI under which the assertions are hosted; \
+            each assertion gets the id '<IRI>/<UUID>.json'. \
+            This is required for hosted assertions. \
+            Without it, signed assertions get a random 'urn:uuid:<UUID>' id. \
+            See also --{A_L_OUT_DIR}.",
+        ))
+        .num_args(1)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .value_name("IRI")
+        .value_hint(ValueHint::Url)
+        .long(A_L_ID_BASE)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
+fn subcommand_batch() -> Command {
+    Command::new(SC_BATCH)
+        .about("Bakes one badge per recipient of a recipient list")
+        .long_about(
+            "Bakes one badge per recipient of a recipient list. \
+            For each recipient, the template assertion gets a fresh id \
+            and a hashed and salted recipient identity; \
+            it is then signed (if a key is given), \
+            and baked into a copy of the source image.",
+        )
+        .arg(arg_assertion().required(true))
+        .arg(arg_badge())
+        .arg(arg_recipients())
+        .arg(arg_id_base())
+        .arg(arg_signing_algorithm())
+        .arg(arg_infer_signing_algorithm())
+        .arg(arg_key_file())
+        .arg(arg_signing_command())
+        .arg(arg_signing_public_key())
+        .arg(arg_signing_key_id())
+        .arg(arg_jws_type())
+        .arg(arg_jws_content_type())
+        .arg(arg_jws_embed_jwk())
+        .arg(arg_cert())
+        .arg(arg_bake_url())
+        .arg(arg_source_image().required(true))
+        .arg(arg_format())
+        .arg(arg_sidecars())
+        .arg(arg_png_compress())
+        .arg(arg_payload_warn_size())
+        .arg(arg_out_dir())
+}
+
+fn parse_date_time(value: &str) -> Result<DateTime<FixedOffset>, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(value)
+}
+
+fn arg_id() -> Arg {
+    Arg::new(A_L_ID)
+        .help("The IRI of the assertion")
+        .long_help(formatcp!(
+            "The IRI of the assertion. \
+            For hosted assertions, this is required, \
+            and has to be the URL the assertion is hosted under. \
+            For signed assertions, it defaults to a random 'urn:uuid:<UUID>'. \
+            See also --{A_L_VERIFICATION}.",
+        ))
+        .num_args(1)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .value_name("IRI")
+        .value_hint(ValueHint::Url)
+        .long(A_L_ID)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
+fn arg_recipient() -> Arg {
+    Arg::new(A_L_RECIPIENT)
+        .help("The identity of the recipient, in plain text")
+        .long_help(formatcp!(
+            "The identity of the recipient, in plain text; \
+            for example an email address. \
+            Unless --{A_L_PLAIN_IDENTITY} is given, \
+            only its hash is stored in the assertion. \
+            See also --{A_L_IDENTITY_TYPE}.",
+        ))
+        .num_args(1)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .value_name("IDENTITY")
+        .long(A_L_RECIPIENT)
+        .action(ArgAction::Set)
+        .required(true)
+}
+
+fn arg_identity_type() -> Arg {
+    Arg::new(A_L_IDENTITY_TYPE)
+        .help("The type of the recipient identity")
+        .num_args(1)
+        .value_parser(value_parser!(IdentityType))
+        .value_name("TYPE")
+        .long(A_L_IDENTITY_TYPE)
+        .action(ArgAction::Set)
+        .default_value("email")
+        .required(false)
+}
+
+fn arg_plain_identity() -> Arg {
+    Arg::new(A_L_PLAIN_IDENTITY)
+        .help("Store the recipient identity in plain text, instead of hashed")
+        .action(ArgAction::SetTrue)
+        .long(A_L_PLAIN_IDENTITY)
+        .conflicts_with(A_L_SALT)
+        .required(false)
+}
+
+fn arg_salt() -> Arg {
+    Arg::new(A_L_SALT)
+        .help("Salt the hash of the recipient identity")
+        .long_help(
+            "Salt the hash of the recipient identity with this value. \
+            Without a value, a random salt is used. \
+            If not given at all, the identity is hashed without salt.",
+        )
+        .num_args(0..=1)
+        .require_equals(true)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .value_name("SALT")
+        .long(A_L_SALT)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
+fn arg_issued_on() -> Arg {
+    Arg::new(A_L_ISSUED_ON)
+        .help("When the badge was awarded (RFC 3339); defaults to now")
+        .num_args(1)
+        .value_parser(parse_date_time)
+        .value_name("DATE-TIME")
+        .long(A_L_ISSUED_ON)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
+fn arg_expires() -> Arg {
+    Arg::new(A_L_EXPIRES)
+        .help("When the badge expires (RFC 3339)")
+        .num_args(1)
+        .value_parser(parse_date_time)
+        .value_name("DATE-TIME")
+        .long(A_L_EXPIRES)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
+fn arg_evidence() -> Arg {
+    Arg::new(A_L_EVIDENCE)
+        .help("IRI of evidence for the achievement; may be given multiple times")
+        .num_args(1)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .value_name("IRI")
+        .value_hint(ValueHint::Url)
+        .long(A_L_EVIDENCE)
+        .action(ArgAction::Append)
+        .required(false)
+}
+
+fn arg_narrative() -> Arg {
+    Arg::new(A_L_NARRATIVE)
+        .help("A narrative that connects the evidence")
+        .num_args(1)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .value_name("TEXT")
+        .long(A_L_NARRATIVE)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
+fn arg_verification() -> Arg {
+    Arg::new(A_L_VERIFICATION)
+        .help("Whether the assertion is hosted or signed")
+        .num_args(1)
+        .value_parser(value_parser!(VerificationKind))
+        .value_name("KIND")
+        .long(A_L_VERIFICATION)
+        .action(ArgAction::Set)
+        .default_value("hosted")
+        .required(false)
+}
+
+fn arg_creator() -> Arg {
+    Arg::new(A_L_CREATOR)
+        .help("The IRI of the CryptographicKey used for signing")
+        .long_help(formatcp!(
+            "The IRI of the CryptographicKey used for signing; \
+            only valid with --{A_L_VERIFICATION} signed. \
+            If not given, verifiers check the keys of the issuer.",
+        ))
+        .num_args(1)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .value_name("IRI")
+        .value_hint(ValueHint::Url)
+        .long(A_L_CREATOR)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
+fn arg_output() -> Arg {
+    Arg::new(A_L_OUTPUT)
+        .help("Write the assertion into this file, instead of stdout")
+        .num_args(1)
+        .value_parser(value_parser!(std::path::PathBuf))
+        .value_name("JSON-LD-FILE")
+        .value_hint(ValueHint::FilePath)
+        .short(A_S_OUTPUT)
+        .long(A_L_OUTPUT)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
+fn subcommand_new_assertion() -> Command {
+    Command::new(SC_NEW_ASSERTION)
+        .about("Creates a new Open Badge 2.0 JSON-LD Assertion")
+        .long_about(formatcp!(
+            "Creates a new Open Badge 2.0 JSON-LD Assertion \
+            from the given values, \
+            to be used with -{A_S_ASSERTION}, --{A_L_ASSERTION}.",
+        ))
+        .arg(arg_id())
+        .arg(arg_badge())
+        .arg(arg_recipient())
+        .arg(arg_identity_type())
+        .arg(arg_plain_identity())
+        .arg(arg_salt())
+        .arg(arg_issued_on())
+        .arg(arg_expires())
+        .arg(arg_evidence())
+        .arg(arg_narrative())
+        .arg(arg_verification())
+        .arg(arg_creator())
+        .arg(arg_output())
+}
+
+fn subcommand_check_recipient() -> Command {
+    Command::new(SC_CHECK_RECIPIENT)
+        .about("Checks whether an Open Badge was awarded to a given recipient")
+        .long_about(formatcp!(
+            "Checks whether an Open Badge was awarded to a given recipient. \
+            The badge is verified first (see the '{SC_VERIFY}' subcommand), \
+            then the given plain-text identity is normalized \
+            (lower-case email, canonical URL, E.164 telephone number), \
+            and compared to the (possibly hashed and salted) \
+            recipient identity of the assertion. \
+            Exits with an error if the recipient does not match.",
+        ))
+        .arg(arg_input())
+        .arg(
+            arg_recipient()
+                .help("The identity to check for, in plain text")
+                .long_help(
+                    "The identity to check for, in plain text; \
+                    for example an email address. \
+                    It is interpreted as the identity type used in the assertion.",
+                ),
+        )
+        .arg(arg_resolve_dir())
+        .arg(arg_resolve_base())
+}
+
+fn arg_owner() -> Arg {
+    Arg::new(A_L_OWNER)
+        .help("The IRI of the Issuer owning the key")
+        .long_help(formatcp!(
+            "The IRI of the Issuer owning the key. \
+            Defaults to the 'id' of --{A_L_ISSUER}, \
+            which is required if this is not given.",
+        ))
+        .num_args(1)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .value_name("IRI")
+        .value_hint(ValueHint::Url)
+        .long(A_L_OWNER)
+        .action(ArgAction::Set)
+        .required_unless_present(A_L_ISSUER)
+}
+
+fn arg_issuer() -> Arg {
+    Arg::new(A_L_ISSUER)
+        .help("Add the key to the publicKey of this Issuer JSON-LD file")
+        .long_help(
+            "Add the IRI of the key to the 'publicKey' \
+            of this Issuer JSON-LD file, in place; \
+            keys already listed there are kept, \
+            so badges signed with them remain verifiable. \
+            All other content of the file is left as is.",
+        )
+        .num_args(1)
+        .value_parser(value_parser!(std::path::PathBuf))
+        .value_name("JSON-LD-FILE")
+        .value_hint(ValueHint::FilePath)
+        .long(A_L_ISSUER)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
+fn subcommand_publish_key() -> Command {
+    Command::new(SC_PUBLISH_KEY)
+        .about("Creates the CryptographicKey JSON-LD of an issuer")
+        .long_about(formatcp!(
+            "Creates the Open Badge 2.0 JSON-LD CryptographicKey \
+            for verifying badges signed with the given key, \
+            to be hosted under its IRI. \
+            The public key is derived from a private key or a certificate. \
+            Optionally, the key gets linked from the Issuer file \
+            (see --{A_L_ISSUER}).",
+        ))
+        .arg(
+            arg_key_file()
+                .help("Read the private key or certificate from this file")
+                .long_help(
+                    "Read the private key or certificate (chain) from this file. \
+                    The formats supported for signing are supported for private keys; \
+                    certificates may be PEM or DER, \
+                    in which case the key of the leaf certificate is used.",
+                )
+                .value_name("KEY-OR-CERT-FILE")
+                .required(true),
+        )
+        .arg(
+            arg_id()
+                .help("The IRI of the CryptographicKey")
+                .long_help(
+                    "The IRI of the CryptographicKey, \
+                    which has to be the URL it is hosted under.",
+                )
+                .required(true),
+        )
+        .arg(arg_owner())
+        .arg(arg_issuer())
+        .arg(arg_output().help("Write the CryptographicKey into this file, instead of stdout"))
+}
+
+fn arg_rsa_bits() -> Arg {
+    Arg::new(A_L_RSA_BITS)
+        .help("The size of RSA keys, in bits")
+        .num_args(1)
+        .value_parser(
+            clap::builder::RangedU64ValueParser::<usize>::new()
+                .range(MIN_RSA_BITS as u64..=MAX_RSA_BITS as u64),
+        )
+        .value_name("BITS")
+        .long(A_L_RSA_BITS)
+        .action(ArgAction::Set)
+        .default_value("3072")
+        .required(false)
+}
+
+fn arg_cert_kind() -> Arg {
+    Arg::new(A_L_CERT_KIND)
+        .help("What to create for the public key, besides the key itsself")
+        .long_help(
+            "What to create for the public key, besides the key itsself: \
+            nothing, a self-signed x.509 certificate, \
+            or a certificate signing request (CSR), to be signed by a CA. \
+            Certificates are not supported for ES256K.",
+        )
+        .num_args(1)
+        .value_parser(value_parser!(CertKind))
+        .value_name("KIND")
+        .long(A_L_CERT_KIND)
+        .action(ArgAction::Set)
+        .default_value("none")
+        .required(false)
+}
+
+fn arg_subject() -> Arg {
+    Arg::new(A_L_SUBJECT)
+        .help("The subject distinguished name of the certificate (request)")
+        .long_help(formatcp!(
+            "The subject distinguished name of the certificate (request), \
+            like 'CN=Example,O=Example Org,C=DE'. \
+            Supported attributes are CN, O, OU, C, ST and L. \
+            Defaults to the first --{A_L_SAN} as common name (CN).",
+        ))
+        .num_args(1)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .value_name("DN")
+        .long(A_L_SUBJECT)
+        .action(ArgAction::Set)
+        .required(false)
+}
+
+fn arg_san() -> Arg {
+    Arg::new(A_L_SAN)
+        .help("A subject alternative name (DNS name or IP address) of the certificate (request); may be given multiple times")
+        .num_args(1)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .value_name("NAME")
+        .long(A_L_SAN)
+        .action(ArgAction::Append)
+        .required(false)
+}
+
+fn arg_validity_days() -> Arg {
+    Arg::new(A_L_VALIDITY_DAYS)
+        .help("For how many days from now a self-signed certificate is valid")
+        .num_args(1)
+        .value_parser(value_parser!(u32).range(1..=i64::from(MAX_VALIDITY_DAYS)))
+        .value_name("DAYS")
+        .long(A_L_VALIDITY_DAYS)
+        .action(ArgAction::Set)
+        .default_value("365")
+        .required(false)
+}
+
+fn arg_file_base() -> Arg {
+    Arg::new(A_L_FILE_BASE)
+        .help("The common beginning of the paths of all the files written")
+        .long_help(
+            "The common beginning of the paths of all the files written; \
+            for example, 'keys/issuer' results in \
+            'keys/issuer.priv.pem', 'keys/issuer.pub.der', 'keys/issuer.cert.pem', ...",
+        )
+        .num_args(1)
+        .value_parser(value_parser!(std::path::PathBuf))
+        .value_name("FILE-BASE")
+        .value_hint(ValueHint::FilePath)
+        .action(ArgAction::Set)
+        .required(true)
+}
+
+fn arg_force() -> Arg {
+    Arg::new(A_L_FORCE)
+        .help("Overwrite already existing files")
+        .long_help(
+            "Overwrite already existing files; \
+            without this, nothing is written if any of the files exists. \
+            Be careful, as this may destroy a private key still in use!",
+        )
+        .num_args(0)
+        .long(A_L_FORCE)
+        .action(ArgAction::SetTrue)
+        .required(false)
+}
+
+fn subcommand_keygen() -> Command {
+    Command::new(SC_KEYGEN)
+        .about("Generates a private key, and optionally a certificate (request) for it")
+        .long_about(formatcp!(
+            "Generates a private key for signing badges \
+            with the given signing algorithm, \
+            and optionally a self-signed certificate \
+            or a certificate signing request for it \
+            (see --{A_L_CERT_KIND}). \
+            All of them are written in both PEM and DER format; \
+            the private key as PKCS#8, the public key as SubjectPublicKeyInfo. \
+            The paths of the written files are printed to stdout. \
+            On unix, the private key files are only readable by their owner.",
+        ))
+        .arg(
+            arg_signing_algorithm()
+                .help("Signing algorithm to generate a key for")
+                .long_help(formatcp!(
+                    "Which signing algorithm to generate a key for. \
+                    This determines the key type: \
+                    RSA (of --{A_L_RSA_BITS} size) for RS256, RS384 and RS512, \
+                    ECDSA on the respective curve for ES256, ES384 and ES256K, \
+                    and Ed25519 for EdDSA.",
+                ))
+                .required(true),
+        )
+        .arg(arg_rsa_bits())
+        .arg(arg_cert_kind())
+        .arg(arg_subject())
+        .arg(arg_san())
+        .arg(arg_validity_days())
+        .arg(arg_file_base())
+        .arg(arg_force())
+}
+
+fn subcommand_check_key() -> Command {
+    Command::new(SC_CHECK_KEY)
+        .about("Checks which key of the issuer a signed Open Badge was signed with")
+        .long_about(formatcp!(
+            "Checks which of the keys of the issuer a signed Open Badge was signed with. \
+            The badge is verified first (see the '{SC_VERIFY}' subcommand); \
+            the public key is selected by the 'verification.creator' \
+            of the assertion or the 'kid' of the JWS header, \
+            and if neither is given, all the keys of the issuer are tried. \
+            This allows to validate badges signed before a key rotation. \
+            The IRI of the key is printed to stdout.",
+        ))
+        .arg(arg_input())
+        .arg(
+            arg_id()
+                .help("The IRI of the key the badge is expected to be signed with")
+                .long_help(
+                    "The IRI of the CryptographicKey \
+                    the badge is expected to be signed with. \
+                    Exits with an error if it was signed with an other key.",
+                ),
+        )
+        .arg(arg_resolve_dir())
+        .arg(arg_resolve_base())
+}
+
 // fn arg_overwrite() -> Arg {
 //     Arg::new(A_L_OVERWRITE)
 //         .help("Whether to overwrite already set values in the output.")
@@ -274,16 +1309,31 @@ This does not affect the log level for the log-file.",
 //         .required(false)
 // }
 
-pub static ARGS: LazyLock<[Arg; 10]> = LazyLock::new(|| {
+pub static ARGS: LazyLock<[Arg; 25]> = LazyLock::new(|| {
     [
         arg_version(),
         // arg_project_root(),
         arg_raw_panic(),
         arg_assertion(),
         arg_signing_algorithm(),
+        arg_infer_signing_algorithm(),
         arg_key_file(),
+        arg_signing_command(),
+        arg_signing_public_key(),
+        arg_signing_key_id(),
+        arg_jws_type(),
+        arg_jws_content_type(),
+        arg_jws_embed_jwk(),
+        arg_cert(),
+        arg_bake_url(),
         arg_source_image(),
         arg_baked(),
+        arg_format(),
+        arg_sidecars(),
+        arg_png_compress(),
+        arg_payload_warn_size(),
+        arg_jws_output(),
+        arg_jws_serialization(),
         arg_verbose(),
         arg_log_level(),
         arg_quiet(),
@@ -316,7 +1366,15 @@ fn arg_matcher() -> Command {
         .bin_name(clap::crate_name!())
         .help_expected(true)
         .disable_version_flag(true)
-        .args(ARGS.iter());
+        .args(ARGS.iter())
+        .subcommand(subcommand_extract())
+        .subcommand(subcommand_verify())
+        .subcommand(subcommand_batch())
+        .subcommand(subcommand_new_assertion())
+        .subcommand(subcommand_check_recipient())
+        .subcommand(subcommand_publish_key())
+        .subcommand(subcommand_keygen())
+        .subcommand(subcommand_check_key());
     let duplicate_short_options = find_duplicate_short_options();
     assert!(
         duplicate_short_options.is_empty(),
@@ -405,6 +1463,329 @@ fn print_version_and_exit(quiet: bool) {
     std::process::exit(0);
 }
 
+fn extract(args: &ArgMatches, quiet: bool) -> BoxResult<()> {
+    #![allow(clippy::print_stdout)]
+
+    let baked_loc = args
+        .get_one::<PathBuf>(A_L_IMAGE)
+        .expect("This argument is required");
+    let format = args.get_one::<ImageType>(A_L_FORMAT).copied();
+    let payload = obadgen::process::extract(baked_loc, format)?.ok_or_else(|| {
+        format!(
+            "No Open Badge content found in image file '{}'",
+            baked_loc.display()
+        )
+    })?;
+    if !quiet {
+        let kind: &str = payload.kind.into();
+        println!("{kind}");
+    }
+    println!("{}", payload.content);
+    Ok(())
+}
+
+fn verify_input(args: &ArgMatches, input_loc: &Path) -> BoxResult<Verified> {
+    let fetcher: Box<dyn Fetcher> = if let (Some(dir), Some(base_iri)) = (
+        args.get_one::<PathBuf>(A_L_RESOLVE_DIR),
+        args.get_one::<String>(A_L_RESOLVE_BASE),
+    ) {
+        Box::new(DirFetcher::new(base_iri, dir))
+    } else {
+        Box::new(HttpFetcher)
+    };
+    let payload = obadgen::process::read_payload(input_loc)?;
+    Ok(obadgen::verify::verify(&payload, fetcher.as_ref())?)
+}
+
+fn check_recipient(args: &ArgMatches, quiet: bool) -> BoxResult<()> {
+    #![allow(clippy::print_stdout)]
+
+    let input_loc = args
+        .get_one::<PathBuf>(A_L_INPUT)
+        .expect("This argument is required");
+    let recipient = args
+        .get_one::<String>(A_L_RECIPIENT)
+        .expect("This argument is required");
+    let verified = verify_input(args, input_loc)?;
+    let identity = &verified.assertion.recipient;
+    if !identity.matches(recipient)? {
+        return Err(format!(
+            "The badge '{}' was not awarded to '{recipient}'",
+            verified.assertion.id
+        )
+        .into());
+    }
+    log::info!(
+        "The badge '{}' was awarded to '{recipient}'.",
+        verified.assertion.id
+    );
+    if !quiet {
+        println!("{}", verified.assertion.id);
+    }
+    Ok(())
+}
+
+fn check_key(args: &ArgMatches, quiet: bool) -> BoxResult<()> {
+    #![allow(clippy::print_stdout)]
+
+    let input_loc = args
+        .get_one::<PathBuf>(A_L_INPUT)
+        .expect("This argument is required");
+    let verified = verify_input(args, input_loc)?;
+    let key = verified.key.ok_or_else(|| {
+        format!(
+            "The badge '{}' is not signed, but hosted",
+            verified.assertion.id
+        )
+    })?;
+    if let Some(expected_key_id) = args.get_one::<String>(A_L_ID) {
+        if *expected_key_id != key.id {
+            return Err(format!(
+                "The badge '{}' was signed with the key '{}', not '{expected_key_id}'",
+                verified.assertion.id, key.id
+            )
+            .into());
+        }
+    }
+    log::info!(
+        "The badge '{}' was signed with the key '{}'.",
+        verified.assertion.id,
+        key.id
+    );
+    if !quiet {
+        println!("{}", key.id);
+    }
+    Ok(())
+}
+
+fn verify(args: &ArgMatches, quiet: bool) -> BoxResult<()> {
+    #![allow(clippy::print_stdout)]
+
+    let input_loc = args
+        .get_one::<PathBuf>(A_L_INPUT)
+        .expect("This argument is required");
+    let verified = verify_input(args, input_loc)?;
+    log::info!("The badge '{}' is valid.", verified.assertion.id);
+    if !quiet {
+        println!("{}", verified.assertion.to_json_ld()?);
+    }
+    Ok(())
+}
+
+fn batch(args: &ArgMatches, verbosity: Verbosity) -> BoxResult<()> {
+    let settings = settings(args, verbosity);
+    let batch_settings = BatchSettings {
+        badge_id: args
+            .get_one::<String>(A_L_BADGE)
+            .expect("This argument is required")
+            .clone(),
+        recipients_loc: args
+            .get_one::<PathBuf>(A_L_RECIPIENTS)
+            .expect("This argument is required")
+            .clone(),
+        out_dir: args
+            .get_one::<PathBuf>(A_L_OUT_DIR)
+            .expect("This argument is required")
+            .clone(),
+        id_base: args.get_one::<String>(A_L_ID_BASE).cloned(),
+    };
+    log::trace!("Created BatchSettings.");
+
+    obadgen::batch::run(&settings, &batch_settings)?;
+    Ok(())
+}
+
+fn new_assertion(args: &ArgMatches) -> BoxResult<()> {
+    #![allow(clippy::print_stdout)]
+
+    let hashing = if args.get_flag(A_L_PLAIN_IDENTITY) {
+        IdentityHashing::Plain
+    } else if args.contains_id(A_L_SALT) {
+        IdentityHashing::Salted(match args.get_one::<String>(A_L_SALT) {
+            Some(salt) => salt.clone(),
+            None => Identity::random_salt()?,
+        })
+    } else {
+        IdentityHashing::Unsalted
+    };
+    let settings = NewAssertionSettings {
+        id: args.get_one::<String>(A_L_ID).cloned(),
+        badge_id: args
+            .get_one::<String>(A_L_BADGE)
+            .expect("This argument is required")
+            .clone(),
+        recipient: args
+            .get_one::<String>(A_L_RECIPIENT)
+            .expect("This argument is required")
+            .clone(),
+        identity_type: args
+            .get_one::<IdentityType>(A_L_IDENTITY_TYPE)
+            .copied()
+            .unwrap_or_default(),
+        hashing,
+        issued_on: args
+            .get_one::<DateTime<FixedOffset>>(A_L_ISSUED_ON)
+            .copied(),
+        expires: args.get_one::<DateTime<FixedOffset>>(A_L_EXPIRES).copied(),
+        evidence: args
+            .get_many::<String>(A_L_EVIDENCE)
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        narrative: args.get_one::<String>(A_L_NARRATIVE).cloned(),
+        verification: args
+            .get_one::<VerificationKind>(A_L_VERIFICATION)
+            .copied()
+            .unwrap_or_default(),
+        creator: args.get_one::<String>(A_L_CREATOR).cloned(),
+    };
+    log::trace!("Created NewAssertionSettings.");
+
+    let json_ld = obadgen::assertion_gen::create(&settings)?.to_json_ld()?;
+    if let Some(output_loc) = args.get_one::<PathBuf>(A_L_OUTPUT) {
+        std::fs::write(output_loc, json_ld)?;
+    } else {
+        println!("{json_ld}");
+    }
+    Ok(())
+}
+
+fn publish_key(args: &ArgMatches) -> BoxResult<()> {
+    #![allow(clippy::print_stdout)]
+
+    let settings = PublishKeySettings {
+        key_loc: args
+            .get_one::<PathBuf>(A_L_SIGNING_PRIVATE_KEY)
+            .expect("This argument is required")
+            .clone(),
+        key_id: args
+            .get_one::<String>(A_L_ID)
+            .expect("This argument is required")
+            .clone(),
+        owner: args.get_one::<String>(A_L_OWNER).cloned(),
+        issuer_loc: args.get_one::<PathBuf>(A_L_ISSUER).cloned(),
+    };
+    log::trace!("Created PublishKeySettings.");
+
+    let json_ld = obadgen::key_publish::run(&settings)?.to_json_ld()?;
+    if let Some(output_loc) = args.get_one::<PathBuf>(A_L_OUTPUT) {
+        std::fs::write(output_loc, json_ld)?;
+    } else {
+        println!("{json_ld}");
+    }
+    Ok(())
+}
+
+fn keygen(args: &ArgMatches, quiet: bool) -> BoxResult<()> {
+    #![allow(clippy::print_stdout)]
+
+    let settings = KeygenSettings {
+        algorithm: args
+            .get_one::<Algorithm>(A_L_SIGNING_ALGORITHM)
+            .copied()
+            .expect("This argument is required"),
+        rsa_bits: args
+            .get_one::<usize>(A_L_RSA_BITS)
+            .copied()
+            .expect("This argument has a default value"),
+        cert: args
+            .get_one::<CertKind>(A_L_CERT_KIND)
+            .copied()
+            .unwrap_or_default(),
+        subject: args.get_one::<String>(A_L_SUBJECT).cloned(),
+        subject_alt_names: args
+            .get_many::<String>(A_L_SAN)
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        validity_days: args
+            .get_one::<u32>(A_L_VALIDITY_DAYS)
+            .copied()
+            .expect("This argument has a default value"),
+        file_base: args
+            .get_one::<PathBuf>(A_L_FILE_BASE)
+            .expect("This argument is required")
+            .clone(),
+    };
+    log::trace!("Created KeygenSettings.");
+
+    let files = obadgen::cert_gen::create(&settings)?.write_files(args.get_flag(A_L_FORCE))?;
+    if !quiet {
+        for file in files {
+            println!("{}", file.display());
+        }
+    }
+    Ok(())
+}
+
+/// Creates the settings shared by the main command
+/// and the batch subcommand;
+/// only `baked_loc`, `jws_loc` and `jws_serialization` are left unset.
+fn settings(args: &ArgMatches, verbosity: Verbosity) -> Settings {
+    let assertion_loc = args.get_one::<PathBuf>(A_L_ASSERTION).cloned();
+    let sign_alg = args
+        .get_one::<Algorithm>(A_L_SIGNING_ALGORITHM)
+        .copied()
+        .unwrap_or_default();
+    let infer_sign_alg = args.get_flag(A_L_INFER_SIGNING_ALGORITHM);
+    let sign_key_loc = args.get_one::<PathBuf>(A_L_SIGNING_PRIVATE_KEY).cloned();
+    let sign_command = args
+        .get_many::<String>(A_L_SIGNING_COMMAND)
+        .map(|command| command.cloned().collect());
+    let sign_public_key_loc = args.get_one::<PathBuf>(A_L_SIGNING_PUBLIC_KEY).cloned();
+    let sign_key_id = args.get_one::<String>(A_L_SIGNING_KEY_ID).cloned();
+    let cert_locs = args
+        .get_many::<PathBuf>(A_L_CERT)
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let jws_header = HeaderOptions {
+        r#type: args.get_one::<String>(A_L_JWS_TYPE).cloned(),
+        content_type: args.get_one::<String>(A_L_JWS_CONTENT_TYPE).cloned(),
+        embed_jwk: args.get_flag(A_L_JWS_EMBED_JWK),
+    };
+    let hosted_payload = if args.contains_id(A_L_BAKE_URL) {
+        args.get_one::<String>(A_L_BAKE_URL)
+            .cloned()
+            .map_or(HostedPayload::Id, HostedPayload::Url)
+    } else {
+        HostedPayload::Json
+    };
+    let source_image_loc = args.get_one::<PathBuf>(A_L_SOURCE_IMAGE).cloned();
+    let image_format = args.get_one::<ImageType>(A_L_FORMAT).copied();
+    let sidecars = args.get_flag(A_L_SIDECARS);
+    let png_compress = args.get_flag(A_L_PNG_COMPRESS);
+    let payload_warn_size = args
+        .get_one::<usize>(A_L_PAYLOAD_WARN_SIZE)
+        .copied()
+        .filter(|size| *size > 0);
+
+    Settings {
+        // repo_path: Some(repo_path),
+        // date_format: date_format.to_owned(),
+        // overwrite,
+        verbosity,
+        assertion_loc,
+        sign_alg,
+        infer_sign_alg,
+        sign_key_loc,
+        sign_command,
+        sign_public_key_loc,
+        sign_key_id,
+        cert_locs,
+        jws_header,
+        hosted_payload,
+        source_image_loc,
+        image_format,
+        baked_loc: None,
+        jws_loc: None,
+        jws_serialization: Serialization::default(),
+        sidecars,
+        png_compress,
+        payload_warn_size,
+    }
+}
+
 fn main() -> BoxResult<()> {
     let log_filter_reload_handle = logger::setup_logging()?;
 
@@ -424,6 +1805,18 @@ fn main() -> BoxResult<()> {
     let verbosity = verbosity(&args);
     logger::set_log_level(&log_filter_reload_handle, verbosity)?;
 
+    match args.subcommand() {
+        Some((SC_EXTRACT, sub_args)) => return extract(sub_args, quiet),
+        Some((SC_VERIFY, sub_args)) => return verify(sub_args, quiet),
+        Some((SC_BATCH, sub_args)) => return batch(sub_args, verbosity),
+        Some((SC_NEW_ASSERTION, sub_args)) => return new_assertion(sub_args),
+        Some((SC_CHECK_RECIPIENT, sub_args)) => return check_recipient(sub_args, quiet),
+        Some((SC_PUBLISH_KEY, sub_args)) => return publish_key(sub_args),
+        Some((SC_KEYGEN, sub_args)) => return keygen(sub_args, quiet),
+        Some((SC_CHECK_KEY, sub_args)) => return check_key(sub_args, quiet),
+        _ => (),
+    }
+
     // if args.get_flag(A_L_LIST) {
     //     let environment = Environment::stub();
     //     // let list = var::list_keys(&environment);
@@ -436,27 +1829,14 @@ fn main() -> BoxResult<()> {
 
     // let overwrite = overwrite(&args);
 
-    let assertion_loc = args.get_one::<PathBuf>(A_L_ASSERTION).cloned();
-    let sign_alg = args
-        .get_one::<Algorithm>(A_L_SIGNING_ALGORITHM)
-        .copied()
-        .unwrap_or_default();
-    let sign_key_loc = args.get_one::<PathBuf>(A_L_SIGNING_PRIVATE_KEY).cloned();
-    let cert_loc = None; // TODO ... maybe, if at all possible
-    let source_image_loc = args.get_one::<PathBuf>(A_L_SOURCE_IMAGE).cloned();
-    let baked_loc = args.get_one::<PathBuf>(A_L_BAKED_IMAGE).cloned();
-
     let settings = Settings {
-        // repo_path: Some(repo_path),
-        // date_format: date_format.to_owned(),
-        // overwrite,
-        verbosity,
-        assertion_loc,
-        sign_alg,
-        sign_key_loc,
-        cert_loc,
-        source_image_loc,
-        baked_loc,
+        baked_loc: args.get_one::<PathBuf>(A_L_BAKED_IMAGE).cloned(),
+        jws_loc: args.get_one::<PathBuf>(A_L_JWS_OUTPUT).cloned(),
+        jws_serialization: args
+            .get_one::<Serialization>(A_L_JWS_SERIALIZATION)
+            .copied()
+            .unwrap_or_default(),
+        ..settings(&args, verbosity)
     };
     log::trace!("Created Settings.");
 
@@ -2,9 +2,36 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use clap::ValueEnum;
+use ring::rand::{SecureRandom, SystemRandom};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use typed_builder::TypedBuilder;
 
+use crate::hash;
+
+/// The number of random bytes in a generated salt.
+const SALT_LEN: usize = 16;
+/// The maximum number of digits in an E.164 telephone number.
+const E164_MAX_DIGITS: usize = 15;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Not a valid {type:?} identity: '{value}'; {msg}")]
+    InvalidIdentity {
+        r#type: ObjType,
+        value: String,
+        msg: &'static str,
+    },
+
+    #[error("Unsupported hash algorithm in identity '{identity}'; supported are sha256 and md5")]
+    UnsupportedHash { identity: String },
+
+    #[error("Failed to generate a random salt")]
+    Randomness,
+}
+
 /// The property by which the recipient of a badge is identified.
 /// This value should be an IRI mapped in the present context.
 /// For example, `email` maps to <http://schema.org/email>
@@ -12,8 +39,9 @@ use typed_builder::TypedBuilder;
 /// will represent a value of a `Profile’s` `email` property.
 /// See [Profile Identifier Properties](
 /// http://www.imsglobal.org/sites/default/files/Badges/OBv2p0Final/index.html#ProfileIdentifierProperties).
-#[derive(Debug, Default, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy, Serialize, Deserialize, ValueEnum)]
 #[serde(rename_all = "lowercase")]
+#[value(rename_all = "lowercase")]
 // #[serde(tag = "type")]
 pub enum ObjType {
     #[default]
@@ -23,6 +51,98 @@ pub enum ObjType {
     // TODO DID?
 }
 
+impl ObjType {
+    fn invalid(self, value: &str, msg: &'static str) -> Error {
+        Error::InvalidIdentity {
+            r#type: self,
+            value: value.to_owned(),
+            msg,
+        }
+    }
+
+    fn normalize_email(self, value: &str) -> Result<String, Error> {
+        let (local, domain) = value
+            .split_once('@')
+            .ok_or_else(|| self.invalid(value, "missing '@'"))?;
+        if local.is_empty() || domain.is_empty() || domain.contains('@') {
+            return Err(self.invalid(value, "expected exactly one '@', surrounded by text"));
+        }
+        Ok(value.to_lowercase())
+    }
+
+    fn normalize_url(self, value: &str) -> Result<String, Error> {
+        let (scheme, rest) = value
+            .split_once("://")
+            .ok_or_else(|| self.invalid(value, "missing '://'"))?;
+        if !scheme.starts_with(|chr: char| chr.is_ascii_alphabetic())
+            || !scheme
+                .chars()
+                .all(|chr| chr.is_ascii_alphanumeric() || "+-.".contains(chr))
+        {
+            return Err(self.invalid(value, "invalid scheme"));
+        }
+        let path_start = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+        let (authority, path) = rest.split_at(path_start);
+        if authority.is_empty() {
+            return Err(self.invalid(value, "missing host"));
+        }
+        let path = if path.starts_with('/') {
+            path.to_owned()
+        } else {
+            format!("/{path}")
+        };
+        Ok(format!(
+            "{}://{}{path}",
+            scheme.to_lowercase(),
+            authority.to_lowercase()
+        ))
+    }
+
+    fn normalize_telephone(self, value: &str) -> Result<String, Error> {
+        let digits: String = value
+            .strip_prefix('+')
+            .ok_or_else(|| self.invalid(value, "E.164 numbers start with '+'"))?
+            // The national trunk prefix, as in "+41 (0)79 ...",
+            // is not part of the international number
+            .replace("(0)", "")
+            .chars()
+            .filter(|chr| !" -.()".contains(*chr))
+            .collect();
+        if !digits.chars().all(|chr| chr.is_ascii_digit())
+            || digits.is_empty()
+            || digits.len() > E164_MAX_DIGITS
+            || digits.starts_with('0')
+        {
+            return Err(self.invalid(
+                value,
+                "E.164 numbers consist of up to 15 digits, the first of which is not 0",
+            ));
+        }
+        Ok(format!("+{digits}"))
+    }
+
+    /// Brings a plain-text identity of this type into its canonical form,
+    /// which is what gets hashed:
+    /// lower-case email addresses,
+    /// URLs with lower-case scheme and host,
+    /// and telephone numbers in E.164 format.
+    ///
+    /// # Errors
+    ///
+    /// If `value` is not a valid identity of this type.
+    pub fn normalize(self, value: &str) -> Result<String, Error> {
+        let value = value.trim();
+        if value.is_empty() || (value.contains(char::is_whitespace) && self != Self::Telephone) {
+            return Err(self.invalid(value, "empty or containing whitespace"));
+        }
+        match self {
+            Self::EMail => self.normalize_email(value),
+            Self::Url => self.normalize_url(value),
+            Self::Telephone => self.normalize_telephone(value),
+        }
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize, TypedBuilder)]
 #[serde(rename_all = "lowercase")]
 // #[serde(rename = "IdentityObject")]
@@ -57,6 +177,176 @@ impl Obj {
             salt: None,
         }
     }
+
+    /// Generates a fresh, cryptographically random salt.
+    ///
+    /// # Errors
+    ///
+    /// If the system fails to provide randomness.
+    pub fn random_salt() -> Result<String, Error> {
+        let mut bytes = [0_u8; SALT_LEN];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_err| Error::Randomness)?;
+        Ok(BASE64_URL.encode(bytes))
+    }
+
+    /// Creates a plain-text (not hashed) identity.
+    ///
+    /// # Errors
+    ///
+    /// If `value` is not a valid identity of type `r#type`.
+    pub fn plain(r#type: ObjType, value: &str) -> Result<Self, Error> {
+        Ok(Self::new(r#type, r#type.normalize(value)?, false))
+    }
+
+    /// Creates an identity that is hashed with SHA-256,
+    /// optionally salted.
+    ///
+    /// # Errors
+    ///
+    /// If `value` is not a valid identity of type `r#type`.
+    pub fn hashed(r#type: ObjType, value: &str, salt: Option<String>) -> Result<Self, Error> {
+        let normalized = r#type.normalize(value)?;
+        Ok(Self {
+            r#type,
+            identity: hash::sha256_with_salt(normalized, salt.as_deref().unwrap_or_default()),
+            hashed: true,
+            salt,
+        })
+    }
+
+    /// Creates an identity that is hashed with SHA-256,
+    /// salted with a fresh, random salt.
+    ///
+    /// # Errors
+    ///
+    /// If `value` is not a valid identity of type `r#type`.
+    ///
+    /// If the system fails to provide randomness.
+    pub fn hashed_salted(r#type: ObjType, value: &str) -> Result<Self, Error> {
+        Self::hashed(r#type, value, Some(Self::random_salt()?))
+    }
+
+    /// Checks whether this identity is the given plain-text one.
+    ///
+    /// # Errors
+    ///
+    /// If `value` is not a valid identity of this type.
+    ///
+    /// If this identity is hashed with an unsupported algorithm.
+    pub fn matches(&self, value: &str) -> Result<bool, Error> {
+        let normalized = self.r#type.normalize(value)?;
+        if !self.hashed {
+            return Ok(self
+                .r#type
+                .normalize(&self.identity)
+                .is_ok_and(|own| own == normalized));
+        }
+        let expected = self.identity.to_lowercase();
+        let hash_fn: fn(&str, &str) -> String = if expected.starts_with("sha256$") {
+            |input, salt| hash::sha256_with_salt(input, salt)
+        } else if expected.starts_with("md5$") {
+            |input, salt| hash::md5_with_salt(input, salt)
+        } else {
+            return Err(Error::UnsupportedHash {
+                identity: self.identity.clone(),
+            });
+        };
+        let salt = self.salt.as_deref().unwrap_or_default();
+        // Issuers are supposed to hash the normalized identity,
+        // but some hash it as given.
+        let matches = [normalized.as_str(), value.trim()]
+            .into_iter()
+            .any(|candidate| hash_fn(candidate, salt) == expected);
+        Ok(matches)
+    }
 }
 
-// NOTE This is already tested in assertion.rs
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants;
+    use crate::util::test::is_good_error;
+
+    #[test]
+    fn normal_types() {
+        is_good_error::<Error>();
+    }
+
+    #[test]
+    fn normalize() -> Result<(), Error> {
+        assert_eq!(
+            ObjType::EMail.normalize(" Alice@Example.ORG ")?,
+            "alice@example.org"
+        );
+        assert!(ObjType::EMail.normalize("alice").is_err());
+        assert!(ObjType::EMail.normalize("a@b@c").is_err());
+        assert_eq!(
+            ObjType::Url.normalize("HTTPS://Example.org")?,
+            "https://example.org/"
+        );
+        assert_eq!(
+            ObjType::Url.normalize("https://Example.org/Alice?x=Y")?,
+            "https://example.org/Alice?x=Y"
+        );
+        assert!(ObjType::Url.normalize("example.org").is_err());
+        assert_eq!(
+            ObjType::Telephone.normalize("+41 (0)79-123.45.67")?,
+            "+41791234567"
+        );
+        assert_eq!(
+            ObjType::Telephone.normalize("+1 (555) 010-0199")?,
+            "+15550100199"
+        );
+        assert!(ObjType::Telephone.normalize("079 123 45 67").is_err());
+        assert!(ObjType::Telephone.normalize("+0123").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn hash_and_match() -> Result<(), Error> {
+        let identity = Obj::hashed_salted(ObjType::EMail, "Alice@Example.org")?;
+        assert!(identity.hashed);
+        assert!(identity.salt.is_some());
+        assert!(identity.matches("alice@example.org")?);
+        assert!(!identity.matches("bob@example.org")?);
+        assert_ne!(Obj::random_salt()?, Obj::random_salt()?);
+
+        let plain = Obj::plain(ObjType::Telephone, "+41 79 123 45 67")?;
+        assert!(!plain.hashed);
+        assert!(plain.matches("+41791234567")?);
+
+        // Different spellings of the same number hash the same
+        let salt = Some("pepper".to_owned());
+        let telephone = Obj::hashed(ObjType::Telephone, "+41 (0)79-123.45.67", salt.clone())?;
+        assert_eq!(
+            Obj::hashed(ObjType::Telephone, "+41 79 123 45 67", salt)?.identity,
+            telephone.identity
+        );
+        assert!(telephone.matches("+41 79 123 45 67")?);
+
+        let unsalted = Obj {
+            r#type: ObjType::EMail,
+            identity: constants::BADGE_ASSERTION_RECIPIENT_EMAIL_HASH_UNSALTED.clone(),
+            hashed: true,
+            salt: None,
+        };
+        assert!(unsalted.matches(constants::BADGE_ASSERTION_RECIPIENT_EMAIL)?);
+
+        let md5 = Obj {
+            r#type: ObjType::EMail,
+            identity: hash::md5_with_salt("alice@example.org", "pepper"),
+            hashed: true,
+            salt: Some("pepper".to_owned()),
+        };
+        assert!(md5.matches("ALICE@example.org")?);
+
+        let unsupported = Obj {
+            identity: "sha1$0000".to_owned(),
+            ..md5
+        };
+        assert!(unsupported.matches("alice@example.org").is_err());
+        Ok(())
+    }
+}
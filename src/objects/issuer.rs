@@ -86,11 +86,13 @@ pub struct Obj {
     #[builder(setter(into))]
     pub email: Option<String>,
     /// The key(s) an issuer uses to sign `Assertion`s.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Listing more than one allows to rotate keys,
+    /// while `Assertion`s signed with older keys stay verifiable.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, with = "super::one_or_many")]
     #[builder(default)]
-    #[builder(setter(strip_option))]
     #[builder(setter(into))]
-    pub public_key: Option<String>,
+    pub public_key: Vec<String>,
     /// Instructions for how to verify `Assertion`s published by this Profile.
     #[serde(skip_serializing_if = "Option::is_none")]
     #[builder(default)]
@@ -116,7 +118,7 @@ impl Obj {
             description: None,
             image: None,
             email: None,
-            public_key: None,
+            public_key: vec![],
             verification: None,
             revocation_list: None,
         }
@@ -162,12 +164,30 @@ mod tests {
         let mut obj = Obj::new("http://abc.de/org.json");
         obj.name = Some("John Doe".to_string());
         obj.url = Some("https://abc.de/".to_string());
-        obj.public_key = Some("http://abc.de/key.json".to_string());
+        obj.public_key = vec!["http://abc.de/key.json".to_string()];
         let json_ld = obj.to_json_ld()?;
         assert_eq!(&json_ld, EXP_JSON_LD_WITH_KEY);
         Ok(())
     }
 
+    #[test]
+    fn test_multiple_keys() -> Result<(), Box<dyn std::error::Error>> {
+        let mut obj: Obj = serde_json::from_str(EXP_JSON_LD_WITH_KEY)?;
+        assert_eq!(obj.public_key, ["http://abc.de/key.json"]);
+        obj.public_key.push("http://abc.de/key-2.json".to_string());
+        let json_ld = obj.to_json_ld()?;
+        assert_eq!(
+            &json_ld,
+            &EXP_JSON_LD_WITH_KEY.replace(
+                r#""http://abc.de/key.json""#,
+                "[\n    \"http://abc.de/key.json\",\n    \"http://abc.de/key-2.json\"\n  ]"
+            )
+        );
+        let reparsed: Obj = serde_json::from_str(&json_ld)?;
+        assert_eq!(reparsed, obj);
+        Ok(())
+    }
+
     #[test]
     fn test_builder_simple() -> Result<(), Box<dyn std::error::Error>> {
         let obj = Obj::builder()
@@ -186,7 +206,7 @@ mod tests {
             .id("http://abc.de/org.json")
             .name("John Doe")
             .url("https://abc.de/")
-            .public_key("http://abc.de/key.json")
+            .public_key(["http://abc.de/key.json".to_string()])
             .build();
         let json_ld = obj.to_json_ld()?;
         assert_eq!(&json_ld, EXP_JSON_LD_WITH_KEY);
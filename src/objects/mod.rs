@@ -13,6 +13,37 @@ pub mod verification;
 
 use serde::Serialize;
 
+/// (De-)Serializes a list of strings
+/// from/to either a single string or an array of strings,
+/// as JSON-LD allows for properties with one or more values.
+/// A single value is serialized as a plain string.
+pub mod one_or_many {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    pub fn serialize<S: Serializer>(values: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+        match values {
+            [single] => single.serialize(serializer),
+            _ => values.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<String>, D::Error> {
+        Ok(match OneOrMany::deserialize(deserializer)? {
+            OneOrMany::One(single) => vec![single],
+            OneOrMany::Many(values) => values,
+        })
+    }
+}
+
 pub trait ToJsonLd
 where
     Self: Serialize,
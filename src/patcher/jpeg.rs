@@ -0,0 +1,253 @@
+// SPDX-FileCopyrightText: 2026 Robin Vobruba <hoijui.quaero@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Baking of JPEG images,
+//! with the Open Badge meta-data in an XMP packet (see [`super::xmp`]),
+//! stored in an APP1 segment.
+//!
+//! All other segments and the image data are copied byte-for-byte.
+
+use std::path::Path;
+
+use super::{xmp, Error};
+
+pub struct Patcher;
+
+/// Start Of Image, the marker every JPEG file starts with.
+const SOI: [u8; 2] = [0xFF, 0xD8];
+/// The marker of the JFIF header segment.
+const APP0: u8 = 0xE0;
+/// The marker of the segment holding EXIF or XMP meta-data.
+const APP1: u8 = 0xE1;
+/// Start Of Scan, followed by the (entropy coded) image data.
+const SOS: u8 = 0xDA;
+/// End Of Image
+const EOI: u8 = 0xD9;
+
+/// The start of the data of an APP1 segment holding an XMP packet.
+const XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// The maximum size of the data of a segment,
+/// as its size (including the 2 size bytes) is stored in 16 bits.
+const MAX_SEGMENT_DATA: usize = u16::MAX as usize - 2;
+
+fn malformed(msg: &str) -> Error {
+    Error::Boxed(format!("Malformed JPEG: {msg}").into())
+}
+
+/// A marker segment of a JPEG file, borrowed from the raw file content.
+struct Segment<'a> {
+    marker: u8,
+    /// The segment data, without marker and size.
+    data: &'a [u8],
+    /// The whole segment, exactly as it appears in the file.
+    raw: &'a [u8],
+}
+
+impl Segment<'_> {
+    fn is_xmp(&self) -> bool {
+        self.marker == APP1 && self.data.starts_with(XMP_SIGNATURE)
+    }
+
+    /// Whether this segment belongs to the header (JFIF or EXIF),
+    /// which should stay in front of the XMP segment.
+    fn is_header(&self) -> bool {
+        self.marker == APP0 || (self.marker == APP1 && !self.is_xmp())
+    }
+}
+
+/// Splits the content of a JPEG file into the marker segments before the image data,
+/// and the rest, starting with the Start Of Scan marker.
+fn split_segments(jpeg: &[u8]) -> Result<(Vec<Segment<'_>>, &[u8]), Error> {
+    let mut rest = jpeg
+        .strip_prefix(&SOI)
+        .ok_or_else(|| malformed("Start Of Image marker missing"))?;
+    let mut segments = Vec::new();
+    loop {
+        // Any number of 0xFF fill bytes may precede a marker
+        let fill = rest.iter().take_while(|byte| **byte == 0xFF).count();
+        if fill == 0 {
+            return Err(malformed("marker expected"));
+        }
+        let marker = *rest
+            .get(fill)
+            .ok_or_else(|| malformed("truncated marker"))?;
+        if marker == SOS || marker == EOI {
+            return Ok((segments, rest));
+        }
+        let (raw, data) = if matches!(marker, 0x01 | 0xD0..=0xD7) {
+            // Stand-alone markers, without data
+            (rest.get(..=fill), Some(&[][..]))
+        } else {
+            let size = rest
+                .get(fill + 1..fill + 3)
+                .and_then(|bytes| <[u8; 2]>::try_from(bytes).ok())
+                .map(u16::from_be_bytes)
+                .ok_or_else(|| malformed("truncated segment size"))?;
+            let end = fill + 1 + usize::from(size);
+            (rest.get(..end), rest.get(fill + 3..end))
+        };
+        let (Some(raw), Some(data)) = (raw, data) else {
+            return Err(malformed("truncated segment"));
+        };
+        rest = rest.get(raw.len()..).unwrap_or_default();
+        segments.push(Segment { marker, data, raw });
+    }
+}
+
+/// Bakes at the segment level (see [`super::rewrite_file`]):
+/// The XMP segment is replaced,
+/// or, if there is none, inserted after the JFIF and EXIF headers.
+fn bake(input: &[u8], verify: &str, fail_if_verify_present: bool) -> Result<Vec<u8>, Error> {
+    let (segments, image_data) = split_segments(input)?;
+    let xmp_segment = segments.iter().find(|segment| segment.is_xmp());
+    let present_packet = xmp_segment.and_then(|segment| segment.data.get(XMP_SIGNATURE.len()..));
+    let Some(packet) = xmp::bake(present_packet, verify, fail_if_verify_present)? else {
+        return Ok(input.to_vec());
+    };
+    let segment_size = XMP_SIGNATURE.len() + packet.len();
+    if segment_size > MAX_SEGMENT_DATA {
+        return Err(Error::Boxed(
+            format!(
+                "The XMP meta-data ({segment_size} bytes) is too big for a JPEG segment \
+                (max. {MAX_SEGMENT_DATA} bytes); consider baking a URL instead"
+            )
+            .into(),
+        ));
+    }
+    let segment_size = u16::try_from(segment_size + 2).map_err(|_err| malformed("too big"))?;
+
+    let mut xmp_raw = Vec::with_capacity(usize::from(segment_size) + 2);
+    xmp_raw.extend_from_slice(&[0xFF, APP1]);
+    xmp_raw.extend_from_slice(&segment_size.to_be_bytes());
+    xmp_raw.extend_from_slice(XMP_SIGNATURE);
+    xmp_raw.extend_from_slice(&packet);
+
+    let insert_pos = if xmp_segment.is_some() {
+        segments.iter().position(Segment::is_xmp)
+    } else {
+        segments.iter().position(|segment| !segment.is_header())
+    }
+    .unwrap_or(segments.len());
+    let mut output = Vec::with_capacity(input.len() + xmp_raw.len());
+    output.extend_from_slice(&SOI);
+    for (idx, segment) in segments.iter().enumerate() {
+        if idx == insert_pos {
+            output.extend_from_slice(&xmp_raw);
+        }
+        if !segment.is_xmp() {
+            output.extend_from_slice(segment.raw);
+        }
+    }
+    if insert_pos == segments.len() {
+        output.extend_from_slice(&xmp_raw);
+    }
+    output.extend_from_slice(image_data);
+    Ok(output)
+}
+
+impl super::Patcher for Patcher {
+    fn rewrite<P: AsRef<Path>, S: AsRef<str>>(
+        input_file: P,
+        output_file: P,
+        verify: S,
+        fail_if_verify_present: bool,
+    ) -> Result<(), Error> {
+        super::rewrite_file(
+            input_file,
+            output_file,
+            verify,
+            fail_if_verify_present,
+            bake,
+        )
+    }
+}
+
+impl super::Extractor for Patcher {
+    fn extract<P: AsRef<Path>>(input_file: P) -> Result<Option<String>, Error> {
+        super::extract_file(input_file, |input| {
+            let (segments, _image_data) = split_segments(input)?;
+            segments
+                .iter()
+                .find(|segment| segment.is_xmp())
+                .and_then(|segment| segment.data.get(XMP_SIGNATURE.len()..))
+                .map_or(Ok(None), xmp::extract)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patcher::{Extractor, Patcher as _};
+    use std::fs;
+
+    const SOURCE_IMAGE: &str = "res/media/img/test.jpg";
+
+    #[test]
+    fn extract_unbaked() -> Result<(), Error> {
+        assert_eq!(Patcher::extract(SOURCE_IMAGE)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn bake_and_extract() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempfile::tempdir()?;
+        let baked = tmp_dir.path().join("baked.jpg");
+        let verify = "https://example.org/assertion.json";
+        Patcher::rewrite(Path::new(SOURCE_IMAGE), &baked, verify, true)?;
+        assert_eq!(Patcher::extract(&baked)?.as_deref(), Some(verify));
+        Ok(())
+    }
+
+    #[test]
+    fn bake_lossless() -> Result<(), Box<dyn std::error::Error>> {
+        let input = fs::read(SOURCE_IMAGE)?;
+        let verify = "https://example.org/assertion.json";
+        let baked = bake(&input, verify, true)?;
+
+        let (in_segments, in_image_data) = split_segments(&input)?;
+        let (segments, image_data) = split_segments(&baked)?;
+        assert_eq!(image_data, in_image_data);
+        // The JFIF header stays first, followed by the XMP
+        assert_eq!(segments.first().map(|segment| segment.marker), Some(APP0));
+        assert!(segments.get(1).is_some_and(Segment::is_xmp));
+        let raw = |segments: &[Segment<'_>]| -> Vec<Vec<u8>> {
+            segments
+                .iter()
+                .filter(|segment| !segment.is_xmp())
+                .map(|segment| segment.raw.to_vec())
+                .collect()
+        };
+        assert_eq!(raw(&segments), raw(&in_segments));
+
+        // Baking the same again changes nothing, different content replaces it
+        assert_eq!(bake(&baked, verify, true)?, baked);
+        assert!(matches!(
+            bake(&baked, "https://example.org/other.json", true),
+            Err(Error::VerifyAlreadySet { .. })
+        ));
+        let rebaked = bake(&baked, "https://example.org/other.json", false)?;
+        let (segments, _image_data) = split_segments(&rebaked)?;
+        assert_eq!(
+            segments.iter().filter(|segment| segment.is_xmp()).count(),
+            1
+        );
+        assert_eq!(raw(&segments), raw(&in_segments));
+        Ok(())
+    }
+
+    #[test]
+    fn too_big() -> Result<(), Box<dyn std::error::Error>> {
+        let input = fs::read(SOURCE_IMAGE)?;
+        assert!(bake(&input, &"x".repeat(MAX_SEGMENT_DATA), true).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_input() {
+        assert!(bake(b"not a JPEG", "x", true).is_err());
+        assert!(bake(&[0xFF, 0xD8, 0xFF, APP0, 0x00, 0x10, 0x4A], "x", true).is_err());
+    }
+}
@@ -2,12 +2,10 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
-use png::text_metadata::ITXtChunk;
+use png::chunk::{self, ChunkType};
+use png::text_metadata::{EncodableTextChunk, ITXtChunk};
 
-use std::fs::File;
-use std::io::BufReader;
-
-use std::io::BufWriter;
+use std::io::Read;
 use std::path::Path;
 
 use super::Error;
@@ -25,25 +23,228 @@ pub struct Patcher;
 //     text: signature || JSON.stringify(assertion)
 //   })
 
-fn conv_read_err(err: png::DecodingError) -> Error {
-    Error::Boxed(Box::new(err))
-}
+/// The 8 bytes every PNG file starts with.
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The keyword of the iTXt chunk holding the Open Badge meta-data.
+const KEYWORD: &str = "openbadges";
+
+/// Length, type and CRC of a chunk, which surround its data.
+const CHUNK_OVERHEAD: usize = 12;
+
+/// The maximum size of the decompressed text of an iTXt chunk,
+/// as also used by the `png` crate when decoding;
+/// protects against decompression bombs.
+const DECOMPRESSION_LIMIT: usize = 2 * 1024 * 1024;
 
 fn conv_write_err(err: png::EncodingError) -> Error {
     Error::Boxed(Box::new(err))
 }
 
-fn create_reencoder<'a, W: std::io::Write>(
-    w: W,
-    info: &'a png::Info,
-) -> Result<png::Encoder<'a, W>, Error> {
-    log::trace!("Creating encoder ...");
-    let mut enc_info = info.clone();
-    enc_info.interlaced = false;
-    let mut encoder = png::Encoder::with_info(w, enc_info)?;
-    // encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
-    // encoder.set_adaptive_filter(png::AdaptiveFilterType::NonAdaptive);
-    Ok(encoder)
+fn malformed(msg: &str) -> Error {
+    Error::Boxed(format!("Malformed PNG: {msg}").into())
+}
+
+/// A chunk of a PNG file, borrowed from the raw file content.
+struct RawChunk<'a> {
+    r#type: ChunkType,
+    /// The chunk data, without length, type and CRC.
+    data: &'a [u8],
+    /// The whole chunk, exactly as it appears in the file.
+    raw: &'a [u8],
+}
+
+impl RawChunk<'_> {
+    /// Whether this is an iTXt chunk with our keyword.
+    fn is_open_badge(&self) -> bool {
+        self.r#type == chunk::iTXt
+            && self
+                .data
+                .split(|byte| *byte == 0)
+                .next()
+                .is_some_and(|keyword| keyword == KEYWORD.as_bytes())
+    }
+
+    /// Whether this chunk starts the (default) image.
+    ///
+    /// In an animated PNG (APNG),
+    /// the frame control (fcTL) chunk of the first frame
+    /// may precede the image data (IDAT),
+    /// and should stay directly in front of it.
+    fn starts_image(&self) -> bool {
+        self.r#type == chunk::IDAT || self.r#type == chunk::fcTL
+    }
+}
+
+/// Splits the content of a PNG file into its chunks,
+/// without decoding or validating any of them.
+fn split_chunks(png: &[u8]) -> Result<Vec<RawChunk<'_>>, Error> {
+    let mut rest = png
+        .strip_prefix(SIGNATURE)
+        .ok_or_else(|| malformed("PNG signature missing"))?;
+    let mut chunks = Vec::new();
+    while !rest.is_empty() {
+        let (length, _) = rest
+            .split_first_chunk::<4>()
+            .ok_or_else(|| malformed("truncated chunk length"))?;
+        let length = usize::try_from(u32::from_be_bytes(*length))
+            .map_err(|_err| malformed("chunk too big"))?;
+        let (raw, after) = rest
+            .split_at_checked(length.saturating_add(CHUNK_OVERHEAD))
+            .ok_or_else(|| malformed("truncated chunk"))?;
+        let r#type = raw
+            .get(4..8)
+            .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok())
+            .map(ChunkType)
+            .ok_or_else(|| malformed("truncated chunk type"))?;
+        let data = raw
+            .get(8..8 + length)
+            .ok_or_else(|| malformed("truncated chunk data"))?;
+        chunks.push(RawChunk { r#type, data, raw });
+        rest = after;
+    }
+    if !chunks.iter().any(|chunk| chunk.r#type == chunk::IDAT) {
+        return Err(malformed("no image data (IDAT) found"));
+    }
+    if chunks.last().map(|chunk| chunk.r#type) != Some(chunk::IEND) {
+        return Err(malformed("does not end with an IEND chunk"));
+    }
+    Ok(chunks)
+}
+
+/// Returns whether an iTXt chunk is compressed, and its text,
+/// decompressed if necessary.
+fn parse_itxt(data: &[u8]) -> Result<(bool, String), Error> {
+    let invalid = || malformed("invalid iTXt chunk");
+    let (_keyword, rest) = split_at_null(data).ok_or_else(invalid)?;
+    let ([compressed, _method], rest) = rest.split_first_chunk::<2>().ok_or_else(invalid)?;
+    let (_language_tag, rest) = split_at_null(rest).ok_or_else(invalid)?;
+    let (_translated_keyword, text) = split_at_null(rest).ok_or_else(invalid)?;
+    let compressed = *compressed != 0;
+    let text = if compressed {
+        let mut decompressed = Vec::new();
+        flate2::read::ZlibDecoder::new(text)
+            .take(DECOMPRESSION_LIMIT as u64 + 1)
+            .read_to_end(&mut decompressed)?;
+        if decompressed.len() > DECOMPRESSION_LIMIT {
+            return Err(malformed(&format!(
+                "iTXt text decompresses to more than {DECOMPRESSION_LIMIT} bytes"
+            )));
+        }
+        decompressed
+    } else {
+        text.to_vec()
+    };
+    let text = String::from_utf8(text).map_err(|_err| malformed("iTXt text is not valid UTF-8"))?;
+    Ok((compressed, text))
+}
+
+/// Splits off the part before the first null byte.
+fn split_at_null(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let pos = bytes.iter().position(|byte| *byte == 0)?;
+    Some((bytes.get(..pos)?, bytes.get(pos + 1..)?))
+}
+
+/// Bakes at the chunk level (see [`super::rewrite_file`]):
+/// Existing Open Badge chunks are dropped in favor of a new one,
+/// inserted right before the first image data (IDAT) chunk,
+/// or the first frame control (fcTL) chunk preceding it.
+/// This keeps animated PNGs (APNG) intact,
+/// including all their frames, delays, dispose and blend operations,
+/// and the number of loops.
+///
+/// If `compress` is set, the text of the new chunk is zlib compressed;
+/// the input only counts as already baked if this matches too.
+fn bake(
+    input: &[u8],
+    verify: &str,
+    fail_if_verify_present: bool,
+    compress: bool,
+) -> Result<Vec<u8>, Error> {
+    let chunks = split_chunks(input)?;
+    if let Some(actl) = chunks.iter().find(|chunk| chunk.r#type == chunk::acTL) {
+        let num = |range| {
+            actl.data
+                .get(range)
+                .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok())
+                .map_or(0, u32::from_be_bytes)
+        };
+        log::debug!(
+            "Animated PNG with {} frames, played {} times (0: infinitely).",
+            num(0..4),
+            num(4..8)
+        );
+    }
+
+    let mut already_as_proposed = false;
+    for chunk in chunks.iter().filter(|chunk| chunk.is_open_badge()) {
+        let (compressed, present) = parse_itxt(chunk.data)?;
+        if present == verify {
+            already_as_proposed = compressed == compress;
+        } else if fail_if_verify_present {
+            return Err(Error::VerifyAlreadySet {
+                present,
+                proposed: verify.to_owned(),
+            });
+        } else {
+            already_as_proposed = false;
+            break;
+        }
+    }
+    if already_as_proposed {
+        log::debug!("The image is already baked as proposed.");
+        return Ok(input.to_vec());
+    }
+
+    let mut ob_chunk = ITXtChunk::new(KEYWORD, verify);
+    ob_chunk.compressed = compress;
+    let mut output = Vec::with_capacity(input.len() + verify.len() + CHUNK_OVERHEAD + 16);
+    output.extend_from_slice(SIGNATURE);
+    let mut ob_chunk_written = false;
+    for chunk in &chunks {
+        if chunk.is_open_badge() {
+            log::trace!("Dropping the old OpenBadge iTXt text chunk ...");
+            continue;
+        }
+        if chunk.starts_image() && !ob_chunk_written {
+            log::trace!("Writing OpenBadge iTXt text chunk ...");
+            ob_chunk.encode(&mut output).map_err(conv_write_err)?;
+            ob_chunk_written = true;
+        }
+        output.extend_from_slice(chunk.raw);
+    }
+    Ok(output)
+}
+
+impl Patcher {
+    /// Like [`super::Patcher::rewrite`],
+    /// optionally zlib compressing the Open Badge meta-data
+    /// (a compressed iTXt chunk).
+    ///
+    /// # Errors
+    ///
+    /// Reading the source file failed.
+    ///
+    /// The source file is not a well-formed PNG.
+    ///
+    /// Writing the target file failed.
+    pub fn rewrite_with_compression<P: AsRef<Path>, S: AsRef<str>>(
+        input_file: P,
+        output_file: P,
+        verify: S,
+        fail_if_verify_present: bool,
+        compress: bool,
+    ) -> Result<(), Error> {
+        super::rewrite_file(
+            input_file,
+            output_file,
+            verify,
+            fail_if_verify_present,
+            |input, verify, fail_if_verify_present| {
+                bake(input, verify, fail_if_verify_present, compress)
+            },
+        )
+    }
 }
 
 impl super::Patcher for Patcher {
@@ -53,97 +254,273 @@ impl super::Patcher for Patcher {
         verify: S,
         fail_if_verify_present: bool,
     ) -> Result<(), Error> {
-        log::trace!("Opening input file '{}' ...", input_file.as_ref().display());
-        let input = File::open(input_file)?;
-        let input_buf = BufReader::new(input);
+        Self::rewrite_with_compression(
+            input_file,
+            output_file,
+            verify,
+            fail_if_verify_present,
+            false,
+        )
+    }
+}
 
-        log::trace!(
-            "Opening output file '{}' ...",
-            output_file.as_ref().display()
-        );
-        let output = File::create(output_file)?;
-        let w = BufWriter::new(output);
-
-        log::trace!("Decoding ...");
-        let decoder = png::Decoder::new(input_buf);
-        log::trace!("Creating reader ...");
-        let mut reader = decoder.read_info().map_err(conv_read_err)?;
-        // Allocate the output buffer.
-        let mut buf = vec![
-            0;
-            reader
-                .output_buffer_size()
-                .expect("output buffer does not fit into memory")
-        ];
-
-        // let mut decoder = png::StreamingDecoder::new(input_buf);
-        // let mut decoder_stream = decoder.as_;
-
-        log::trace!("Reading info ...");
-        // let mut header = decoder.read_header_info();
-        let info = reader.info().clone();
-
-        log::trace!("Creating encoder ...");
-        let mut encoder = create_reencoder(w, &info)?;
-
-        log::trace!("Re-Encoding tEXt text-chunks from input ...");
-        for chunk in &info.uncompressed_latin1_text {
-            encoder
-                .add_text_chunk(chunk.keyword.clone(), chunk.text.clone())
-                .map_err(conv_write_err)?;
-        }
-        log::trace!("Re-Encoding zTXt text-chunks from input ...");
-        for chunk in &info.compressed_latin1_text {
-            encoder
-                .add_ztxt_chunk(
-                    chunk.keyword.clone(),
-                    chunk.get_text().map_err(conv_read_err)?,
-                )
-                .map_err(conv_write_err)?;
-        }
-        log::trace!("Re-Encoding iTXt text-chunks from input ...");
-        let mut verify_already_as_proposed = false;
-        for chunk in &info.utf8_text {
-            let text = chunk.get_text().map_err(conv_read_err)?;
-            if chunk.keyword == "openbadges" {
-                if text == verify.as_ref() {
-                    verify_already_as_proposed = true;
-                } else if fail_if_verify_present {
-                    return Err(Error::VerifyAlreadySet {
-                        present: text,
-                        proposed: verify.as_ref().to_owned(),
-                    });
-                } else {
-                    // Skip writing this chunk,
-                    // to write it further down with the new value.
-                    continue;
-                }
-            }
-            encoder
-                .add_itxt_chunk(chunk.keyword.clone(), text)
-                .map_err(conv_write_err)?;
+impl super::Extractor for Patcher {
+    fn extract<P: AsRef<Path>>(input_file: P) -> Result<Option<String>, Error> {
+        super::extract_file(input_file, |input| {
+            // Text chunks may also come after the image data
+            split_chunks(input)?
+                .iter()
+                .find(|chunk| chunk.is_open_badge())
+                .map(|chunk| parse_itxt(chunk.data).map(|(_compressed, text)| text))
+                .transpose()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patcher::{Extractor, Patcher as _};
+    use std::fs;
+
+    const SOURCE_IMAGE: &str = "res/media/img/test.png";
+
+    #[test]
+    fn extract_unbaked() -> Result<(), Error> {
+        assert_eq!(Patcher::extract(SOURCE_IMAGE)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn bake_and_extract() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempfile::tempdir()?;
+        let baked = tmp_dir.path().join("baked.png");
+        let verify = "https://example.org/assertion.json";
+        Patcher::rewrite(Path::new(SOURCE_IMAGE), &baked, verify, true)?;
+        assert_eq!(Patcher::extract(&baked)?.as_deref(), Some(verify));
+        Ok(())
+    }
+
+    const PRIVATE_CHUNK: ChunkType = ChunkType(*b"prIv");
+
+    /// Creates a small PNG with some ancillary chunks
+    /// a decoding/re-encoding baker would likely lose.
+    fn ancillary_png() -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, 2, 2);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_pixel_dims(Some(png::PixelDimensions {
+            xppu: 11_811,
+            yppu: 11_811,
+            unit: png::Unit::Meter,
+        }));
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+        let mut writer = encoder.write_header()?;
+        writer.write_chunk(chunk::eXIf, b"MM\0\x2a\0\0\0\x08\0\0")?;
+        writer.write_chunk(PRIVATE_CHUNK, b"unknown to any decoder")?;
+        writer.write_image_data(&[0x7f; 2 * 2 * 3])?;
+        writer.finish()?;
+        Ok(png)
+    }
+
+    fn raw_chunks(png: &[u8]) -> Result<Vec<(ChunkType, Vec<u8>)>, Error> {
+        Ok(split_chunks(png)?
+            .iter()
+            .map(|chunk| (chunk.r#type, chunk.raw.to_vec()))
+            .collect())
+    }
+
+    #[test]
+    fn bake_lossless() -> Result<(), Box<dyn std::error::Error>> {
+        let input = ancillary_png()?;
+        let verify = "https://example.org/assertion.json";
+        let baked = bake(&input, verify, true, false)?;
+
+        let mut chunks = raw_chunks(&baked)?;
+        let ob_pos = split_chunks(&baked)?
+            .iter()
+            .position(RawChunk::is_open_badge)
+            .ok_or("OpenBadge chunk missing")?;
+        let (ob_type, _ob_raw) = chunks.remove(ob_pos);
+        assert_eq!(ob_type, chunk::iTXt);
+        assert_eq!(chunks.get(ob_pos).map(|chunk| chunk.0), Some(chunk::IDAT));
+        // Everything else is left untouched
+        assert_eq!(chunks, raw_chunks(&input)?);
+        for ancillary in [chunk::pHYs, chunk::sRGB, chunk::eXIf, PRIVATE_CHUNK] {
+            assert!(chunks.iter().any(|chunk| chunk.0 == ancillary));
         }
 
-        log::trace!("Creating writer ...");
-        let mut writer = encoder.write_header().map_err(conv_write_err)?;
+        // Baking the same again changes nothing
+        assert_eq!(bake(&baked, verify, true, false)?, baked);
+        Ok(())
+    }
+
+    #[test]
+    fn rebake() -> Result<(), Box<dyn std::error::Error>> {
+        let input = ancillary_png()?;
+        let baked = bake(&input, "https://example.org/old.json", true, false)?;
+        assert!(matches!(
+            bake(&baked, "https://example.org/new.json", true, false),
+            Err(Error::VerifyAlreadySet { .. })
+        ));
+        let rebaked = bake(&baked, "https://example.org/new.json", false, false)?;
+        let ob_chunks: Vec<String> = split_chunks(&rebaked)?
+            .iter()
+            .filter(|chunk| chunk.is_open_badge())
+            .map(|chunk| parse_itxt(chunk.data).map(|(_compressed, text)| text))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(ob_chunks, ["https://example.org/new.json"]);
+        assert_eq!(rebaked.len(), baked.len());
+        Ok(())
+    }
+
+    /// The animation parameters and frames of an animated PNG.
+    type Animation = (
+        (u32, u32),
+        Vec<(u16, u16, png::DisposeOp, png::BlendOp, Vec<u8>)>,
+    );
 
-        if !verify_already_as_proposed {
-            log::trace!("Creating OpenBadge iTXt text chunk ...");
-            let ob_chunk = ITXtChunk::new("openbadges", verify.as_ref());
-            log::trace!("Writing OpenBadge iTXt text chunk ...");
-            writer.write_text_chunk(&ob_chunk).map_err(conv_write_err)?;
+    /// Creates a small animated PNG with 3 frames,
+    /// looping twice.
+    fn animated_png() -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, 2, 2);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_animated(3, 2)?;
+        let mut writer = encoder.write_header()?;
+        for (idx, delay, dispose_op, blend_op) in [
+            (0_u8, 10, png::DisposeOp::None, png::BlendOp::Source),
+            (1, 25, png::DisposeOp::Background, png::BlendOp::Over),
+            (2, 50, png::DisposeOp::Previous, png::BlendOp::Source),
+        ] {
+            writer.set_frame_delay(delay, 100)?;
+            writer.set_dispose_op(dispose_op)?;
+            writer.set_blend_op(blend_op)?;
+            writer.write_image_data(&[idx * 100; 4])?;
         }
+        writer.finish()?;
+        Ok(png)
+    }
 
-        // Read the next frame. An APNG might contain multiple frames.
-        log::trace!("Writing input-image data ...");
-        while let Ok(info) = reader.next_frame(&mut buf) {
-            log::trace!("  Writing a frame ...");
-            let bytes = buf
-                .get(..info.buffer_size())
-                .expect("Only possible if the PNG Reader is buggy");
-            writer.write_image_data(bytes).map_err(conv_write_err)?;
+    fn decode_animation(png: &[u8]) -> Result<Animation, Box<dyn std::error::Error>> {
+        let mut reader = png::Decoder::new(std::io::Cursor::new(png)).read_info()?;
+        let actl = reader
+            .info()
+            .animation_control
+            .ok_or("Not an animated PNG")?;
+        let mut buf = vec![0; reader.output_buffer_size().ok_or("Image too big")?];
+        let mut frames = Vec::new();
+        for _ in 0..actl.num_frames {
+            let output_info = reader.next_frame(&mut buf)?;
+            let fctl = reader.info().frame_control.ok_or("Frame control missing")?;
+            frames.push((
+                fctl.delay_num,
+                fctl.delay_den,
+                fctl.dispose_op,
+                fctl.blend_op,
+                buf.get(..output_info.buffer_size())
+                    .ok_or("Frame too big")?
+                    .to_vec(),
+            ));
         }
+        Ok(((actl.num_frames, actl.num_plays), frames))
+    }
+
+    #[test]
+    fn bake_animated() -> Result<(), Box<dyn std::error::Error>> {
+        let input = animated_png()?;
+        let tmp_dir = tempfile::tempdir()?;
+        let source = tmp_dir.path().join("animated.apng");
+        let baked_loc = tmp_dir.path().join("baked.apng");
+        fs::write(&source, &input)?;
+        let verify = "https://example.org/assertion.json";
+        Patcher::rewrite(&source, &baked_loc, verify, true)?;
+        let baked = fs::read(&baked_loc)?;
+
+        let types: Vec<ChunkType> = raw_chunks(&baked)?.iter().map(|chunk| chunk.0).collect();
+        let ob_pos = types
+            .iter()
+            .position(|r#type| *r#type == chunk::iTXt)
+            .ok_or("OpenBadge chunk missing")?;
+        assert!(types
+            .get(..ob_pos)
+            .is_some_and(|before| before.contains(&chunk::acTL)));
+        assert_eq!(types.get(ob_pos + 1), Some(&chunk::fcTL));
+        assert_eq!(types.get(ob_pos + 2), Some(&chunk::IDAT));
+
+        let animation = decode_animation(&baked)?;
+        assert_eq!(animation.0, (3, 2));
+        assert_eq!(animation.1.len(), 3);
+        assert_eq!(animation, decode_animation(&input)?);
+        assert_eq!(Patcher::extract(&baked_loc)?.as_deref(), Some(verify));
+        Ok(())
+    }
+
+    #[test]
+    fn bake_compressed() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempfile::tempdir()?;
+        let baked_loc = tmp_dir.path().join("baked.png");
+        let verify = format!("{{\"narrative\": \"{}\"}}", "Very long. ".repeat(1000));
+        Patcher::rewrite_with_compression(
+            Path::new(SOURCE_IMAGE),
+            &baked_loc,
+            &verify,
+            true,
+            true,
+        )?;
+        let baked = fs::read(&baked_loc)?;
+        let chunks = split_chunks(&baked)?;
+        let ob_chunk = chunks
+            .iter()
+            .find(|chunk| chunk.is_open_badge())
+            .ok_or("OpenBadge chunk missing")?;
+        assert_eq!(parse_itxt(ob_chunk.data)?, (true, verify.clone()));
+        assert!(ob_chunk.data.len() < verify.len() / 10);
+        assert_eq!(Patcher::extract(&baked_loc)?.as_ref(), Some(&verify));
 
+        // Other PNG readers understand it too
+        let mut reader = png::Decoder::new(std::io::Cursor::new(&baked)).read_info()?;
+        reader.finish()?;
+        let texts = &reader.info().utf8_text;
+        assert_eq!(texts.len(), 1);
+        assert_eq!(
+            texts.first().map(ITXtChunk::get_text).transpose()?,
+            Some(verify.clone())
+        );
+
+        // Re-baking the same content uncompressed changes the chunk
+        let uncompressed = bake(&baked, &verify, true, false)?;
+        assert!(uncompressed.len() > baked.len());
+        assert_eq!(bake(&uncompressed, &verify, true, true)?, baked);
         Ok(())
     }
+
+    #[test]
+    fn decompression_bomb() -> Result<(), Box<dyn std::error::Error>> {
+        let input = ancillary_png()?;
+        let fits = "a".repeat(DECOMPRESSION_LIMIT);
+        let baked = bake(&input, &fits, true, true)?;
+        assert_eq!(bake(&baked, &fits, true, true)?, baked);
+
+        let bomb = "a".repeat(DECOMPRESSION_LIMIT + 1);
+        let baked = bake(&input, &bomb, true, true)?;
+        // Compresses well, but is refused when decompressing
+        assert!(baked.len() < input.len() + DECOMPRESSION_LIMIT / 100);
+        assert!(bake(&baked, &bomb, true, true).is_err());
+        let ob_chunk_data = split_chunks(&baked)?
+            .iter()
+            .find(|chunk| chunk.is_open_badge())
+            .map(|chunk| chunk.data)
+            .ok_or("OpenBadge chunk missing")?;
+        assert!(parse_itxt(ob_chunk_data).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_input() {
+        assert!(bake(b"not a PNG", "x", true, false).is_err());
+        let mut truncated = ancillary_png().unwrap_or_default();
+        truncated.truncate(truncated.len() - 3);
+        assert!(bake(&truncated, "x", true, false).is_err());
+    }
 }
@@ -3,8 +3,12 @@
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
 use super::Error;
+use super::PayloadKind;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Read, Write};
 use std::path::Path;
 use xml::reader::EventReader;
 use xml::writer::EmitterConfig;
@@ -20,12 +24,27 @@ use xml::writer::EmitterConfig;
 //            verify="https://thejeshgn.github.io/openbadge/thejeshgn-reader-badge.json">
 //        </openbadges:assertion>
 //
+// or, for an embedded JSON assertion:
+//
+//        <openbadges:assertion>
+//            <![CDATA[
+//            {
+//                "@context": "https://w3id.org/openbadges/v2",
+//                ...
+//            }
+//            ]]>
+//        </openbadges:assertion>
+//
 // <!-- rest of SVG content -->
 //
 // </svg>
 
 pub struct Patcher;
 
+/// Bakes and extracts gzip compressed SVGs (SVGZ);
+/// otherwise the same as [`Patcher`].
+pub struct CompressedPatcher;
+
 fn conv_read_err(err: xml::reader::Error) -> Error {
     Error::Boxed(Box::new(err))
 }
@@ -34,26 +53,64 @@ fn conv_write_err(err: xml::writer::Error) -> Error {
     Error::Boxed(Box::new(err))
 }
 
-fn add_assertion<W: std::io::Write, S: AsRef<str>>(
+/// Writes a new `<openbadges:assertion>` element.
+///
+/// As required by the baking specification,
+/// an embedded JSON assertion goes into the element body (as CDATA),
+/// while anything else (a URL or a JWS) goes into the `verify` attribute.
+fn add_assertion<W: std::io::Write>(
     writer: &mut xml::writer::EventWriter<W>,
-    verify: S,
+    verify: &str,
 ) -> Result<(), Error> {
-    log::info!(
-        "openbadges:assertion - not yet present (as first element after '<svg ...>') -> adding it!"
-    );
-    // Not yet present (as first element after "<svg ...>")
-    // -> create and add it!
-    let ob_start = xml::writer::XmlEvent::start_element("openbadges:assertion")
-        .attr("verify", verify.as_ref());
-    let ob_end = xml::writer::XmlEvent::end_element();
-    let ob_assert_elems: [xml::writer::XmlEvent; 2] = [ob_start.into(), ob_end.into()];
-    for elem in ob_assert_elems {
-        writer.write(elem).map_err(conv_write_err)?;
+    let ob_start = xml::writer::XmlEvent::start_element("openbadges:assertion");
+    if PayloadKind::detect(verify) == PayloadKind::Assertion {
+        writer.write(ob_start).map_err(conv_write_err)?;
+        let body = if verify.contains("]]>") {
+            // This would end the CDATA section prematurely
+            xml::writer::XmlEvent::characters(verify)
+        } else {
+            xml::writer::XmlEvent::cdata(verify)
+        };
+        writer.write(body).map_err(conv_write_err)?;
+    } else {
+        writer
+            .write(ob_start.attr("verify", verify))
+            .map_err(conv_write_err)?;
     }
+    writer
+        .write(xml::writer::XmlEvent::end_element())
+        .map_err(conv_write_err)?;
 
     Ok(())
 }
 
+/// Replaces an already present `<openbadges:assertion>` element,
+/// which contained `present`, either in its `verify` attribute
+/// or in its body.
+fn replace_assertion<W: std::io::Write>(
+    writer: &mut xml::writer::EventWriter<W>,
+    present: String,
+    verify: &str,
+    fail_if_verify_present: bool,
+) -> Result<(), Error> {
+    if present.is_empty() {
+        log::info!("openbadges:assertion - present but empty -> filling it!");
+    } else if present == verify.trim() {
+        log::info!("openbadges:assertion - verify is already set to the desired value!");
+    } else if fail_if_verify_present {
+        return Err(Error::VerifyAlreadySet {
+            present,
+            proposed: verify.to_owned(),
+        });
+    } else {
+        log::info!(
+            "openbadges:assertion - verify is already set to an other value -> overwriting!"
+        );
+    }
+    // We always (re-)write it, to ensure it uses the right representation
+    add_assertion(writer, verify)
+}
+
 fn add_namespace<W: std::io::Write>(
     writer: &mut xml::writer::EventWriter<W>,
     evt_in: &xml::reader::XmlEvent,
@@ -91,6 +148,84 @@ fn add_namespace<W: std::io::Write>(
     Ok(())
 }
 
+/// Bakes the SVG read from `input`, writing it to `output`.
+fn rewrite<R: Read, W: Write>(
+    input: R,
+    output: W,
+    verify: &str,
+    fail_if_verify_present: bool,
+) -> Result<(), Error> {
+    let mut writer = EmitterConfig::new()
+        // .perform_indent(true)
+        .create_writer(output);
+
+    let parser = EventReader::new(input);
+    let mut passed_init_elem = false;
+    // The `verify` attribute and the body
+    // of an already present `<openbadges:assertion>` element,
+    // while we are within it
+    let mut present: Option<(Option<String>, String)> = None;
+    for evt_in_res in parser {
+        let evt_in = evt_in_res.map_err(conv_read_err)?;
+
+        if let Some((verify_attr_val, body)) = present.as_mut() {
+            if let xml::reader::XmlEvent::CData(text)
+            | xml::reader::XmlEvent::Characters(text)
+            | xml::reader::XmlEvent::Whitespace(text) = &evt_in
+            {
+                body.push_str(text);
+            } else if matches!(&evt_in, xml::reader::XmlEvent::EndElement { name } if is_ob_assertion(name))
+            {
+                let present_val = verify_attr_val
+                    .take()
+                    .unwrap_or_else(|| body.trim().to_owned());
+                present = None;
+                replace_assertion(&mut writer, present_val, verify, fail_if_verify_present)?;
+            } else {
+                log::warn!("openbadges:assertion - ignoring unexpected content: {evt_in:?}");
+            }
+            continue;
+        }
+
+        if passed_init_elem {
+            // evt_in now holds the first (non-whitespace) element
+            // after the initial one ("<svg ...>").
+            // According to the Open Badge spec.,
+            // this would have to be "<openbadges:assertion ...>".
+            // If it is, we collect its content,
+            // and replace it once we reach its end.
+            // If it is *not*, we want to insert it.
+            if let xml::reader::XmlEvent::StartElement {
+                name, attributes, ..
+            } = &evt_in
+            {
+                if is_ob_assertion(name) {
+                    let verify_attr_val = attributes
+                        .iter()
+                        .find(|attr| attr.name.local_name == "verify")
+                        .map(|attr| attr.value.clone());
+                    present = Some((verify_attr_val, String::new()));
+                    passed_init_elem = false;
+                    continue;
+                }
+            }
+            if !matches!(evt_in, xml::reader::XmlEvent::Whitespace(_)) {
+                log::info!("openbadges:assertion - not yet present (as first element after '<svg ...>') -> adding it!");
+                add_assertion(&mut writer, verify)?;
+                passed_init_elem = false;
+            }
+        }
+        add_namespace(
+            &mut writer,
+            &evt_in,
+            evt_in.as_writer_event(),
+            &mut passed_init_elem,
+        )?;
+    }
+
+    Ok(())
+}
+
 impl super::Patcher for Patcher {
     fn rewrite<P: AsRef<Path>, S: AsRef<str>>(
         input_file: P,
@@ -98,84 +233,149 @@ impl super::Patcher for Patcher {
         verify: S,
         fail_if_verify_present: bool,
     ) -> Result<(), Error> {
-        let input = File::open(input_file)?;
-        let input_buf = BufReader::new(input);
-
+        let input = BufReader::new(File::open(input_file)?);
         let output = File::create(output_file)?;
-        let mut writer = EmitterConfig::new()
-            // .perform_indent(true)
-            .create_writer(output);
-
-        let parser = EventReader::new(input_buf);
-        let mut passed_init_elem = false;
-        for evt_in_res in parser {
-            let evt_in = evt_in_res.map_err(conv_read_err)?;
-            let mut evt_out_opt = Box::new(evt_in.as_writer_event());
-
-            if passed_init_elem {
-                // evt_in now holds the first element after the initial one ("<svg ...>").
-                // According to the Open Badge spec.,
-                // this would have to be "<openbadges:assertion ...>".
-                // If it is, we either want to throw an error for it already existing,
-                // or we want to modify it,
-                // or we just let it be, if it already contains the content we are supposed to set.
-                // If it is *not*, we want to insert it.
-                let mut verify_attr_val = None;
-                let mut is_ob_assert = false;
-                if let xml::reader::XmlEvent::StartElement {
-                    name,
-                    attributes,
-                    namespace: _,
-                } = &evt_in
+        rewrite(input, output, verify.as_ref(), fail_if_verify_present)
+    }
+}
+
+impl super::Patcher for CompressedPatcher {
+    fn rewrite<P: AsRef<Path>, S: AsRef<str>>(
+        input_file: P,
+        output_file: P,
+        verify: S,
+        fail_if_verify_present: bool,
+    ) -> Result<(), Error> {
+        let input = BufReader::new(GzDecoder::new(File::open(input_file)?));
+        let mut output = GzEncoder::new(File::create(output_file)?, Compression::default());
+        rewrite(input, &mut output, verify.as_ref(), fail_if_verify_present)?;
+        output.finish()?;
+        Ok(())
+    }
+}
+
+fn is_ob_assertion(name: &xml::name::OwnedName) -> bool {
+    Some("http://openbadges.org") == name.namespace.as_deref() && name.local_name == "assertion"
+}
+
+/// Extracts the baked content from the SVG read from `input`.
+fn extract<R: Read>(input: R) -> Result<Option<String>, Error> {
+    let parser = EventReader::new(input);
+    // The body of the `<openbadges:assertion>` element,
+    // while we are within it
+    let mut body: Option<String> = None;
+    for evt_in_res in parser {
+        let evt_in = evt_in_res.map_err(conv_read_err)?;
+        match (&evt_in, body.as_mut()) {
+            (
+                xml::reader::XmlEvent::StartElement {
+                    name, attributes, ..
+                },
+                None,
+            ) if is_ob_assertion(name) => {
+                if let Some(attr) = attributes
+                    .iter()
+                    .find(|attr| attr.name.local_name == "verify")
                 {
-                    if Some("http://openbadges.org") == name.namespace.as_deref()
-                        && name.local_name == "assertion"
-                    {
-                        for attr in attributes {
-                            if attr.name.local_name == "verify" {
-                                verify_attr_val = Some(attr.value.clone());
-                            }
-                        }
-                        is_ob_assert = true;
-                    }
-                };
-                let mut add_new_ob_assert = false;
-                if is_ob_assert {
-                    if let Some(val) = verify_attr_val {
-                        if val == verify.as_ref() {
-                            log::info!("openbadges:assertion - verify is already set to the desired value!");
-                        } else if fail_if_verify_present {
-                            return Err(Error::VerifyAlreadySet {
-                                present: val,
-                                proposed: verify.as_ref().to_owned(),
-                            });
-                        } else {
-                            // overwrite it
-                            log::info!("openbadges:assertion - verify is already set to an other value -> overwriting!");
-                            evt_out_opt = Box::new(Some(
-                                xml::writer::XmlEvent::start_element("openbadges:assertion")
-                                    .attr("verify", verify.as_ref())
-                                    .into(),
-                            ));
-                        }
-                    } else {
-                        log::info!("openbadges:assertion - not yet present (as first element after '<svg ...>') (1/2) -> adding it!");
-                        // Not yet present (as first element after "<svg ...>")
-                        // -> create and add it!
-                        add_new_ob_assert = true;
-                    }
-                } else {
-                    log::info!("openbadges:assertion - not yet present (as first element after '<svg ...>') (2/2) -> adding it!");
-                    add_new_ob_assert = true;
-                }
-                if add_new_ob_assert {
-                    add_assertion(&mut writer, &verify)?;
+                    log::debug!("openbadges:assertion - found 'verify' attribute");
+                    return Ok(Some(attr.value.clone()));
                 }
-                passed_init_elem = false;
+                body = Some(String::new());
+            }
+            (
+                xml::reader::XmlEvent::CData(text) | xml::reader::XmlEvent::Characters(text),
+                Some(body),
+            ) => {
+                body.push_str(text);
+            }
+            (xml::reader::XmlEvent::EndElement { name }, Some(body)) if is_ob_assertion(name) => {
+                log::debug!("openbadges:assertion - found element body");
+                let content = body.trim();
+                return Ok((!content.is_empty()).then(|| content.to_owned()));
             }
-            add_namespace(&mut writer, &evt_in, *evt_out_opt, &mut passed_init_elem)?;
+            _ => (),
         }
+    }
+
+    Ok(None)
+}
+
+impl super::Extractor for Patcher {
+    fn extract<P: AsRef<Path>>(input_file: P) -> Result<Option<String>, Error> {
+        extract(BufReader::new(File::open(input_file)?))
+    }
+}
+
+impl super::Extractor for CompressedPatcher {
+    fn extract<P: AsRef<Path>>(input_file: P) -> Result<Option<String>, Error> {
+        extract(BufReader::new(GzDecoder::new(File::open(input_file)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patcher::{Extractor, Patcher as _};
+
+    const SOURCE_IMAGE: &str = "res/media/img/test.svg";
+
+    #[test]
+    fn extract_unbaked() -> Result<(), Error> {
+        assert_eq!(Patcher::extract(SOURCE_IMAGE)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn bake_and_extract() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempfile::tempdir()?;
+        let baked = tmp_dir.path().join("baked.svg");
+        let verify = "https://example.org/assertion.json";
+        Patcher::rewrite(Path::new(SOURCE_IMAGE), &baked, verify, true)?;
+        assert_eq!(Patcher::extract(&baked)?.as_deref(), Some(verify));
+        Ok(())
+    }
+
+    #[test]
+    fn bake_assertion_as_cdata() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempfile::tempdir()?;
+        let baked = tmp_dir.path().join("baked.svg");
+        let rebaked = tmp_dir.path().join("rebaked.svg");
+        let assertion = "{\n  \"type\": \"Assertion\"\n}";
+        Patcher::rewrite(Path::new(SOURCE_IMAGE), &baked, assertion, true)?;
+        let baked_content = std::fs::read_to_string(&baked)?;
+        assert!(baked_content.contains("<![CDATA["));
+        assert!(!baked_content.contains("verify="));
+        assert_eq!(Patcher::extract(&baked)?.as_deref(), Some(assertion));
+
+        // Re-baking the same content is fine, different content is not
+        Patcher::rewrite(&baked, &rebaked, assertion, true)?;
+        assert_eq!(Patcher::extract(&rebaked)?.as_deref(), Some(assertion));
+        assert!(matches!(
+            Patcher::rewrite(&baked, &rebaked, "https://example.org/a.json", true),
+            Err(Error::VerifyAlreadySet { .. })
+        ));
+        Patcher::rewrite(&baked, &rebaked, "https://example.org/a.json", false)?;
+        assert_eq!(
+            Patcher::extract(&rebaked)?.as_deref(),
+            Some("https://example.org/a.json")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn bake_compressed() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempfile::tempdir()?;
+        let source = tmp_dir.path().join("source.svgz");
+        let baked = tmp_dir.path().join("baked.svgz");
+        let mut encoder = GzEncoder::new(File::create(&source)?, Compression::default());
+        encoder.write_all(&std::fs::read(SOURCE_IMAGE)?)?;
+        encoder.finish()?;
 
+        let verify = "https://example.org/assertion.json";
+        assert_eq!(CompressedPatcher::extract(&source)?, None);
+        CompressedPatcher::rewrite(&source, &baked, verify, true)?;
+        assert_eq!(CompressedPatcher::extract(&baked)?.as_deref(), Some(verify));
+        assert!(Patcher::extract(&baked).is_err());
         Ok(())
     }
 }
@@ -0,0 +1,333 @@
+// SPDX-FileCopyrightText: 2026 Robin Vobruba <hoijui.quaero@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Baking of WebP images.
+//!
+//! The Open Badge meta-data goes into an XMP packet (see [`super::xmp`]),
+//! stored in the `XMP ` chunk of the
+//! [extended file format](https://developers.google.com/speed/webp/docs/riff_container#extended_file_format).
+//!
+//! Simple (lossy or lossless) WebP files are converted to the extended format,
+//! which only adds a header chunk.
+//! All other chunks are copied byte-for-byte.
+
+use std::path::Path;
+
+use super::{xmp, Error};
+
+pub struct Patcher;
+
+const RIFF: &[u8; 4] = b"RIFF";
+const WEBP: &[u8; 4] = b"WEBP";
+/// The extended format header chunk
+const VP8X: &[u8; 4] = b"VP8X";
+/// Lossy image data
+const VP8: &[u8; 4] = b"VP8 ";
+/// Lossless image data
+const VP8L: &[u8; 4] = b"VP8L";
+const XMP: &[u8; 4] = b"XMP ";
+/// Chunks the XMP chunk has to come after;
+/// only unknown chunks may follow it.
+const PRECEDING_XMP: [&[u8; 4]; 8] = [VP8X, b"ICCP", b"ANIM", b"ANMF", b"ALPH", VP8, VP8L, b"EXIF"];
+
+/// The VP8X flag indicating an XMP chunk
+const FLAG_XMP: u8 = 0x04;
+/// The VP8X flag indicating transparency
+const FLAG_ALPHA: u8 = 0x10;
+
+/// The `FourCC` and size of a chunk, which precede its data.
+const CHUNK_HEADER_SIZE: usize = 8;
+
+fn malformed(msg: &str) -> Error {
+    Error::Boxed(format!("Malformed WebP: {msg}").into())
+}
+
+fn read_u32_le(bytes: &[u8], offset: usize) -> Option<u32> {
+    bytes
+        .get(offset..offset + 4)
+        .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok())
+        .map(u32::from_le_bytes)
+}
+
+/// A chunk of a WebP file, borrowed from the raw file content.
+struct Chunk<'a> {
+    fourcc: [u8; 4],
+    /// The chunk data, without `FourCC`, size and padding.
+    data: &'a [u8],
+    /// The whole chunk, exactly as it appears in the file.
+    raw: &'a [u8],
+}
+
+/// Splits the content of a WebP file into its chunks,
+/// without decoding or validating any of them.
+fn split_chunks(webp: &[u8]) -> Result<Vec<Chunk<'_>>, Error> {
+    if webp.get(..4) != Some(RIFF) || webp.get(8..12) != Some(WEBP) {
+        return Err(malformed("RIFF/WEBP header missing"));
+    }
+    let riff_size = read_u32_le(webp, 4)
+        .and_then(|size| usize::try_from(size).ok())
+        .ok_or_else(|| malformed("truncated header"))?;
+    let mut rest = webp
+        .get(12..riff_size.saturating_add(8))
+        .ok_or_else(|| malformed("truncated file"))?;
+    let mut chunks = Vec::new();
+    while !rest.is_empty() {
+        let fourcc = rest
+            .first_chunk::<4>()
+            .copied()
+            .ok_or_else(|| malformed("truncated chunk FourCC"))?;
+        let size = read_u32_le(rest, 4)
+            .and_then(|size| usize::try_from(size).ok())
+            .ok_or_else(|| malformed("truncated chunk size"))?;
+        let data = rest
+            .get(CHUNK_HEADER_SIZE..CHUNK_HEADER_SIZE + size)
+            .ok_or_else(|| malformed("truncated chunk"))?;
+        // Chunks are padded to an even size
+        let (raw, after) = rest
+            .split_at_checked(CHUNK_HEADER_SIZE + size + size % 2)
+            .ok_or_else(|| malformed("truncated chunk padding"))?;
+        chunks.push(Chunk { fourcc, data, raw });
+        rest = after;
+    }
+    Ok(chunks)
+}
+
+fn write_chunk(output: &mut Vec<u8>, fourcc: [u8; 4], data: &[u8]) -> Result<(), Error> {
+    let size = u32::try_from(data.len()).map_err(|_err| malformed("chunk too big"))?;
+    output.extend_from_slice(&fourcc);
+    output.extend_from_slice(&size.to_le_bytes());
+    output.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        output.push(0);
+    }
+    Ok(())
+}
+
+/// Creates the data of the extended format header (`VP8X`) chunk
+/// for a simple format file, from its only image chunk.
+fn create_vp8x(image: &Chunk<'_>) -> Result<[u8; 10], Error> {
+    let (width, height, alpha) = match &image.fourcc {
+        VP8 => {
+            // 3 bytes frame tag, 3 bytes start code, 14 bits width, 14 bits height
+            if image.data.get(3..6) != Some(&[0x9D, 0x01, 0x2A]) {
+                return Err(malformed("invalid VP8 start code"));
+            }
+            let dims = read_u32_le(image.data, 6).ok_or_else(|| malformed("truncated VP8"))?;
+            (dims & 0x3FFF, (dims >> 16) & 0x3FFF, false)
+        }
+        VP8L => {
+            // 1 byte signature, 14 bits width - 1, 14 bits height - 1, 1 bit alpha
+            if image.data.first() != Some(&0x2F) {
+                return Err(malformed("invalid VP8L signature"));
+            }
+            let bits = read_u32_le(image.data, 1).ok_or_else(|| malformed("truncated VP8L"))?;
+            (
+                (bits & 0x3FFF) + 1,
+                ((bits >> 14) & 0x3FFF) + 1,
+                (bits >> 28) & 1 == 1,
+            )
+        }
+        _ => return Err(malformed("image data (VP8/VP8L) expected as first chunk")),
+    };
+    let mut vp8x = [0; 10];
+    let (flags, canvas) = vp8x.split_at_mut(4);
+    if let Some(flags) = flags.first_mut() {
+        *flags = if alpha { FLAG_ALPHA } else { 0 };
+    }
+    // Canvas width and height minus one, as 24 bit numbers each
+    for (dst, dim) in canvas.chunks_exact_mut(3).zip([width, height]) {
+        dst.copy_from_slice(
+            dim.saturating_sub(1)
+                .to_le_bytes()
+                .get(..3)
+                .unwrap_or_default(),
+        );
+    }
+    Ok(vp8x)
+}
+
+/// Bakes at the chunk level (see [`super::rewrite_file`]):
+/// The header (`VP8X`) gets the XMP flag set,
+/// and the `XMP ` chunk is replaced,
+/// or, if there is none, inserted after all the known chunks.
+fn bake(input: &[u8], verify: &str, fail_if_verify_present: bool) -> Result<Vec<u8>, Error> {
+    let chunks = split_chunks(input)?;
+    let present_packet = chunks
+        .iter()
+        .find(|chunk| &chunk.fourcc == XMP)
+        .map(|chunk| chunk.data);
+    let Some(packet) = xmp::bake(present_packet, verify, fail_if_verify_present)? else {
+        return Ok(input.to_vec());
+    };
+
+    let first = chunks.first().ok_or_else(|| malformed("no chunks"))?;
+    let mut vp8x = if &first.fourcc == VP8X {
+        first.data.to_vec()
+    } else {
+        log::debug!("Converting to the extended WebP file format ...");
+        create_vp8x(first)?.to_vec()
+    };
+    *vp8x
+        .first_mut()
+        .ok_or_else(|| malformed("empty VP8X chunk"))? |= FLAG_XMP;
+
+    let insert_pos = chunks
+        .iter()
+        .position(|chunk| &chunk.fourcc == XMP)
+        .or_else(|| {
+            chunks
+                .iter()
+                .rposition(|chunk| PRECEDING_XMP.contains(&&chunk.fourcc))
+                .map(|pos| pos + 1)
+        })
+        .unwrap_or(chunks.len());
+    let mut output = Vec::with_capacity(input.len() + packet.len() + 32);
+    output.extend_from_slice(RIFF);
+    // Placeholder for the RIFF size
+    output.extend_from_slice(&[0; 4]);
+    output.extend_from_slice(WEBP);
+    write_chunk(&mut output, *VP8X, &vp8x)?;
+    for (idx, chunk) in chunks.iter().enumerate() {
+        if idx == insert_pos {
+            write_chunk(&mut output, *XMP, &packet)?;
+        }
+        if &chunk.fourcc != VP8X && &chunk.fourcc != XMP {
+            output.extend_from_slice(chunk.raw);
+        }
+    }
+    if insert_pos == chunks.len() {
+        write_chunk(&mut output, *XMP, &packet)?;
+    }
+    let riff_size = u32::try_from(output.len() - 8).map_err(|_err| malformed("too big"))?;
+    output
+        .get_mut(4..8)
+        .ok_or_else(|| malformed("no RIFF size"))?
+        .copy_from_slice(&riff_size.to_le_bytes());
+    Ok(output)
+}
+
+impl super::Patcher for Patcher {
+    fn rewrite<P: AsRef<Path>, S: AsRef<str>>(
+        input_file: P,
+        output_file: P,
+        verify: S,
+        fail_if_verify_present: bool,
+    ) -> Result<(), Error> {
+        super::rewrite_file(
+            input_file,
+            output_file,
+            verify,
+            fail_if_verify_present,
+            bake,
+        )
+    }
+}
+
+impl super::Extractor for Patcher {
+    fn extract<P: AsRef<Path>>(input_file: P) -> Result<Option<String>, Error> {
+        super::extract_file(input_file, |input| {
+            split_chunks(input)?
+                .iter()
+                .find(|chunk| &chunk.fourcc == XMP)
+                .map_or(Ok(None), |chunk| xmp::extract(chunk.data))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patcher::{Extractor, Patcher as _};
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+    use std::fs;
+
+    /// A simple format, lossy, 1x1 pixel image
+    const SOURCE_IMAGE: &str = "res/media/img/test.webp";
+    /// A simple format, lossless, 1x1 pixel image, with alpha
+    const LOSSLESS: &str = "UklGRhoAAABXRUJQVlA4TA0AAAAvAAAAEAcQERGIiP4HAA==";
+    /// An extended format, lossy, 1x1 pixel image, with alpha
+    const EXTENDED: &str = "UklGRkoAAABXRUJQVlA4WAoAAAAQAAAAAAAAAAAAQUxQSAwAAAARBxAR/Q9ERP8DAABWUDggGAAAABQBAJ0BKgEAAQAAAP4AAA3AAP7mtQAAAA==";
+
+    fn fourccs(webp: &[u8]) -> Result<Vec<[u8; 4]>, Error> {
+        Ok(split_chunks(webp)?
+            .iter()
+            .map(|chunk| chunk.fourcc)
+            .collect())
+    }
+
+    #[test]
+    fn extract_unbaked() -> Result<(), Error> {
+        assert_eq!(Patcher::extract(SOURCE_IMAGE)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn bake_and_extract() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempfile::tempdir()?;
+        let baked = tmp_dir.path().join("baked.webp");
+        let verify = "https://example.org/assertion.json";
+        Patcher::rewrite(Path::new(SOURCE_IMAGE), &baked, verify, true)?;
+        assert_eq!(Patcher::extract(&baked)?.as_deref(), Some(verify));
+        Ok(())
+    }
+
+    #[test]
+    fn bake_formats() -> Result<(), Box<dyn std::error::Error>> {
+        let verify = "https://example.org/assertion.json";
+        for (input, expected_vp8x, expected_chunks) in [
+            (
+                fs::read(SOURCE_IMAGE)?,
+                [FLAG_XMP, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                vec![*VP8X, *VP8, *XMP],
+            ),
+            (
+                BASE64.decode(LOSSLESS)?,
+                [FLAG_XMP | FLAG_ALPHA, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                vec![*VP8X, *VP8L, *XMP],
+            ),
+            (
+                BASE64.decode(EXTENDED)?,
+                [FLAG_XMP | FLAG_ALPHA, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                vec![*VP8X, *b"ALPH", *VP8, *XMP],
+            ),
+        ] {
+            let baked = bake(&input, verify, true)?;
+            assert_eq!(fourccs(&baked)?, expected_chunks);
+            let chunks = split_chunks(&baked)?;
+            assert_eq!(
+                chunks.first().map(|chunk| chunk.data),
+                Some(&expected_vp8x[..])
+            );
+            // The image data is left untouched
+            for chunk in split_chunks(&input)?
+                .iter()
+                .skip_while(|chunk| &chunk.fourcc == VP8X)
+            {
+                assert!(chunks
+                    .iter()
+                    .any(|baked_chunk| baked_chunk.raw == chunk.raw));
+            }
+            assert_eq!(
+                read_u32_le(&baked, 4).map(|size| size as usize),
+                Some(baked.len() - 8)
+            );
+
+            // Baking the same again changes nothing, different content replaces it
+            assert_eq!(bake(&baked, verify, true)?, baked);
+            assert!(matches!(
+                bake(&baked, "https://example.org/other.json", true),
+                Err(Error::VerifyAlreadySet { .. })
+            ));
+            let rebaked = bake(&baked, "https://example.org/other.json", false)?;
+            assert_eq!(fourccs(&rebaked)?, expected_chunks);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_input() {
+        assert!(bake(b"not a WebP", "x", true).is_err());
+        assert!(bake(b"RIFF\x10\0\0\0WEBPVP8 \x20\0\0\0", "x", true).is_err());
+    }
+}
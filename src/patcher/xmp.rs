@@ -0,0 +1,290 @@
+// SPDX-FileCopyrightText: 2026 Robin Vobruba <hoijui.quaero@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Open Badge meta-data in an [XMP](https://www.adobe.com/devnet/xmp.html) packet,
+//! as used for image formats without a dedicated place for it,
+//! like JPEG and WebP.
+//!
+//! The baked content (`verify`, in Open Badge terms)
+//! is the value of the `openbadges:verify` property,
+//! in the namespace [`NAMESPACE`], which is the same as used for baking SVGs:
+//!
+//! ```xml
+//! <rdf:Description rdf:about="" xmlns:openbadges="http://openbadges.org">
+//!   <openbadges:verify>https://example.org/assertion.json</openbadges:verify>
+//! </rdf:Description>
+//! ```
+//!
+//! All other meta-data in the packet is left as is.
+
+use xml::name::OwnedName;
+use xml::reader::{EventReader, XmlEvent};
+use xml::writer::EmitterConfig;
+
+use super::Error;
+
+/// The XML namespace of the Open Badge XMP property.
+pub const NAMESPACE: &str = "http://openbadges.org";
+/// The preferred XML namespace prefix for [`NAMESPACE`].
+pub const PREFIX: &str = "openbadges";
+/// The name of the XMP property holding the baked content.
+pub const PROPERTY: &str = "verify";
+
+const RDF_NAMESPACE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+
+/// An XMP packet without any properties.
+const EMPTY_PACKET: &str = "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\
+<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\
+<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\
+<rdf:Description rdf:about=\"\"/>\
+</rdf:RDF>\
+</x:xmpmeta>\
+<?xpacket end=\"w\"?>";
+
+fn conv_read_err(err: xml::reader::Error) -> Error {
+    Error::Boxed(Box::new(err))
+}
+
+fn conv_write_err(err: xml::writer::Error) -> Error {
+    Error::Boxed(Box::new(err))
+}
+
+fn is_ob_verify(name: &OwnedName) -> bool {
+    Some(NAMESPACE) == name.namespace.as_deref() && name.local_name == PROPERTY
+}
+
+fn is_rdf(name: &OwnedName, local_name: &str) -> bool {
+    Some(RDF_NAMESPACE) == name.namespace.as_deref() && name.local_name == local_name
+}
+
+/// Returns the baked content from an XMP packet, if there is any.
+///
+/// Besides the element form shown in the [module docs](self),
+/// this also reads the attribute form
+/// (`<rdf:Description openbadges:verify="..."/>`).
+///
+/// # Errors
+///
+/// If the packet is not well-formed XML.
+pub fn extract(packet: &[u8]) -> Result<Option<String>, Error> {
+    // The body of the `<openbadges:verify>` element,
+    // while we are within it
+    let mut body: Option<String> = None;
+    for evt_res in EventReader::new(packet) {
+        match (evt_res.map_err(conv_read_err)?, body.as_mut()) {
+            (XmlEvent::StartElement { name, .. }, None) if is_ob_verify(&name) => {
+                body = Some(String::new());
+            }
+            (XmlEvent::StartElement { attributes, .. }, None) => {
+                if let Some(attr) = attributes.into_iter().find(|attr| is_ob_verify(&attr.name)) {
+                    log::debug!("{PREFIX}:{PROPERTY} - found as attribute");
+                    return Ok(Some(attr.value));
+                }
+            }
+            (XmlEvent::CData(text) | XmlEvent::Characters(text), Some(body)) => {
+                body.push_str(&text);
+            }
+            (XmlEvent::EndElement { name }, Some(body)) if is_ob_verify(&name) => {
+                log::debug!("{PREFIX}:{PROPERTY} - found as element");
+                let content = body.trim();
+                return Ok((!content.is_empty()).then(|| content.to_owned()));
+            }
+            _ => (),
+        }
+    }
+    Ok(None)
+}
+
+/// Sets the baked content in an XMP packet,
+/// creating a new packet if none is given.
+///
+/// Any existing `openbadges:verify` property is removed,
+/// and the new one is added to the first `rdf:Description`.
+///
+/// # Errors
+///
+/// If the packet is not well-formed XML.
+pub fn rewrite(packet_opt: Option<&[u8]>, verify: &str) -> Result<Vec<u8>, Error> {
+    let packet = packet_opt.unwrap_or(EMPTY_PACKET.as_bytes());
+    let mut output = Vec::with_capacity(packet.len() + verify.len() + 128);
+    let mut writer = EmitterConfig::new()
+        .write_document_declaration(false)
+        .create_writer(&mut output);
+
+    let write_property = |writer: &mut xml::writer::EventWriter<&mut Vec<u8>>| {
+        writer
+            .write(
+                xml::writer::XmlEvent::start_element(format!("{PREFIX}:{PROPERTY}").as_str())
+                    .ns(PREFIX, NAMESPACE),
+            )
+            .and_then(|()| writer.write(xml::writer::XmlEvent::characters(verify)))
+            .and_then(|()| writer.write(xml::writer::XmlEvent::end_element()))
+            .map_err(conv_write_err)
+    };
+
+    let mut written = false;
+    // Depth within an already present `<openbadges:verify>` element
+    let mut skip_depth = 0_usize;
+    for evt_res in EventReader::new(packet) {
+        let mut evt = evt_res.map_err(conv_read_err)?;
+        if skip_depth > 0 {
+            match evt {
+                XmlEvent::StartElement { .. } => skip_depth += 1,
+                XmlEvent::EndElement { .. } => skip_depth -= 1,
+                XmlEvent::StartDocument { .. }
+                | XmlEvent::EndDocument
+                | XmlEvent::ProcessingInstruction { .. }
+                | XmlEvent::CData(_)
+                | XmlEvent::Comment(_)
+                | XmlEvent::Characters(_)
+                | XmlEvent::Whitespace(_) => (),
+            }
+            continue;
+        }
+        match &mut evt {
+            // We do not want an XML declaration in front of the packet
+            XmlEvent::StartDocument { .. } => continue,
+            XmlEvent::StartElement { name, .. } if is_ob_verify(name) => {
+                log::debug!("{PREFIX}:{PROPERTY} - removing the present one");
+                skip_depth = 1;
+                continue;
+            }
+            XmlEvent::StartElement { attributes, .. } => {
+                attributes.retain(|attr| !is_ob_verify(&attr.name));
+            }
+            XmlEvent::EndElement { name } if !written && is_rdf(name, "RDF") => {
+                // There was no description to add our property to
+                writer
+                    .write(
+                        xml::writer::XmlEvent::start_element("rdf:Description")
+                            .attr("rdf:about", ""),
+                    )
+                    .map_err(conv_write_err)?;
+                write_property(&mut writer)?;
+                writer
+                    .write(xml::writer::XmlEvent::end_element())
+                    .map_err(conv_write_err)?;
+                written = true;
+            }
+            XmlEvent::EndElement { .. }
+            | XmlEvent::EndDocument
+            | XmlEvent::ProcessingInstruction { .. }
+            | XmlEvent::CData(_)
+            | XmlEvent::Comment(_)
+            | XmlEvent::Characters(_)
+            | XmlEvent::Whitespace(_) => (),
+        }
+        let is_description =
+            matches!(&evt, XmlEvent::StartElement { name, .. } if is_rdf(name, "Description"));
+        if let Some(evt_out) = evt.as_writer_event() {
+            writer.write(evt_out).map_err(conv_write_err)?;
+        }
+        if is_description && !written {
+            write_property(&mut writer)?;
+            written = true;
+        }
+    }
+    if !written {
+        return Err(Error::Boxed(
+            "Invalid XMP packet: no 'rdf:RDF' element found".into(),
+        ));
+    }
+    Ok(output)
+}
+
+/// Bakes into an XMP packet,
+/// which is created if none is given.
+///
+/// Returns `None` if the packet already contains `verify`.
+///
+/// # Errors
+///
+/// If the packet is not well-formed XML.
+///
+/// If the packet already contains different content,
+/// and `fail_if_verify_present` is set.
+pub fn bake(
+    packet_opt: Option<&[u8]>,
+    verify: &str,
+    fail_if_verify_present: bool,
+) -> Result<Option<Vec<u8>>, Error> {
+    match packet_opt.map(extract).transpose()?.flatten() {
+        Some(present) if present == verify.trim() => {
+            log::info!("{PREFIX}:{PROPERTY} - already set to the desired value!");
+            return Ok(None);
+        }
+        Some(present) if fail_if_verify_present => {
+            return Err(Error::VerifyAlreadySet {
+                present,
+                proposed: verify.to_owned(),
+            });
+        }
+        Some(_present) => {
+            log::info!("{PREFIX}:{PROPERTY} - already set to an other value -> overwriting!");
+        }
+        None => (),
+    }
+    rewrite(packet_opt, verify).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERIFY: &str = "https://example.org/assertion.json";
+
+    #[test]
+    fn new_packet() -> Result<(), Error> {
+        let packet = rewrite(None, VERIFY)?;
+        let packet_str = String::from_utf8_lossy(&packet);
+        assert!(packet_str.starts_with("<?xpacket begin="));
+        assert!(packet_str.ends_with("<?xpacket end=\"w\"?>"));
+        assert!(packet_str.contains(&format!("xmlns:{PREFIX}=\"{NAMESPACE}\"")));
+        assert_eq!(extract(&packet)?.as_deref(), Some(VERIFY));
+        assert_eq!(extract(EMPTY_PACKET.as_bytes())?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_other_properties() -> Result<(), Box<dyn std::error::Error>> {
+        let existing = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/"
+        xmlns:ob="http://openbadges.org" ob:verify="https://example.org/old.json">
+      <dc:creator>Someone &amp; Co.</dc:creator>
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>"#;
+        assert_eq!(
+            extract(existing.as_bytes())?.as_deref(),
+            Some("https://example.org/old.json")
+        );
+        assert!(matches!(
+            bake(Some(existing.as_bytes()), VERIFY, true),
+            Err(Error::VerifyAlreadySet { .. })
+        ));
+        let assertion = "{\n  \"narrative\": \"<b>Done</b> & dusted\"\n}";
+        let packet = bake(Some(existing.as_bytes()), assertion, false)?.ok_or("Not baked")?;
+        assert_eq!(extract(&packet)?.as_deref(), Some(assertion));
+        let packet_str = String::from_utf8_lossy(&packet);
+        assert!(packet_str.contains("<dc:creator>Someone &amp; Co.</dc:creator>"));
+        assert!(!packet_str.contains("old.json"));
+
+        // Re-baking the same content changes nothing
+        assert_eq!(bake(Some(&packet), assertion, true)?, None);
+        let rebaked = bake(Some(&packet), VERIFY, false)?.ok_or("Not baked")?;
+        assert_eq!(extract(&rebaked)?.as_deref(), Some(VERIFY));
+        assert_eq!(
+            String::from_utf8_lossy(&rebaked).matches(PROPERTY).count(),
+            2
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_packet() {
+        assert!(rewrite(Some(b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/>"), VERIFY).is_err());
+        assert!(rewrite(Some(b"<not xml"), VERIFY).is_err());
+    }
+}
@@ -3,24 +3,27 @@
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
 use std::borrow::Cow;
-use std::fs::File;
+use std::fs::{self, File};
 use std::path::Path;
 use std::path::PathBuf;
 
-use biscuit::jws::Secret;
 use chrono::DateTime;
 use thiserror::Error;
 
-use crate::patcher::ImageType;
-use crate::settings::Settings;
-use crate::signature::Algorithm;
+use crate::patcher::Extractor;
+use crate::patcher::Payload;
+use crate::patcher::PayloadKind;
+use crate::patcher::{ImageType, ToImageTypeError};
+use crate::settings::{HostedPayload, Settings};
+use crate::signature::{Algorithm, HeaderOptions, Serialization};
+use crate::signer::{CommandSigner, KeySigner, Signer};
 use crate::Assertion;
 use crate::Identity;
 use crate::IdentityType;
 use crate::Verification;
 use crate::VerificationType;
 use crate::{box_err::BoxResult, patcher, patcher::Patcher};
-use crate::{constants, signature};
+use crate::{cert_chain, constants, hash, signature, ToJsonLd};
 
 /// This includes any error that may happen during a bakign attempt.
 #[derive(Error, Debug)]
@@ -37,6 +40,9 @@ pub enum Error {
     #[error("There was an issue reading or parsing the given x.509 certificate: {msg}")]
     InvalidCertificate { msg: String },
 
+    #[error("There was an issue reading or parsing the given recipient list: {msg}")]
+    InvalidRecipients { msg: String },
+
     #[error("There was an issue reading or parsing the given badge source image: {msg}")]
     InvalidSourceImage { msg: String },
 
@@ -91,10 +97,9 @@ pub fn run_examples() -> BoxResult<()> {
             badge_assert.expires =
                 Some(DateTime::parse_from_rfc3339(constants::DT_FAR_FUTURE)?.into());
             // let private_key_str = fs::read_to_string(constants::ISSUER_KEY_PATH_PRIV)?;
-            let key_priv =
-                biscuit::jws::Secret::rsa_keypair_from_file(constants::ISSUER_KEY_PATH_PRIV)?;
-            let alg = Algorithm::ES256;
-            let content = signature::sign(badge_assert, alg, &key_priv)?;
+            let signer =
+                KeySigner::from_file(Algorithm::ES256, false, constants::ISSUER_KEY_PATH_PRIV)?;
+            let content = signature::sign(&badge_assert, &signer)?;
             // log::debug!("XXX\n{content}\nXXX");
             // fs::write("badge_assert_plain.txt", &content)?;
             // fs::write("badge_assert_jws.txt", &content)?;
@@ -147,7 +152,7 @@ pub fn run_examples() -> BoxResult<()> {
     Ok(())
 }
 
-fn read_assertion(assertion_loc: &Path) -> BoxResult<Assertion> {
+pub(crate) fn read_assertion(assertion_loc: &Path) -> BoxResult<Assertion> {
     let assertion: Assertion = serde_json::from_reader(File::open(assertion_loc)?)?;
 
     if VerificationType::VerificationObject == assertion.verification.r#type {
@@ -163,140 +168,409 @@ fn read_assertion(assertion_loc: &Path) -> BoxResult<Assertion> {
     Ok(assertion)
 }
 
-fn read_priv_key_opt(alg: Algorithm, key_loc_opt: Option<&PathBuf>) -> BoxResult<Option<Secret>> {
-    Ok(if let Some(key_loc) = key_loc_opt {
-        let key_loc_str =
-            key_loc
-                .as_os_str()
-                .to_str()
-                .ok_or_else(|| Error::InvalidSigningPrivateKey {
-                    // TODO FIXME Could be solved, if we read the file first and give the content to biscuit
-                    msg: format!(
-                        "Can only read private key from Unicode path; this is not one: '{}'",
-                        key_loc.display()
-                    ),
-                })?;
-        Some(signature::load_private_key_pair(alg, key_loc_str)?)
-    } else {
-        None
-    })
+/// Creates a signer for the private key file,
+/// with the built-in signing backend.
+#[cfg(not(feature = "jsonwebtoken"))]
+fn key_signer(settings: &Settings, key_loc: &Path) -> BoxResult<Box<dyn Signer>> {
+    Ok(Box::new(
+        KeySigner::from_file(settings.sign_alg, settings.infer_sign_alg, key_loc)?
+            .with_key_id(settings.sign_key_id.clone()),
+    ))
 }
 
-fn read_cert_chain_opt(cert_loc_opt: Option<&PathBuf>) -> BoxResult<Option<Vec<String>>> {
-    Ok(if let Some(cert_loc) = cert_loc_opt {
-        let cert_loc_str =
-            cert_loc
-                .as_os_str()
-                .to_str()
-                .ok_or_else(|| Error::InvalidCertificate {
-                    // TODO FIXME Could be solved, if we read the file first and give the content to biscuit
-                    msg: format!(
-                        "Can only read x.509certificate from Unicode path; this is not one: '{}'",
-                        cert_loc.display()
-                    ),
-                })?;
-        todo!("Certificate chain loading is not yet implemented, because it is probably not possible from a (standardized format) file, but nneeds to be reconstructed though a very complicated process, that browsers know how to do.");
-        return Err(Error::InvalidCertificate {
-            msg: format!("Certificate chain loading is not yet implemented, becasue it is probably not possible from a (standardized format) file, but nneeds to be reconstructed though a very complicated process, that browsers know how to do. ... from '{cert_loc_str}'",),
-        }.into());
-    //         Certificate::
-    //         // TODO FIXME Support more key types!
-    //         let key = biscuit::jws::Secret::rsa_keypair_from_file(key_loc_str).map_err(|err| {
-    //             let hint = if let biscuit::errors::Error::KeyRejected(_key_rejected) = &err {
-    //                 Some(
-    //                     "
-    // Often, keys generated for use in OpenSSL-based software are
-    // encoded in PEM format, which is not supported by *ring*. PEM-encoded
-    // keys that are in `RSAPrivateKey` format can be re-encoded into DER
-    // using an OpenSSL command like this:
-
-    // ```sh
-    // openssl rsa -in private_key.pem -outform DER -out private_key.der
-    // ```
-    // ",
-    //                 )
-    //             } else {
-    //                 None
-    //             };
-    //             Error::InvalidSigningPrivateKey {
-    //                 msg: format!(
-    //                     "Failed to decode a valid crypto key from '{key_loc_str}': {err:#?}{}",
-    //                     hint.unwrap_or_default()
-    //                 ),
-    //             }
-    //         })?;
-    // Some(key)
-    } else {
-        None
+/// Creates a signer for the private key file,
+/// with the jsonwebtoken signing backend,
+/// if it supports the signing algorithm.
+#[cfg(feature = "jsonwebtoken")]
+fn key_signer(settings: &Settings, key_loc: &Path) -> BoxResult<Box<dyn Signer>> {
+    crate::signature_jsonwebtoken::signer_from_file(
+        settings.sign_alg,
+        settings.infer_sign_alg,
+        key_loc,
+        settings.sign_key_id.clone(),
+    )
+}
+
+/// Creates the signer as configured in `settings`,
+/// if signing is used.
+///
+/// # Errors
+///
+/// If both a private key and a signing command are given.
+///
+/// If the signing key could not be read,
+/// or does not fit the signing algorithm.
+pub fn create_signer_opt(settings: &Settings) -> BoxResult<Option<Box<dyn Signer>>> {
+    Ok(match (&settings.sign_key_loc, &settings.sign_command) {
+        (None, None) => None,
+        (Some(_), Some(_)) => {
+            return Err(Error::InvalidSettings {
+                msg: "Either sign with a private-key or with an external command, not both"
+                    .to_owned(),
+            }
+            .into());
+        }
+        (Some(key_loc), None) => Some(key_signer(settings, key_loc)?),
+        (None, Some(command)) => {
+            let public_key_loc =
+                settings
+                    .sign_public_key_loc
+                    .as_ref()
+                    .ok_or_else(|| Error::InvalidSettings {
+                        msg: "Signing with an external command requires the public key".to_owned(),
+                    })?;
+            if settings.sign_alg == Algorithm::None {
+                return Err(Error::InvalidSettings {
+                    msg: "Signing with an external command requires \
+                            the signing algorithm to be specified explicitly"
+                        .to_owned(),
+                }
+                .into());
+            }
+            Some(Box::new(
+                CommandSigner::from_file(settings.sign_alg, public_key_loc, command)?
+                    .with_key_id(settings.sign_key_id.clone()),
+            ))
+        }
     })
 }
 
-fn parse_image_types(
-    source_image_loc: &Path,
-    baked_loc: &Path,
-) -> BoxResult<(ImageType, ImageType)> {
-    let source_image_type =
-        ImageType::try_from(source_image_loc).map_err(|err| Error::InvalidSettings {
-            msg: format!("Invalid source image path: {err:#?}!"),
-        })?;
-    let baked_type = ImageType::try_from(baked_loc).map_err(|err| Error::InvalidSettings {
-        msg: format!("Invalid baked image path: {err:#?}!"),
+pub(crate) fn read_cert_chain_opt(
+    cert_locs: &[PathBuf],
+    signer_opt: Option<&dyn Signer>,
+) -> BoxResult<Option<Vec<String>>> {
+    if cert_locs.is_empty() {
+        return Ok(None);
+    }
+    let signer = signer_opt.ok_or_else(|| Error::InvalidSettings {
+        msg: "A certificate chain is only used when signing, but no signing key is supplied"
+            .to_owned(),
     })?;
+    let chain = cert_chain::read(cert_locs, &signer.public_key_der()?)?;
+    Ok(Some(cert_chain::to_x5c(&chain)?))
+}
 
-    if source_image_type != baked_type {
-        return Err(Error::InvalidSettings { msg: format!(
-            "Source and baked image types must be equal, but are: {source_image_type:#?} & {baked_type:#?}") }.into());
+/// Figures out the type of the source image,
+/// from its content, unless `format` is given;
+/// see [`ImageType::detect`].
+///
+/// # Errors
+///
+/// If the file can not be read,
+/// or its content is not of a supported image type.
+pub fn source_image_type(
+    source_image_loc: &Path,
+    format: Option<ImageType>,
+) -> BoxResult<ImageType> {
+    if let Some(format) = format {
+        log::debug!("Using the explicitly set image type {format:?}");
+        return Ok(format);
+    }
+    Ok(
+        ImageType::detect(source_image_loc).map_err(|err| Error::InvalidSettings {
+            msg: format!("Invalid source image: {err}"),
+        })?,
+    )
+}
+
+/// Figures out the type of the source image,
+/// which the baked image will have as well.
+fn parse_image_type(
+    source_image_loc: &Path,
+    baked_loc: &Path,
+    format: Option<ImageType>,
+) -> BoxResult<ImageType> {
+    let image_type = source_image_type(source_image_loc, format)?;
+    match ImageType::try_from(baked_loc) {
+        Ok(baked_ext_type) if baked_ext_type != image_type => {
+            log::warn!(
+                "The baked image '{}' will be a {image_type:?} image, \
+                despite its extension suggesting {baked_ext_type:?}",
+                baked_loc.display()
+            );
+        }
+        _ => (),
     }
+    Ok(image_type)
+}
 
-    Ok((source_image_type, baked_type))
+/// Whether `url` can be baked to reference a hosted assertion,
+/// which requires it to be fetchable.
+fn is_hosted_url(url: &str) -> bool {
+    PayloadKind::detect(url) == PayloadKind::Url
+        && url.split_once("://").is_some_and(|(scheme, _rest)| {
+            ["http", "https"].contains(&scheme.to_lowercase().as_str())
+        })
 }
 
-fn create_baking_content(
-    assertion: Assertion,
-    alg: Algorithm,
-    key_priv_opt: Option<Secret>,
-    x509_chain: Option<Vec<String>>,
+fn hosted_baking_content(
+    assertion: &Assertion,
+    hosted_payload: &HostedPayload,
 ) -> BoxResult<Cow<'static, str>> {
-    Ok(if false {
-        // TODO FIXME We need to support this too!
-        let verify_url = "https://thejeshgn.github.io/openbadge/thejeshgn-reader-badge.json";
-        Cow::Borrowed(verify_url)
-    } else {
-        match (&assertion.verification.r#type, key_priv_opt) {
-            (VerificationType::VerificationObject, _) => {
-                return Err(Error::Impossible {
+    Ok(match hosted_payload {
+        HostedPayload::Json => Cow::Owned(serde_json::to_string_pretty(assertion)?),
+        HostedPayload::Id => {
+            if !is_hosted_url(&assertion.id) {
+                return Err(Error::InvalidSettings {
                     msg: format!(
-                        "We already made sure that the Assertions verification.type is not {:#?}!",
-                        assertion.verification.r#type
+                        "The assertion IRI '{}' is not an HTTP(S) URL, \
+                        so the assertion can not be hosted under it",
+                        assertion.id
                     ),
                 }
                 .into());
             }
-            (VerificationType::HostedBadge, Some(_key_priv)) => {
-                return Err(Error::InvalidSettings { msg: format!(
+            log::info!("Baking the assertion IRI '{}' ...", assertion.id);
+            Cow::Owned(assertion.id.clone())
+        }
+        HostedPayload::Url(url) => {
+            if !is_hosted_url(url) {
+                return Err(Error::InvalidSettings {
+                    msg: format!("Not a valid HTTP(S) URL to bake: '{url}'"),
+                }
+                .into());
+            }
+            if url != &assertion.id {
+                log::warn!(
+                    "The URL to bake ('{url}') differs from the assertions IRI ('{}'); \
+                    make sure the assertion is hosted under its IRI, \
+                    or verification will fail.",
+                    assertion.id
+                );
+            }
+            log::info!("Baking the assertion URL '{url}' ...");
+            Cow::Owned(url.clone())
+        }
+    })
+}
+
+pub(crate) fn create_baking_content(
+    assertion: &Assertion,
+    signer_opt: Option<&dyn Signer>,
+    x509_chain: Option<Vec<String>>,
+    hosted_payload: &HostedPayload,
+    header_opts: &HeaderOptions,
+) -> BoxResult<Cow<'static, str>> {
+    Ok(match (&assertion.verification.r#type, signer_opt) {
+        (VerificationType::VerificationObject, _) => {
+            return Err(Error::Impossible {
+                msg: format!(
+                    "We already made sure that the Assertions verification.type is not {:#?}!",
+                    assertion.verification.r#type
+                ),
+            }
+            .into());
+        }
+        (VerificationType::HostedBadge, Some(_signer)) => {
+            return Err(Error::InvalidSettings { msg: format!(
                 "The Assertions verification.type is {:#?}, but a private-key is also supplied; You must change either of these!",
                 assertion.verification.r#type) }.into());
-            }
-            (VerificationType::SignedBadge { creator: _ }, None) => {
-                return Err(Error::InvalidSettings { msg: format!(
+        }
+        (VerificationType::SignedBadge { creator: _ }, None) => {
+            return Err(Error::InvalidSettings { msg: format!(
                 "The Assertions verification.type is {:#?}, but a private-key is *not* supplied; You must change either of these!",
                 assertion.verification.r#type) }.into());
+        }
+        (VerificationType::SignedBadge { creator }, Some(signer)) => {
+            if hosted_payload != &HostedPayload::Json {
+                return Err(Error::InvalidSettings { msg: format!(
+                    "The Assertions verification.type is {:#?}, but baking by URL is only possible for hosted assertions; You must change either of these!",
+                    assertion.verification.r#type) }.into());
             }
-            (VerificationType::SignedBadge { creator }, Some(key_priv)) => {
-                let content = signature::sign_with_cert(assertion, alg, &key_priv, x509_chain)?;
-                // log::debug!("XXX\n{content}\nXXX");
-                // fs::write("badge_assert_plain.txt", &content)?;
-                // fs::write("badge_assert_jws.txt", &content)?;
-                Cow::Owned(content)
-            }
-            (VerificationType::HostedBadge, None) => {
-                let badge_assert_ser = serde_json::to_string_pretty(&assertion)?;
-                Cow::Owned(badge_assert_ser)
-            }
+            let content = if let Some(key_id) = signer.key_id() {
+                if creator.as_deref().is_some_and(|creator| creator != key_id) {
+                    log::warn!(
+                        "Replacing the creator of the assertion with the signing key ID '{key_id}'."
+                    );
+                }
+                let mut assertion = assertion.clone();
+                assertion.verification.r#type = VerificationType::SignedBadge {
+                    creator: Some(key_id.to_owned()),
+                };
+                signature::sign_with_cert(&assertion, signer, x509_chain, header_opts)?
+            } else {
+                signature::sign_with_cert(assertion, signer, x509_chain, header_opts)?
+            };
+            // log::debug!("XXX\n{content}\nXXX");
+            // fs::write("badge_assert_plain.txt", &content)?;
+            // fs::write("badge_assert_jws.txt", &content)?;
+            Cow::Owned(content)
         }
+        (VerificationType::HostedBadge, None) => hosted_baking_content(assertion, hosted_payload)?,
     })
 }
 
+/// The size (in bytes) of baked content above which we warn by default.
+///
+/// Many tools and platforms handling badge images
+/// do not expect meta-data of this size.
+pub const DEFAULT_PAYLOAD_WARN_SIZE: usize = 16 * 1024;
+
+/// Logs a warning if the content to be baked
+/// is larger than configured in `settings`.
+fn warn_if_large(image_type: ImageType, baking_content: &str, settings: &Settings) {
+    let Some(warn_size) = settings.payload_warn_size else {
+        return;
+    };
+    if baking_content.len() <= warn_size {
+        return;
+    }
+    let compression_hint = if image_type == ImageType::Png && !settings.png_compress {
+        ", compressing it"
+    } else {
+        ""
+    };
+    log::warn!(
+        "The content to be baked is {} bytes large, more than {warn_size} bytes; \
+        consider baking only the URL of a hosted assertion{compression_hint}, \
+        or leaving out the certificate chain.",
+        baking_content.len()
+    );
+}
+
+pub(crate) fn bake(
+    image_type: ImageType,
+    source_image_loc: &Path,
+    baked_loc: &Path,
+    baking_content: &str,
+    fail_if_veri_present: bool,
+    settings: &Settings,
+) -> BoxResult<()> {
+    warn_if_large(image_type, baking_content, settings);
+    // let patcher: Box<dyn patcher::Patcher> = match image_type {
+    //     ImageType::Svg => Box::new(patcher::svg::Patcher),
+    //     ImageType::Png => Box::new(patcher::png::Patcher),
+    // };
+    match image_type {
+        ImageType::Svg => {
+            patcher::svg::Patcher::rewrite(
+                source_image_loc,
+                baked_loc,
+                baking_content,
+                fail_if_veri_present,
+            )?;
+        }
+        ImageType::Svgz => {
+            patcher::svg::CompressedPatcher::rewrite(
+                source_image_loc,
+                baked_loc,
+                baking_content,
+                fail_if_veri_present,
+            )?;
+        }
+        ImageType::Png => {
+            patcher::png::Patcher::rewrite_with_compression(
+                source_image_loc,
+                baked_loc,
+                baking_content,
+                fail_if_veri_present,
+                settings.png_compress,
+            )?;
+        }
+        ImageType::Jpeg => {
+            patcher::jpeg::Patcher::rewrite(
+                source_image_loc,
+                baked_loc,
+                baking_content,
+                fail_if_veri_present,
+            )?;
+        }
+        ImageType::Webp => {
+            patcher::webp::Patcher::rewrite(
+                source_image_loc,
+                baked_loc,
+                baking_content,
+                fail_if_veri_present,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// The extension appended to the baked image path
+/// for the signed compact JWS sidecar file.
+pub const SIDECAR_EXT_JWS: &str = "jws";
+/// The extension appended to the baked image path
+/// for the plain JSON assertion sidecar file.
+pub const SIDECAR_EXT_JSON: &str = "json";
+/// The extension appended to the baked image path
+/// for the SHA-256 digest sidecar file.
+pub const SIDECAR_EXT_DIGEST: &str = "sha256";
+
+/// Appends an extension to the (full) baked image path,
+/// e.g. `badge.svg` -> `badge.svg.jws`.
+#[must_use]
+pub fn sidecar_path(baked_loc: &Path, ext: &str) -> PathBuf {
+    let mut path = baked_loc.as_os_str().to_owned();
+    path.push(".");
+    path.push(ext);
+    PathBuf::from(path)
+}
+
+/// Writes copies of what was baked next to the baked image,
+/// so it can be audited without unbaking it.
+///
+/// These are the signed compact JWS (only for signed assertions),
+/// the plain JSON assertion (byte-for-byte as signed, for signed assertions)
+/// and the SHA-256 digest of the baked image,
+/// the latter in the format of `sha256sum`.
+/// See [`sidecar_path`] for the file names.
+///
+/// # Errors
+///
+/// If reading the baked image or writing any of the files fails.
+///
+/// If the signed assertion can not be decoded.
+pub fn write_sidecars(
+    baked_loc: &Path,
+    assertion: &Assertion,
+    baking_content: &str,
+) -> BoxResult<Vec<PathBuf>> {
+    let mut written = Vec::with_capacity(3);
+    let json_ld = if PayloadKind::detect(baking_content) == PayloadKind::Jws {
+        let jws_loc = sidecar_path(baked_loc, SIDECAR_EXT_JWS);
+        fs::write(&jws_loc, format!("{}\n", baking_content.trim()))?;
+        written.push(jws_loc);
+        signature::decode_payload_unverified(baking_content)?
+    } else {
+        assertion.to_json_ld()?.into_bytes()
+    };
+    let json_loc = sidecar_path(baked_loc, SIDECAR_EXT_JSON);
+    fs::write(&json_loc, json_ld)?;
+    written.push(json_loc);
+
+    let digest = hash::sha256_hex(&fs::read(baked_loc)?);
+    let file_name = baked_loc
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let digest_loc = sidecar_path(baked_loc, SIDECAR_EXT_DIGEST);
+    fs::write(&digest_loc, format!("{digest}  {file_name}\n"))?;
+    written.push(digest_loc);
+
+    log::info!(
+        "Wrote the sidecar files of '{}': {}",
+        baked_loc.display(),
+        written
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    Ok(written)
+}
+
+/// The JOSE header options for the standalone JWS (see [`Settings::jws_loc`]):
+/// Unless set explicitly, the media type (`typ`) is the one fitting `serialization`;
+/// see [`Serialization::media_type`].
+fn jws_output_header(header: &HeaderOptions, serialization: Serialization) -> HeaderOptions {
+    let mut header = header.clone();
+    if header.r#type.is_none() {
+        header.r#type = Some(serialization.media_type().to_owned());
+    }
+    header
+}
+
 /// The main function of this crate,
 /// TODO
 ///
@@ -306,51 +580,84 @@ fn create_baking_content(
 ///
 /// If encoding the assertion fails.
 pub fn run(settings: &Settings) -> BoxResult<()> {
-    if let (Some(assertion_loc), Some(source_image_loc), Some(baked_loc)) = (
+    let images = settings
+        .source_image_loc
+        .as_ref()
+        .zip(settings.baked_loc.as_ref());
+    if let (Some(assertion_loc), true) = (
         settings.assertion_loc.as_ref(),
-        settings.source_image_loc.as_ref(),
-        settings.baked_loc.as_ref(),
+        images.is_some() || settings.jws_loc.is_some(),
     ) {
-        log::info!("Baking Open Badge Assertion from {assertion_loc:#?} into image file {baked_loc:#?} now ...");
-
         let assertion = read_assertion(assertion_loc)?;
 
-        let sign_alg = settings.sign_alg;
+        let signer_opt = create_signer_opt(settings)?;
 
-        let key_loc_opt = settings.sign_key_loc.as_ref();
-        let key_priv_opt = read_priv_key_opt(sign_alg, key_loc_opt)?;
+        if settings.jws_loc.is_some() && signer_opt.is_none() {
+            return Err(Error::InvalidSettings {
+                msg: "Writing a JWS requires signing, but no signing key is supplied".to_owned(),
+            }
+            .into());
+        }
 
-        let cert_loc_opt = settings.cert_loc.as_ref();
-        let x509_chain_opt = read_cert_chain_opt(cert_loc_opt)?;
+        let x509_chain_opt = read_cert_chain_opt(&settings.cert_locs, signer_opt.as_deref())?;
 
-        let (source_image_type, _baked_type) =
-            parse_image_types(source_image_loc.as_path(), baked_loc.as_path())?;
+        let image_type_opt = images
+            .map(|(source_image_loc, baked_loc)| {
+                parse_image_type(source_image_loc, baked_loc, settings.image_format)
+            })
+            .transpose()?;
 
-        let baking_content =
-            create_baking_content(assertion, sign_alg, key_priv_opt, x509_chain_opt)?;
+        let baking_content = create_baking_content(
+            &assertion,
+            signer_opt.as_deref(),
+            x509_chain_opt.clone(),
+            &settings.hosted_payload,
+            &settings.jws_header,
+        )?;
 
-        let fail_if_veri_present = true;
+        if let Some(jws_loc) = &settings.jws_loc {
+            log::info!(
+                "Writing the signed Open Badge Assertion to '{}' ...",
+                jws_loc.display()
+            );
+            let jws_header = jws_output_header(&settings.jws_header, settings.jws_serialization);
+            let jws = if jws_header == settings.jws_header {
+                baking_content.clone()
+            } else {
+                create_baking_content(
+                    &assertion,
+                    signer_opt.as_deref(),
+                    x509_chain_opt,
+                    &settings.hosted_payload,
+                    &jws_header,
+                )?
+            };
+            fs::write(
+                jws_loc,
+                signature::serialize(&jws, settings.jws_serialization)?,
+            )?;
+        }
 
-        // let patcher: Box<dyn patcher::Patcher> = match source_image_type {
-        //     ImageType::Svg => Box::new(patcher::svg::Patcher),
-        //     ImageType::Png => Box::new(patcher::png::Patcher),
-        // };
-        match source_image_type {
-            ImageType::Svg => {
-                patcher::svg::Patcher::rewrite(
-                    source_image_loc,
-                    baked_loc,
-                    &baking_content,
-                    fail_if_veri_present,
-                )?;
-            }
-            ImageType::Png => {
-                patcher::png::Patcher::rewrite(
-                    source_image_loc,
-                    baked_loc,
-                    &baking_content,
-                    fail_if_veri_present,
-                )?;
+        if let (Some((source_image_loc, baked_loc)), Some(source_image_type)) =
+            (images, image_type_opt)
+        {
+            log::info!("Baking Open Badge Assertion from {assertion_loc:#?} into image file {baked_loc:#?} now ...");
+
+            let fail_if_veri_present = true;
+
+            // A signed assertion is baked in compact serialization,
+            // as required by the baking specification
+            bake(
+                source_image_type,
+                source_image_loc,
+                baked_loc,
+                &baking_content,
+                fail_if_veri_present,
+                settings,
+            )?;
+
+            if settings.sidecars {
+                write_sidecars(baked_loc, &assertion, &baking_content)?;
             }
         }
 
@@ -367,13 +674,351 @@ pub fn run(settings: &Settings) -> BoxResult<()> {
     }
 }
 
+/// Extracts ("unbakes") the Open Badge content from a baked image.
+///
+/// The image type is figured out from the file content,
+/// unless `format` is given.
+///
+/// # Errors
+///
+/// If the image type is not supported.
+///
+/// If reading or parsing the image fails.
+pub fn extract(baked_loc: &Path, format: Option<ImageType>) -> BoxResult<Option<Payload>> {
+    log::info!(
+        "Extracting Open Badge content from image file '{}' ...",
+        baked_loc.display()
+    );
+
+    let baked_type = match format {
+        Some(format) => format,
+        None => ImageType::detect(baked_loc).map_err(|err| Error::InvalidSettings {
+            msg: format!("Invalid baked image: {err}"),
+        })?,
+    };
+
+    let content_opt = match baked_type {
+        ImageType::Svg => patcher::svg::Patcher::extract(baked_loc)?,
+        ImageType::Svgz => patcher::svg::CompressedPatcher::extract(baked_loc)?,
+        ImageType::Png => patcher::png::Patcher::extract(baked_loc)?,
+        ImageType::Jpeg => patcher::jpeg::Patcher::extract(baked_loc)?,
+        ImageType::Webp => patcher::webp::Patcher::extract(baked_loc)?,
+    };
+
+    Ok(content_opt.map(Payload::new))
+}
+
+/// Reads Open Badge content from a file,
+/// which is either a baked image,
+/// or contains the content directly
+/// (URL, JSON-LD Assertion or JWS).
+///
+/// Which of the two it is, is figured out from the file content.
+///
+/// # Errors
+///
+/// If reading or parsing the file fails.
+///
+/// If the image contains no Open Badge content.
+pub fn read_payload(input_loc: &Path) -> BoxResult<Payload> {
+    match ImageType::detect(input_loc) {
+        Ok(image_type) => extract(input_loc, Some(image_type))?.ok_or_else(|| {
+            format!(
+                "No Open Badge content found in image file '{}'",
+                input_loc.display()
+            )
+            .into()
+        }),
+        Err(ToImageTypeError::UnknownContent { .. }) => {
+            log::info!(
+                "Reading Open Badge content from non-image file '{}' ...",
+                input_loc.display()
+            );
+            Ok(Payload::new(std::fs::read_to_string(input_loc)?))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::util::test::is_good_error;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
 
     #[test]
     fn normal_types() {
         is_good_error::<Error>();
     }
+
+    #[test]
+    fn signer_from_settings() -> BoxResult<()> {
+        assert!(create_signer_opt(&Settings::default())?.is_none());
+        let settings = Settings {
+            sign_key_loc: Some(PathBuf::from(constants::ISSUER_KEY_PATH_PRIV)),
+            infer_sign_alg: true,
+            ..Settings::default()
+        };
+        let signer = create_signer_opt(&settings)?.ok_or("No signer created")?;
+        assert_eq!(signer.algorithm(), Algorithm::ES256);
+        assert_eq!(signer.key_id(), None);
+        let with_key_id = Settings {
+            sign_key_id: Some(constants::ISSUER_KEY_ID.to_owned()),
+            ..settings.clone()
+        };
+        let signer = create_signer_opt(&with_key_id)?.ok_or("No signer created")?;
+        assert_eq!(signer.key_id(), Some(constants::ISSUER_KEY_ID));
+
+        let tmp_dir = tempfile::tempdir()?;
+        let public_key_loc = tmp_dir.path().join("public-key.pem");
+        std::fs::write(&public_key_loc, signer.public_key_pem()?)?;
+        let command = Some(vec!["cat".to_owned()]);
+        let both = Settings {
+            sign_command: command.clone(),
+            ..settings
+        };
+        assert!(create_signer_opt(&both).is_err());
+        let settings = Settings {
+            sign_command: command,
+            sign_public_key_loc: Some(public_key_loc),
+            ..Settings::default()
+        };
+        // The algorithm has to be given explicitly
+        assert!(create_signer_opt(&settings).is_err());
+        let settings = Settings {
+            sign_alg: Algorithm::ES256,
+            ..settings
+        };
+        let signer = create_signer_opt(&settings)?.ok_or("No signer created")?;
+        assert_eq!(signer.algorithm(), Algorithm::ES256);
+        Ok(())
+    }
+
+    #[test]
+    fn hosted_payload() -> BoxResult<()> {
+        let assertion = read_assertion(Path::new(constants::BADGE_ASSERTION_SIMPLE_PATH))?;
+        let bake = |hosted_payload: &HostedPayload| {
+            create_baking_content(
+                &assertion,
+                None,
+                None,
+                hosted_payload,
+                &HeaderOptions::default(),
+            )
+        };
+        assert!(bake(&HostedPayload::Json)?.starts_with('{'));
+        assert_eq!(
+            bake(&HostedPayload::Id)?,
+            constants::BADGE_ASSERTION_SIMPLE_ID
+        );
+        let url = "https://example.org/assertion.json";
+        assert_eq!(bake(&HostedPayload::Url(url.to_owned()))?, url);
+        assert!(bake(&HostedPayload::Url("not a URL".to_owned())).is_err());
+        assert!(bake(&HostedPayload::Url("urn:uuid:1234".to_owned())).is_err());
+
+        let mut unhosted = assertion.clone();
+        unhosted.id = "urn:uuid:2c5ba9ca-a01e-4a0a-8e07-7d2ba5e0c84b".to_owned();
+        assert!(create_baking_content(
+            &unhosted,
+            None,
+            None,
+            &HostedPayload::Id,
+            &HeaderOptions::default(),
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn jws_output() -> BoxResult<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let jws_loc = tmp_dir.path().join("assertion.jws.json");
+        let settings = Settings {
+            assertion_loc: Some(PathBuf::from(constants::BADGE_ASSERTION_WITH_KEY_PATH)),
+            sign_key_loc: Some(PathBuf::from(constants::ISSUER_KEY_PATH_PRIV)),
+            infer_sign_alg: true,
+            jws_loc: Some(jws_loc.clone()),
+            jws_serialization: signature::Serialization::Flattened,
+            ..Settings::default()
+        };
+        run(&settings)?;
+        let jws = fs::read_to_string(&jws_loc)?;
+        assert!(signature::is_json_serialization(&jws));
+        let compact = signature::to_compact(&jws)?;
+        let assertion = signature::decode_unverified(&compact)?;
+        assert_eq!(assertion.id, constants::BADGE_ASSERTION_WITH_KEY_ID);
+        // The media type fits the JSON serialization
+        let header: serde_json::Value = serde_json::from_slice(
+            &BASE64_URL.decode(compact.split('.').next().unwrap_or_default())?,
+        )?;
+        assert_eq!(
+            header.get("typ"),
+            Some(&serde_json::json!(signature::MEDIA_TYPE_JSON))
+        );
+
+        // An explicitly set media type is kept
+        let explicit = Settings {
+            jws_header: HeaderOptions {
+                r#type: Some(signature::MEDIA_TYPE_COMPACT.to_owned()),
+                ..HeaderOptions::default()
+            },
+            ..settings
+        };
+        run(&explicit)?;
+        let compact = signature::to_compact(&fs::read_to_string(&jws_loc)?)?;
+        let header: serde_json::Value = serde_json::from_slice(
+            &BASE64_URL.decode(compact.split('.').next().unwrap_or_default())?,
+        )?;
+        assert_eq!(
+            header.get("typ"),
+            Some(&serde_json::json!(signature::MEDIA_TYPE_COMPACT))
+        );
+
+        let hosted = Settings {
+            assertion_loc: Some(PathBuf::from(constants::BADGE_ASSERTION_SIMPLE_PATH)),
+            jws_loc: Some(jws_loc),
+            ..Settings::default()
+        };
+        assert!(run(&hosted).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sidecars() -> BoxResult<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let baked_loc = tmp_dir.path().join("baked.svg");
+        let settings = Settings {
+            assertion_loc: Some(PathBuf::from(constants::BADGE_ASSERTION_WITH_KEY_PATH)),
+            sign_key_loc: Some(PathBuf::from(constants::ISSUER_KEY_PATH_PRIV)),
+            infer_sign_alg: true,
+            source_image_loc: Some(PathBuf::from("res/media/img/test.svg")),
+            baked_loc: Some(baked_loc.clone()),
+            sidecars: true,
+            ..Settings::default()
+        };
+        run(&settings)?;
+        let baked = extract(&baked_loc, None)?.ok_or("Nothing baked")?;
+        let jws = fs::read_to_string(sidecar_path(&baked_loc, SIDECAR_EXT_JWS))?;
+        assert_eq!(jws.trim(), baked.content.trim());
+        let json = fs::read_to_string(sidecar_path(&baked_loc, SIDECAR_EXT_JSON))?;
+        assert_eq!(
+            serde_json::from_str::<Assertion>(&json)?,
+            read_assertion(Path::new(constants::BADGE_ASSERTION_WITH_KEY_PATH))?
+        );
+        // Exactly what was signed
+        assert_eq!(
+            json.as_bytes(),
+            BASE64_URL.decode(jws.split('.').nth(1).unwrap_or_default())?
+        );
+        let digest = fs::read_to_string(sidecar_path(&baked_loc, SIDECAR_EXT_DIGEST))?;
+        assert_eq!(
+            digest,
+            format!("{}  baked.svg\n", hash::sha256_hex(&fs::read(&baked_loc)?))
+        );
+
+        // Hosted assertions have no JWS
+        let baked_loc = tmp_dir.path().join("hosted.svg");
+        let settings = Settings {
+            assertion_loc: Some(PathBuf::from(constants::BADGE_ASSERTION_SIMPLE_PATH)),
+            source_image_loc: Some(PathBuf::from("res/media/img/test.svg")),
+            baked_loc: Some(baked_loc.clone()),
+            sidecars: true,
+            ..Settings::default()
+        };
+        run(&settings)?;
+        assert!(!sidecar_path(&baked_loc, SIDECAR_EXT_JWS).exists());
+        assert!(sidecar_path(&baked_loc, SIDECAR_EXT_JSON).is_file());
+        assert!(sidecar_path(&baked_loc, SIDECAR_EXT_DIGEST).is_file());
+        Ok(())
+    }
+
+    #[test]
+    fn png_compressed() -> BoxResult<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let mut settings = Settings {
+            assertion_loc: Some(PathBuf::from(constants::BADGE_ASSERTION_WITH_KEY_PATH)),
+            sign_key_loc: Some(PathBuf::from(constants::ISSUER_KEY_PATH_PRIV)),
+            infer_sign_alg: true,
+            source_image_loc: Some(PathBuf::from("res/media/img/test.png")),
+            payload_warn_size: Some(DEFAULT_PAYLOAD_WARN_SIZE),
+            ..Settings::default()
+        };
+        let mut baked = Vec::new();
+        for png_compress in [false, true] {
+            let baked_loc = tmp_dir.path().join(format!("baked-{png_compress}.png"));
+            settings.baked_loc = Some(baked_loc.clone());
+            settings.png_compress = png_compress;
+            run(&settings)?;
+            let payload = extract(&baked_loc, None)?.ok_or("Nothing baked")?;
+            assert_eq!(payload.kind, PayloadKind::Jws);
+            baked.push((fs::metadata(&baked_loc)?.len(), payload));
+        }
+        let [(uncompressed_size, uncompressed), (compressed_size, compressed)] = baked.as_slice()
+        else {
+            return Err("Expected two baked images".into());
+        };
+        assert!(compressed_size < uncompressed_size);
+        assert_eq!(
+            signature::decode_unverified(&compressed.content)?,
+            signature::decode_unverified(&uncompressed.content)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn image_type_from_content() -> BoxResult<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        // A PNG with an SVG extension, and a baked one without extension
+        let source_loc = tmp_dir.path().join("source.svg");
+        let baked_loc = tmp_dir.path().join("baked");
+        fs::copy("res/media/img/test.png", &source_loc)?;
+        let settings = Settings {
+            assertion_loc: Some(PathBuf::from(constants::BADGE_ASSERTION_SIMPLE_PATH)),
+            hosted_payload: HostedPayload::Id,
+            source_image_loc: Some(source_loc),
+            baked_loc: Some(baked_loc.clone()),
+            ..Settings::default()
+        };
+        run(&settings)?;
+        assert_eq!(ImageType::detect(&baked_loc)?, ImageType::Png);
+        let payload = read_payload(&baked_loc)?;
+        assert_eq!(payload.kind, PayloadKind::Url);
+        assert_eq!(extract(&baked_loc, Some(ImageType::Png))?, Some(payload));
+        assert!(extract(&baked_loc, Some(ImageType::Jpeg)).is_err());
+
+        // The explicit format wins
+        let forced = Settings {
+            image_format: Some(ImageType::Svg),
+            ..settings
+        };
+        assert!(run(&forced).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn signed_creator_from_key_id() -> BoxResult<()> {
+        const KEY_ID: &str = "https://example.org/key-2.json";
+        let assertion = read_assertion(Path::new(constants::BADGE_ASSERTION_WITH_KEY_PATH))?;
+        let signer =
+            KeySigner::from_file(Algorithm::ES256, false, constants::ISSUER_KEY_PATH_PRIV)?
+                .with_key_id(Some(KEY_ID.to_owned()));
+        let jws = create_baking_content(
+            &assertion,
+            Some(&signer),
+            None,
+            &HostedPayload::Json,
+            &HeaderOptions::default(),
+        )?;
+        assert_eq!(
+            signature::decode_unverified(&jws)?.verification.r#type,
+            VerificationType::SignedBadge {
+                creator: Some(KEY_ID.to_owned())
+            }
+        );
+        assert_eq!(
+            signature::decode_key_id_unverified(&jws)?.as_deref(),
+            Some(KEY_ID)
+        );
+        Ok(())
+    }
 }
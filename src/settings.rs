@@ -2,12 +2,15 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
+use chrono::{DateTime, FixedOffset};
 use clap::ValueEnum;
 use std::{path::PathBuf, sync::LazyLock};
 use strum::IntoEnumIterator;
 use strum_macros::{EnumIter, EnumString, IntoStaticStr, VariantNames};
 
-use crate::signature::Algorithm;
+use crate::patcher::ImageType;
+use crate::signature::{Algorithm, HeaderOptions, Serialization};
+use crate::IdentityType;
 
 #[derive(
     Debug,
@@ -130,6 +133,18 @@ pub enum ShowRetrieved {
     All(Option<PathBuf>),
 }
 
+/// What to bake into the image for a hosted assertion.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum HostedPayload {
+    /// The (pretty-printed) JSON-LD of the assertion
+    #[default]
+    Json,
+    /// The IRI of the assertion (`Assertion.id`)
+    Id,
+    /// A custom URL, under which the assertion is hosted
+    Url(String),
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct Settings /*<S: ::std::hash::BuildHasher>*/ {
     // pub repo_path: Option<Box<Path>>,
@@ -148,17 +163,184 @@ pub struct Settings /*<S: ::std::hash::BuildHasher>*/ {
     pub verbosity: Verbosity,
     /// Location of the Open Badge Assertion JSON-LD to be baked.
     pub assertion_loc: Option<PathBuf>,
-    /// Location of the private key required for signing,
+    /// The algorithm used for signing,
     /// if signing is used.
     pub sign_alg: Algorithm,
+    /// Whether to infer the signing algorithm from the private key,
+    /// if `sign_alg` is not set.
+    pub infer_sign_alg: bool,
     /// Location of the private key required for signing,
     /// if signing is used.
     pub sign_key_loc: Option<PathBuf>,
-    /// Location of the certification chain,
+    /// The external program (followed by its arguments)
+    /// that creates the signature, if signing is used
+    /// without access to the private key;
+    /// see [`crate::signer::CommandSigner`].
+    pub sign_command: Option<Vec<String>>,
+    /// Location of the public key belonging to the signing key,
+    /// required when signing with `sign_command`.
+    pub sign_public_key_loc: Option<PathBuf>,
+    /// The IRI of the `CryptographicKey` belonging to the signing key;
+    /// see [`crate::signer::Signer::key_id`].
+    pub sign_key_id: Option<String>,
+    /// Locations of the x.509 certificate chain
+    /// (PEM bundles or DER certificates, in any order),
     /// optionally incorporated if signing is used.
-    pub cert_loc: Option<PathBuf>,
+    pub cert_locs: Vec<PathBuf>,
+    /// Options for the JOSE header, if signing is used.
+    pub jws_header: HeaderOptions,
+    /// What to bake into the image, if the assertion is hosted
+    /// (vs signed).
+    pub hosted_payload: HostedPayload,
     /// Location of the to be baked Open Badge image.
     pub source_image_loc: Option<PathBuf>,
+    /// The type of the source image (and thus of the baked one);
+    /// if not given, it is figured out from the file content.
+    pub image_format: Option<ImageType>,
     /// Location of the to be baked Open Badge image.
     pub baked_loc: Option<PathBuf>,
+    /// Location to write the signed assertion to, as a standalone JWS,
+    /// if signing is used.
+    pub jws_loc: Option<PathBuf>,
+    /// The serialization of the JWS written to `jws_loc`.
+    pub jws_serialization: Serialization,
+    /// Whether to write sidecar files next to each baked image;
+    /// see [`crate::process::write_sidecars`].
+    pub sidecars: bool,
+    /// Whether to zlib compress the content baked into PNG images
+    /// (as a compressed iTXt chunk).
+    pub png_compress: bool,
+    /// Baked content larger than this many bytes
+    /// causes a warning to be logged;
+    /// see [`crate::process::DEFAULT_PAYLOAD_WARN_SIZE`].
+    pub payload_warn_size: Option<usize>,
+}
+
+/// Settings specific to baking many badges at once,
+/// one per recipient.
+/// See [`crate::batch::run`].
+#[derive(Clone, Debug, Default)]
+pub struct BatchSettings {
+    /// The IRI of the `BadgeClass` to be awarded.
+    pub badge_id: String,
+    /// Location of the recipient list (CSV or JSON Lines).
+    pub recipients_loc: PathBuf,
+    /// The directory to write the baked images
+    /// (and hosted assertions) into.
+    pub out_dir: PathBuf,
+    /// The base IRI under which the assertions are hosted.
+    /// If not given, the assertion IDs are random `urn:uuid`s,
+    /// which is only possible for signed assertions.
+    pub id_base: Option<String>,
+}
+
+/// Which kind of verification a new assertion uses.
+#[derive(
+    Debug, Default, ValueEnum, EnumString, VariantNames, IntoStaticStr, Eq, PartialEq, Copy, Clone,
+)]
+pub enum VerificationKind {
+    /// The assertion is hosted under its `id`
+    #[default]
+    Hosted,
+    /// The assertion is signed (JWS)
+    Signed,
+}
+
+/// Whether and how the recipient identity of a new assertion is hashed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum IdentityHashing {
+    /// The identity is stored in plain text
+    Plain,
+    /// The identity is hashed without a salt
+    #[default]
+    Unsalted,
+    /// The identity is hashed with the given salt
+    Salted(String),
+}
+
+/// Settings for creating a new assertion.
+/// See [`crate::assertion_gen::create`].
+#[derive(Clone, Debug, Default)]
+pub struct NewAssertionSettings {
+    /// The IRI of the assertion;
+    /// if not given, a random `urn:uuid` is used,
+    /// which is only possible for signed assertions.
+    pub id: Option<String>,
+    /// The IRI of the `BadgeClass` to be awarded.
+    pub badge_id: String,
+    /// The recipient identity, in plain text.
+    pub recipient: String,
+    /// The type of `recipient`.
+    pub identity_type: IdentityType,
+    /// Whether and how to hash `recipient`.
+    pub hashing: IdentityHashing,
+    /// When the badge was awarded; defaults to now.
+    pub issued_on: Option<DateTime<FixedOffset>>,
+    /// When the badge expires, if ever.
+    pub expires: Option<DateTime<FixedOffset>>,
+    /// IRIs of evidence for the achievement.
+    pub evidence: Vec<String>,
+    /// A narrative connecting the evidence.
+    pub narrative: Option<String>,
+    /// Which kind of verification the assertion uses.
+    pub verification: VerificationKind,
+    /// The IRI of the `CryptographicKey` used for signing,
+    /// if `verification` is signed.
+    pub creator: Option<String>,
+}
+
+/// Settings for publishing the public key of an issuer.
+/// See [`crate::key_publish::run`].
+#[derive(Clone, Debug, Default)]
+pub struct PublishKeySettings {
+    /// Location of the private key or certificate
+    /// to take the public key from.
+    pub key_loc: PathBuf,
+    /// The IRI of the `CryptographicKey`.
+    pub key_id: String,
+    /// The IRI of the `Issuer` owning the key;
+    /// if not given, the `id` of the issuer in `issuer_loc`.
+    pub owner: Option<String>,
+    /// Location of an `Issuer` JSON-LD file
+    /// whose `publicKey` gets set to `key_id`.
+    pub issuer_loc: Option<PathBuf>,
+}
+
+/// What to create for the public part of a new key.
+#[derive(
+    Debug, Default, ValueEnum, EnumString, VariantNames, IntoStaticStr, Eq, PartialEq, Copy, Clone,
+)]
+pub enum CertKind {
+    /// Only the public key
+    #[default]
+    None,
+    /// A self-signed x.509 certificate
+    SelfSigned,
+    /// A certificate signing request (CSR), to be signed by a CA
+    Request,
+}
+
+/// Settings for generating a new key,
+/// and optionally a certificate (request) for it.
+/// See [`crate::cert_gen::create`].
+#[derive(Clone, Debug, Default)]
+pub struct KeygenSettings {
+    /// The signing algorithm the key is generated for.
+    pub algorithm: Algorithm,
+    /// The size of RSA keys, in bits.
+    pub rsa_bits: usize,
+    /// What to create for the public part of the key.
+    pub cert: CertKind,
+    /// The subject distinguished name of the certificate (request),
+    /// like "CN=Example,O=Example Org".
+    /// Defaults to the first subject alternative name as common name.
+    pub subject: Option<String>,
+    /// Subject alternative names (DNS names or IP addresses)
+    /// of the certificate (request).
+    pub subject_alt_names: Vec<String>,
+    /// For how many days from now a self-signed certificate is valid.
+    pub validity_days: u32,
+    /// The common beginning of the paths of all the files written;
+    /// see [`crate::cert_gen::Container::write_files`].
+    pub file_base: PathBuf,
 }
@@ -2,17 +2,23 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
 use biscuit::{
     jwa::SignatureAlgorithm,
     jws::{Compact, Header, RegisteredHeader, Secret},
 };
 use clap::ValueEnum;
+use ecdsa::signature::Verifier;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::pkcs8::DecodePublicKey;
 // use ring::signature::RsaKeyPair;
-use ring::signature::KeyPair;
+use rsa::pkcs1::EncodeRsaPublicKey;
+use rsa::traits::PublicKeyParts;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use strum_macros::{EnumString, IntoStaticStr, VariantNames};
 
-use crate::{box_err::BoxResult, process::Error, Assertion};
+use crate::{box_err::BoxResult, key::PrivateKey, process::Error, signer::Signer, Assertion};
 
 #[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
 pub enum AlgorithmType {
@@ -25,6 +31,21 @@ pub enum AlgorithmType {
     RSA,
     /// ECDSA using P-256 and SHA-256
     ECDSA,
+    /// Edwards-curve DSA using Ed25519
+    EdDSA,
+}
+
+impl AlgorithmType {
+    /// The JWK key type (`kty`) of the keys used with this type of algorithm.
+    #[must_use]
+    pub const fn jwk_key_type(self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::RSA => Some("RSA"),
+            Self::ECDSA => Some("EC"),
+            Self::EdDSA => Some("OKP"),
+        }
+    }
 }
 
 #[derive(
@@ -46,22 +67,52 @@ pub enum Algorithm {
     ES256,
     /// ECDSA using P-384 and SHA-384
     ES384,
+    /// ECDSA using secp256k1 and SHA-256
+    #[value(name = "es256k")]
+    ES256K,
+    /// Edwards-curve DSA using Ed25519
+    #[value(name = "eddsa")]
+    EdDSA,
     // /// ECDSA using P-521 and SHA-512 --
     // /// This variant is [unsupported](https://github.com/briansmith/ring/issues/268) and will probably never be.
     // ES512,
 }
 
 impl Algorithm {
+    /// Maps our algorithm to biscuits, if it supports it.
+    /// This is not the case for [`Self::ES256K`] and [`Self::EdDSA`],
+    /// which we handle ourselves.
     #[must_use]
-    pub const fn to_sig_alg(self) -> SignatureAlgorithm {
-        match self {
+    pub const fn to_sig_alg(self) -> Option<SignatureAlgorithm> {
+        Some(match self {
             Self::None => SignatureAlgorithm::None,
             Self::RS256 => SignatureAlgorithm::RS256,
             Self::RS384 => SignatureAlgorithm::RS384,
             Self::RS512 => SignatureAlgorithm::RS512,
             Self::ES256 => SignatureAlgorithm::ES256,
             Self::ES384 => SignatureAlgorithm::ES384,
-        }
+            Self::ES256K | Self::EdDSA => return None,
+        })
+    }
+
+    /// Maps biscuits algorithm to ours, if we support it.
+    #[must_use]
+    pub const fn from_sig_alg(sig_alg: SignatureAlgorithm) -> Option<Self> {
+        Some(match sig_alg {
+            SignatureAlgorithm::None => Self::None,
+            SignatureAlgorithm::RS256 => Self::RS256,
+            SignatureAlgorithm::RS384 => Self::RS384,
+            SignatureAlgorithm::RS512 => Self::RS512,
+            SignatureAlgorithm::ES256 => Self::ES256,
+            SignatureAlgorithm::ES384 => Self::ES384,
+            SignatureAlgorithm::HS256
+            | SignatureAlgorithm::HS384
+            | SignatureAlgorithm::HS512
+            | SignatureAlgorithm::ES512
+            | SignatureAlgorithm::PS256
+            | SignatureAlgorithm::PS384
+            | SignatureAlgorithm::PS512 => return None,
+        })
     }
 
     #[must_use]
@@ -69,81 +120,341 @@ impl Algorithm {
         match self {
             Self::None => AlgorithmType::None,
             Self::RS256 | Self::RS384 | Self::RS512 => AlgorithmType::RSA,
-            Self::ES256 | Self::ES384 => AlgorithmType::ECDSA,
+            Self::ES256 | Self::ES384 | Self::ES256K => AlgorithmType::ECDSA,
+            Self::EdDSA => AlgorithmType::EdDSA,
         }
     }
 }
 
-fn biscuit_to_process_err(err: &biscuit::errors::Error, key_file: &str) -> Error {
-    let hint = if let biscuit::errors::Error::KeyRejected(_key_rejected) = &err {
-        Some(
-            "
-Often, keys generated for use in OpenSSL-based software are
-encoded in PEM format, which is not supported by *ring*. PEM-encoded
-keys can be re-encoded into DER using an OpenSSL command like this:
-
-```sh
-# RSA
-openssl rsa -in private_key.pem -outform DER -out private_key.der
-# ECDSA
-openssl ec -in private_key.pem -outform DER -out private_key.der
-```
-",
+/// Chooses the signing algorithm to use with `key`.
+///
+/// If `alg` is given explicitly, it has to be compatible with the key.
+/// Otherwise, the algorithm is only inferred from the key
+/// if `infer` is set, as we do not want to choose it silently
+/// (for security reasons).
+///
+/// # Errors
+///
+/// If `alg` does not fit the key.
+///
+/// If `alg` is not given and `infer` is not set.
+pub fn select_algorithm(alg: Algorithm, infer: bool, key: &PrivateKey) -> Result<Algorithm, Error> {
+    if alg.r#type() == AlgorithmType::None {
+        if !infer {
+            return Err(Error::InvalidSettings {
+                msg: format!(
+                    "If you try to sign (indicated by suplying a private key), \
+                    you also have to specify the signing algorithm explicitly (for security reasons), \
+                    or explicitly allow to infer it from the key. \
+                    The supplied {} private key is compatible with: {}",
+                    key.name(),
+                    key.compatible_algorithms_str()
+                ),
+            });
+        }
+        let inferred = key.default_algorithm();
+        log::info!(
+            "Using signing algorithm {}, inferred from the {} private key.",
+            <&str>::from(inferred),
+            key.name()
+        );
+        return Ok(inferred);
+    }
+    if !key.supports(alg) {
+        return Err(Error::InvalidSettings {
+            msg: format!(
+                "The signing algorithm {} does not fit the supplied {} private key; \
+                use one of: {}",
+                <&str>::from(alg),
+                key.name(),
+                key.compatible_algorithms_str()
+            ),
+        });
+    }
+    Ok(alg)
+}
+
+/// Loads an RSA, ECDSA or Ed25519 public key from PEM encoded `SubjectPublicKeyInfo`,
+/// as found in [`crate::CryptographicKey::public_key_pem`].
+///
+/// # Errors
+///
+/// If the PEM is not a valid public key of a supported type.
+pub fn load_public_key_pem(public_key_pem: &str) -> BoxResult<(AlgorithmType, Secret)> {
+    let (label, der) = pem_rfc7468::decode_vec(public_key_pem.trim().as_bytes())
+        .map_err(|err| format!("Invalid public key PEM: {err}"))?;
+    if label != "PUBLIC KEY" {
+        return Err(format!("Expected a 'PUBLIC KEY' PEM, but found '{label}'").into());
+    }
+    load_public_key_der(&der)
+}
+
+/// Loads an RSA, ECDSA or Ed25519 public key from DER encoded `SubjectPublicKeyInfo`.
+///
+/// # Errors
+///
+/// If the DER is not a valid public key of a supported type.
+pub fn load_public_key_der(public_key_der: &[u8]) -> BoxResult<(AlgorithmType, Secret)> {
+    if let Ok(key) = rsa::RsaPublicKey::from_public_key_der(public_key_der) {
+        let der = key.to_pkcs1_der()?;
+        return Ok((
+            AlgorithmType::RSA,
+            Secret::PublicKey(der.as_bytes().to_owned()),
+        ));
+    }
+    if let Ok(key) = p256::PublicKey::from_public_key_der(public_key_der) {
+        let point = p256::EncodedPoint::from(key);
+        return Ok((
+            AlgorithmType::ECDSA,
+            Secret::PublicKey(point.as_bytes().to_owned()),
+        ));
+    }
+    if let Ok(key) = p384::PublicKey::from_public_key_der(public_key_der) {
+        let point = p384::EncodedPoint::from(key);
+        return Ok((
+            AlgorithmType::ECDSA,
+            Secret::PublicKey(point.as_bytes().to_owned()),
+        ));
+    }
+    if let Ok(key) = k256::PublicKey::from_public_key_der(public_key_der) {
+        let point = k256::EncodedPoint::from(key);
+        return Ok((
+            AlgorithmType::ECDSA,
+            Secret::PublicKey(point.as_bytes().to_owned()),
+        ));
+    }
+    if let Ok(key) = ed25519_dalek::VerifyingKey::from_public_key_der(public_key_der) {
+        return Ok((
+            AlgorithmType::EdDSA,
+            Secret::PublicKey(key.as_bytes().to_vec()),
+        ));
+    }
+    Err("Failed to decode a supported (RSA, P-256, P-384, secp256k1 or Ed25519) public key".into())
+}
+
+/// Converts an RSA, ECDSA or Ed25519 public key
+/// from DER encoded `SubjectPublicKeyInfo` to a JSON Web Key.
+///
+/// The JWK ([RFC 7517](https://datatracker.ietf.org/doc/html/rfc7517))
+/// contains only the key type and the public key parameters.
+///
+/// # Errors
+///
+/// If the DER is not a valid public key of a supported type.
+pub fn public_key_jwk(public_key_der: &[u8]) -> BoxResult<Map<String, Value>> {
+    fn b64(bytes: &[u8]) -> Value {
+        Value::String(BASE64_URL.encode(bytes))
+    }
+    fn ec_jwk(crv: &str, x: Option<&[u8]>, y: Option<&[u8]>) -> BoxResult<Map<String, Value>> {
+        let (Some(x), Some(y)) = (x, y) else {
+            return Err("Failed to decode the coordinates of the EC public key".into());
+        };
+        Ok(Map::from_iter([
+            ("kty".to_owned(), "EC".into()),
+            ("crv".to_owned(), crv.into()),
+            ("x".to_owned(), b64(x)),
+            ("y".to_owned(), b64(y)),
+        ]))
+    }
+
+    if let Ok(key) = rsa::RsaPublicKey::from_public_key_der(public_key_der) {
+        return Ok(Map::from_iter([
+            ("kty".to_owned(), "RSA".into()),
+            ("n".to_owned(), b64(&key.n().to_bytes_be())),
+            ("e".to_owned(), b64(&key.e().to_bytes_be())),
+        ]));
+    }
+    if let Ok(key) = p256::PublicKey::from_public_key_der(public_key_der) {
+        let point = key.to_encoded_point(false);
+        return ec_jwk(
+            "P-256",
+            point.x().map(AsRef::as_ref),
+            point.y().map(AsRef::as_ref),
+        );
+    }
+    if let Ok(key) = p384::PublicKey::from_public_key_der(public_key_der) {
+        let point = key.to_encoded_point(false);
+        return ec_jwk(
+            "P-384",
+            point.x().map(AsRef::as_ref),
+            point.y().map(AsRef::as_ref),
+        );
+    }
+    if let Ok(key) = k256::PublicKey::from_public_key_der(public_key_der) {
+        let point = key.to_encoded_point(false);
+        return ec_jwk(
+            "secp256k1",
+            point.x().map(AsRef::as_ref),
+            point.y().map(AsRef::as_ref),
+        );
+    }
+    if let Ok(key) = ed25519_dalek::VerifyingKey::from_public_key_der(public_key_der) {
+        return Ok(Map::from_iter([
+            ("kty".to_owned(), "OKP".into()),
+            ("crv".to_owned(), "Ed25519".into()),
+            ("x".to_owned(), b64(key.as_bytes())),
+        ]));
+    }
+    Err("Failed to decode a supported (RSA, P-256, P-384, secp256k1 or Ed25519) public key".into())
+}
+
+/// Decodes the assertion of a signed badge (a compact JWS),
+/// *without* verifying the signature.
+/// This is useful to figure out which key to verify it with.
+///
+/// # Errors
+///
+/// If the JWS is malformed.
+pub fn decode_unverified(jws: &str) -> BoxResult<Assertion> {
+    let encoded: Compact<Assertion, biscuit::Empty> = Compact::new_encoded(jws.trim());
+    Ok(encoded.unverified_payload()?)
+}
+
+/// Decodes the payload of a signed badge (a compact JWS)
+/// into exactly the bytes that were signed,
+/// *without* verifying the signature.
+///
+/// # Errors
+///
+/// If the JWS is malformed.
+pub fn decode_payload_unverified(jws: &str) -> BoxResult<Vec<u8>> {
+    let (_header, payload, _signature) = split_compact(jws)?;
+    Ok(BASE64_URL.decode(payload)?)
+}
+
+/// Decodes the `kid` (key ID) header of a signed badge (a compact JWS),
+/// *without* verifying the signature.
+/// Together with the `creator` of the assertion,
+/// this identifies the key to verify it with.
+///
+/// # Errors
+///
+/// If the JWS is malformed.
+pub fn decode_key_id_unverified(jws: &str) -> BoxResult<Option<String>> {
+    let parts = biscuit::Compact::decode(jws.trim());
+    let header: serde_json::Value = serde_json::from_slice(&parts.part::<Vec<u8>>(0)?)?;
+    Ok(header
+        .get("kid")
+        .and_then(serde_json::Value::as_str)
+        .map(ToOwned::to_owned))
+}
+
+/// Verifies the signature of a signed badge (a compact JWS),
+/// and returns the algorithm used and the signed assertion.
+///
+/// # Errors
+///
+/// If the JWS is malformed.
+///
+/// If the algorithm is unsupported or does not fit the key.
+///
+/// If the signature is invalid.
+pub fn verify(
+    jws: &str,
+    key_type: AlgorithmType,
+    public_key: &Secret,
+) -> BoxResult<(Algorithm, Assertion)> {
+    // We can not use biscuits header parsing here,
+    // as it fails on algorithms it does not know, like EdDSA and ES256K.
+    let parts = biscuit::Compact::decode(jws.trim());
+    let header: serde_json::Value = serde_json::from_slice(&parts.part::<Vec<u8>>(0)?)?;
+    let alg_name = header
+        .get("alg")
+        .and_then(serde_json::Value::as_str)
+        .ok_or("JWS header lacks the signing algorithm ('alg')")?;
+    let alg = alg_name
+        .parse::<Algorithm>()
+        .ok()
+        .filter(|alg| alg.r#type() != AlgorithmType::None)
+        .ok_or_else(|| format!("Unsupported JWS signing algorithm: {alg_name}"))?;
+    if alg.r#type() != key_type {
+        return Err(format!(
+            "JWS signing algorithm {alg:?} does not fit the public key type {key_type:?}"
         )
+        .into());
+    }
+    let assertion = if let Some(sig_alg) = alg.to_sig_alg() {
+        let encoded: Compact<Assertion, biscuit::Empty> = Compact::new_encoded(jws.trim());
+        let decoded = encoded.decode(public_key, sig_alg)?;
+        decoded.unwrap_decoded().1
     } else {
-        None
+        verify_natively(alg, &parts, public_key)?
     };
-    Error::InvalidSigningPrivateKey {
-        msg: format!(
-            "Failed to decode a valid crypto key from '{key_file}': {err:#?}{}",
-            hint.unwrap_or_default(),
-        ),
+    Ok((alg, assertion))
+}
+
+/// Verifies a compact JWS signed with an algorithm
+/// that biscuit does not support.
+fn verify_natively(
+    alg: Algorithm,
+    parts: &biscuit::Compact,
+    public_key: &Secret,
+) -> BoxResult<Assertion> {
+    if parts.len() != 3 {
+        return Err(format!("A compact JWS has 3 parts, but found {}", parts.len()).into());
     }
+    let message = signing_input(parts)?;
+    let signature = parts.part::<Vec<u8>>(2)?;
+    verify_signature(alg, message.as_bytes(), &signature, public_key)?;
+    Ok(parts.part(1)?)
 }
 
-/// Loads an RSA or ECDSA private key(-pair) file in DER format.
+/// Verifies the (JWS formatted) signature of `signing_input`.
 ///
 /// # Errors
 ///
-/// If loading from file failed; usually because of:
-/// - I/O Error
-/// - wrong key encoding
-/// - wrong key type
-/// - unsupported version of key type
-/// - corrupt file content
-pub fn load_private_key_pair(alg: Algorithm, key_file: impl AsRef<str>) -> BoxResult<Secret> {
-    match alg.r#type() {
-        AlgorithmType::None => Err("If you try to sign (indicated by suplying a private key), you also have to specify the signing algorithm explicitly (for security reasons)")?,
-        AlgorithmType::RSA => {
-            log::info!("Trying to read RSA private key from file {} ...", key_file.as_ref());
-            Secret::rsa_keypair_from_file(key_file.as_ref())
-                .map_err(|err| biscuit_to_process_err(&err, key_file.as_ref()).into())
+/// If the signature is invalid.
+///
+/// If the public key does not fit `alg`.
+pub fn verify_signature(
+    alg: Algorithm,
+    signing_input: &[u8],
+    signature: &[u8],
+    public_key: &Secret,
+) -> BoxResult<()> {
+    if let Some(sig_alg) = alg.to_sig_alg() {
+        sig_alg.verify(signature, signing_input, public_key)?;
+        return Ok(());
+    }
+    let Secret::PublicKey(public_key) = public_key else {
+        return Err("Given secret is not a public key!".into());
+    };
+    match alg {
+        Algorithm::EdDSA => {
+            let public_key =
+                ed25519_dalek::VerifyingKey::from_bytes(public_key.as_slice().try_into()?)?;
+            let signature = ed25519_dalek::Signature::from_slice(signature)?;
+            public_key.verify(signing_input, &signature)?;
+        }
+        Algorithm::ES256K => {
+            let public_key = k256::ecdsa::VerifyingKey::from_sec1_bytes(public_key)?;
+            let signature = k256::ecdsa::Signature::from_slice(signature)?;
+            // k256 only accepts the (equally valid) low-S form
+            let signature = signature.normalize_s().unwrap_or(signature);
+            public_key.verify(signing_input, &signature)?;
         }
-        AlgorithmType::ECDSA => {
-            log::info!("Trying to read ECDSA private key from file {} ...", key_file.as_ref());
-            Secret::ecdsa_keypair_from_file(alg.to_sig_alg(), key_file.as_ref())
-                .map_err(|err| biscuit_to_process_err(&err, key_file.as_ref()).into())
+        Algorithm::None
+        | Algorithm::RS256
+        | Algorithm::RS384
+        | Algorithm::RS512
+        | Algorithm::ES256
+        | Algorithm::ES384 => {
+            return Err(format!("Signing algorithm {alg:?} is handled by biscuit").into());
         }
     }
+    Ok(())
 }
 
-fn extract_public_key(alg: Algorithm, key_pair_priv: &Secret) -> BoxResult<Secret> {
-    Ok(match alg.r#type() {
-        AlgorithmType::None => todo!(),
-        AlgorithmType::RSA => {
-            if let Secret::RsaKeyPair(key_pair_priv_inner) = key_pair_priv {
-                Secret::PublicKey(key_pair_priv_inner.public_key().as_ref().to_owned())
-            } else {
-                return Err("Given secret is not an RSA private key(-pair)!")?;
-            }
-        }
-        AlgorithmType::ECDSA => {
-            if let Secret::EcdsaKeyPair(key_pair_priv_inner) = key_pair_priv {
-                Secret::PublicKey(key_pair_priv_inner.public_key().as_ref().to_owned())
-            } else {
-                return Err("Given secret is not an ECDSA private key(-pair)!")?;
-            }
-        }
+/// The signed part of a compact JWS,
+/// which is the (base64url encoded) header and payload,
+/// joined by a '.'.
+fn signing_input(parts: &biscuit::Compact) -> BoxResult<String> {
+    let encoded = parts.encode();
+    let mut dots = encoded.match_indices('.').map(|(idx, _)| idx);
+    Ok(match (dots.next(), dots.next()) {
+        (Some(_), Some(second)) => encoded.get(..second).unwrap_or_default().to_owned(),
+        (Some(_), None) => encoded,
+        _ => return Err("A JWS has to consist of at least header and payload".into()),
     })
 }
 
@@ -153,6 +464,70 @@ struct HeaderExtensions {
     key_type: Option<String>,
     #[serde(rename = "use", skip_serializing_if = "Option::is_none")]
     r#use: Option<String>,
+    /// We set this ourselves (instead of biscuits `web_key`),
+    /// as biscuit does not support all the key types we do.
+    #[serde(rename = "jwk", skip_serializing_if = "Option::is_none")]
+    web_key: Option<Map<String, Value>>,
+}
+
+/// The media type (`typ`) of a JWS in compact serialization;
+/// see <https://datatracker.ietf.org/doc/html/rfc7515#section-9.1>.
+pub const MEDIA_TYPE_COMPACT: &str = "JOSE";
+/// The media type (`typ`) of a JWS in JSON serialization;
+/// see <https://datatracker.ietf.org/doc/html/rfc7515#section-9.1>.
+pub const MEDIA_TYPE_JSON: &str = "JOSE+JSON";
+
+/// The ways to serialize a JWS;
+/// see <https://datatracker.ietf.org/doc/html/rfc7515#section-7>.
+#[derive(
+    Debug, ValueEnum, EnumString, VariantNames, IntoStaticStr, Eq, PartialEq, Clone, Copy, Default,
+)]
+pub enum Serialization {
+    /// The base64url encoded header, payload and signature, joined by '.';
+    /// the only serialization allowed in baked images.
+    #[default]
+    Compact,
+    /// A JSON object with a single signature
+    Flattened,
+    /// A JSON object with a list of signatures
+    General,
+}
+
+impl Serialization {
+    /// The media type (`typ`) of a JWS in this serialization.
+    #[must_use]
+    pub const fn media_type(self) -> &'static str {
+        match self {
+            Self::Compact => MEDIA_TYPE_COMPACT,
+            Self::Flattened | Self::General => MEDIA_TYPE_JSON,
+        }
+    }
+}
+
+/// Options for the JOSE header of signed badges,
+/// beyond what is derived from the signer.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeaderOptions {
+    /// The media type of the whole JWS (`typ`);
+    /// `None` for the default ([`MEDIA_TYPE_COMPACT`]),
+    /// and an empty string to omit the header
+    pub r#type: Option<String>,
+    /// The media type of the payload (`cty`)
+    pub content_type: Option<String>,
+    /// Whether to embed the public key as JWK (`jwk`)
+    pub embed_jwk: bool,
+}
+
+/// Creates the JWK of the public key of the signer,
+/// to be embedded into the JWS header.
+fn signer_jwk(signer: &dyn Signer) -> BoxResult<Map<String, Value>> {
+    let mut jwk = public_key_jwk(&signer.public_key_der()?)?;
+    jwk.insert("use".to_owned(), "sig".into());
+    jwk.insert("alg".to_owned(), <&str>::from(signer.algorithm()).into());
+    if let Some(key_id) = signer.key_id() {
+        jwk.insert("kid".to_owned(), key_id.into());
+    }
+    Ok(jwk)
 }
 
 /// Signs a badge.
@@ -161,64 +536,191 @@ struct HeaderExtensions {
 ///
 /// # Errors
 ///
-/// If computing the Message Authentication Code fails.
-///
-/// # Panics
+/// If encoding the header or the assertion fails.
 ///
-/// If the biscuit crate does something really wrong internally
-/// -> Practically, this can never happen.
+/// If the signer fails.
 pub fn sign_with_cert(
-    badge_assertion: Assertion,
-    alg: Algorithm,
-    secret_key: &Secret,
+    badge_assertion: &Assertion,
+    signer: &dyn Signer,
     x509_chain: Option<Vec<String>>,
+    header_opts: &HeaderOptions,
 ) -> BoxResult<String> {
+    let alg = signer.algorithm();
     let r#use = x509_chain.as_ref().map(|_| "sig".to_string());
     let header = RegisteredHeader {
-        algorithm: alg.to_sig_alg(),
+        // This gets replaced below,
+        // as biscuit does not know all the algorithms we support
+        algorithm: SignatureAlgorithm::None,
         // See: <https://datatracker.ietf.org/doc/html/rfc7515#section-4.1.9>
-        media_type: Some("JOSE+JSON".to_string()),
+        media_type: match header_opts.r#type.as_deref() {
+            None => Some(MEDIA_TYPE_COMPACT.to_owned()),
+            Some("") => None,
+            Some(r#type) => Some(r#type.to_owned()),
+        },
+        // See: <https://datatracker.ietf.org/doc/html/rfc7515#section-4.1.10>
+        content_type: header_opts.content_type.clone(),
+        key_id: signer.key_id().map(ToOwned::to_owned),
         x509_chain,
         ..Default::default()
     };
 
     let header_ext = HeaderExtensions {
-        key_type: Some("RSA".to_string()),
+        key_type: alg.r#type().jwk_key_type().map(ToOwned::to_owned),
         r#use,
+        web_key: if header_opts.embed_jwk {
+            Some(signer_jwk(signer)?)
+        } else {
+            None
+        },
     };
     let header = Header {
         registered: header,
         private: header_ext,
     };
+    let mut header = serde_json::to_value(header)?;
+    if let Some(fields) = header.as_object_mut() {
+        fields.insert("alg".to_owned(), <&str>::from(alg).into());
+    }
+
+    // We let biscuit do the encoding, but sign ourselves
+    let mut parts = biscuit::Compact::with_capacity(3);
+    parts.push(&serde_json::to_vec(&header)?)?;
+    parts.push(badge_assertion)?;
+    let signature = signer.sign(signing_input(&parts)?.as_bytes())?;
+    parts.push(&signature)?;
+    Ok(parts.encode())
+}
 
-    let compact = Compact::new_decoded(header, badge_assertion);
-    let encoded = compact.into_encoded(secret_key)?;
-    Ok(match encoded {
-        Compact::Decoded {
-            header: _,
-            payload: _,
-        } => panic!("This can never happen"),
-        Compact::Encoded(parts) => parts.encode(),
+/// Splits a compact JWS into its (base64url encoded)
+/// header, payload and signature.
+fn split_compact(jws: &str) -> BoxResult<(&str, &str, &str)> {
+    let mut parts = jws.trim().split('.');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(header), Some(payload), Some(signature), None) => Ok((header, payload, signature)),
+        _ => Err("A compact JWS has to consist of exactly 3 parts".into()),
+    }
+}
+
+/// Converts a JWS from compact serialization into the given one.
+/// JSON serializations are pretty-printed.
+///
+/// # Errors
+///
+/// If `compact_jws` is not a compact JWS.
+pub fn serialize(compact_jws: &str, serialization: Serialization) -> BoxResult<String> {
+    let (protected, payload, signature) = split_compact(compact_jws)?;
+    let json = match serialization {
+        Serialization::Compact => return Ok(compact_jws.trim().to_owned()),
+        Serialization::Flattened => serde_json::json!({
+            "payload": payload,
+            "protected": protected,
+            "signature": signature,
+        }),
+        Serialization::General => serde_json::json!({
+            "payload": payload,
+            "signatures": [{
+                "protected": protected,
+                "signature": signature,
+            }],
+        }),
+    };
+    Ok(serde_json::to_string_pretty(&json)?)
+}
+
+/// Whether `jws` looks like a JWS in (flattened or general) JSON serialization.
+#[must_use]
+pub fn is_json_serialization(jws: &str) -> bool {
+    serde_json::from_str::<Value>(jws).is_ok_and(|json| {
+        json.get("payload").is_some()
+            && (json.get("signature").is_some() || json.get("signatures").is_some())
     })
 }
 
-/// Signs a badge without a certificate.
+/// Converts a JWS in any serialization into the compact one.
+///
+/// Unprotected header parameters (`header`) are dropped,
+/// as the compact serialization can not represent them;
+/// they are not covered by the signature anyway.
+///
+/// # Errors
+///
+/// If the JSON is malformed, lacks a (protected) part,
+/// or contains more than one signature.
+pub fn to_compact(jws: &str) -> BoxResult<String> {
+    let jws = jws.trim();
+    if !jws.starts_with('{') {
+        return Ok(jws.to_owned());
+    }
+    let json: Value = serde_json::from_str(jws)?;
+    let str_field = |obj: &Value, name: &str| -> BoxResult<String> {
+        Ok(obj
+            .get(name)
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("The JWS JSON serialization lacks a (string) '{name}'"))?
+            .to_owned())
+    };
+    let payload = str_field(&json, "payload")?;
+    let signature_obj = match json.get("signatures") {
+        None => &json,
+        Some(Value::Array(signatures)) => match signatures.as_slice() {
+            [signature_obj] => signature_obj,
+            _ => {
+                return Err(format!(
+                    "Only JWS with exactly one signature are supported, but found {}",
+                    signatures.len()
+                )
+                .into())
+            }
+        },
+        Some(_) => return Err("The 'signatures' of the JWS are not an array".into()),
+    };
+    if signature_obj.get("header").is_some() {
+        log::debug!("Ignoring the unprotected JWS header.");
+    }
+    let protected = str_field(signature_obj, "protected")?;
+    let signature = str_field(signature_obj, "signature")?;
+    Ok(format!("{protected}.{payload}.{signature}"))
+}
+
+/// Signs a badge without a certificate,
+/// using the default [`HeaderOptions`].
 ///
 /// # Errors
 ///
-/// If computing the Message Authentication Code fails.
+/// If encoding the header or the assertion fails.
 ///
-/// # Panics
+/// If the signer fails.
+pub fn sign(badge_assertion: &Assertion, signer: &dyn Signer) -> BoxResult<String> {
+    sign_with_cert(badge_assertion, signer, None, &HeaderOptions::default())
+}
+
+/// Signs a badge without a certificate, in the given serialization,
+/// with the media type (`typ`) fitting it.
+///
+/// # Errors
 ///
-/// If the biscuit crate does something really wrong internally
-/// -> Practically, this can never happen.
-pub fn sign(badge_assertion: Assertion, alg: Algorithm, secret_key: &Secret) -> BoxResult<String> {
-    sign_with_cert(badge_assertion, alg, secret_key, None)
+/// If encoding the header or the assertion fails.
+///
+/// If the signer fails.
+pub fn sign_as(
+    badge_assertion: &Assertion,
+    signer: &dyn Signer,
+    serialization: Serialization,
+) -> BoxResult<String> {
+    let header_opts = HeaderOptions {
+        r#type: Some(serialization.media_type().to_owned()),
+        ..HeaderOptions::default()
+    };
+    serialize(
+        &sign_with_cert(badge_assertion, signer, None, &header_opts)?,
+        serialization,
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::signer::KeySigner;
     use crate::Assertion;
     use crate::Identity;
     use crate::IdentityType;
@@ -227,25 +729,20 @@ mod tests {
     use crate::{box_err::BoxResult, constants};
     use chrono::DateTime;
 
-    fn sign_and_verify(
-        badge_assertion: Assertion,
-        alg: Algorithm,
-        secret_key: &Secret,
-        // public_key: &Secret,
-    ) -> BoxResult<()> {
-        let encoded = sign(badge_assertion, alg, secret_key)?;
+    fn sign_and_verify(badge_assertion: &Assertion, signer: &dyn Signer) -> BoxResult<()> {
+        let encoded = sign(badge_assertion, signer)?;
         // fs::write("badge_assert_jws.txt", &encoded)?;
-        let encoded_parsed: Compact<Assertion, biscuit::Empty> = Compact::new_encoded(&encoded);
 
-        let public_key = extract_public_key(alg, secret_key)?;
+        let (key_type, public_key) = load_public_key_pem(&signer.public_key_pem()?)?;
         // Decodes and verifies the message
-        let _decoded = encoded_parsed.decode(&public_key, alg.to_sig_alg())?;
+        let (verified_alg, decoded) = verify(&encoded, key_type, &public_key)?;
+        assert_eq!(verified_alg, signer.algorithm());
+        assert_eq!(&decoded, badge_assertion);
 
         Ok(())
     }
 
-    #[test]
-    fn test_sign() -> BoxResult<()> {
+    fn signed_badge_assertion() -> BoxResult<Assertion> {
         let mut badge_assert = Assertion::new(
             constants::BADGE_ASSERTION_WITH_KEY_ID,
             constants::BADGE_DEFINITION_WITH_KEY_ID,
@@ -266,13 +763,244 @@ mod tests {
             DateTime::parse_from_rfc3339(constants::DT_PAST)?,
         );
         badge_assert.expires = Some(DateTime::parse_from_rfc3339(constants::DT_FAR_FUTURE)?.into());
+        Ok(badge_assert)
+    }
 
+    #[test]
+    fn test_sign() -> BoxResult<()> {
+        let badge_assert = signed_badge_assertion()?;
         let alg = Algorithm::ES256;
 
-        let key_pair_priv = load_private_key_pair(alg, constants::ISSUER_KEY_PATH_PRIV)?;
+        let signer = KeySigner::from_file(alg, false, constants::ISSUER_KEY_PATH_PRIV)?;
 
-        sign_and_verify(badge_assert, alg, &key_pair_priv)?;
+        sign_and_verify(&badge_assert, &signer)?;
+        assert_eq!(
+            decode_key_id_unverified(&sign(&badge_assert, &signer)?)?,
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_key_id() -> BoxResult<()> {
+        let badge_assert = signed_badge_assertion()?;
+        let signer =
+            KeySigner::from_file(Algorithm::ES256, false, constants::ISSUER_KEY_PATH_PRIV)?
+                .with_key_id(Some(constants::ISSUER_KEY_ID.to_owned()));
 
+        sign_and_verify(&badge_assert, &signer)?;
+        assert_eq!(
+            decode_key_id_unverified(&sign(&badge_assert, &signer)?)?.as_deref(),
+            Some(constants::ISSUER_KEY_ID)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_eddsa() -> BoxResult<()> {
+        let badge_assert = signed_badge_assertion()?;
+        let alg = Algorithm::EdDSA;
+
+        let signer = KeySigner::from_file(alg, false, "res/keys/ed25519.pkcs8.pem")?;
+
+        sign_and_verify(&badge_assert, &signer)?;
+
+        let encoded = sign(&badge_assert, &signer)?;
+        assert!(decode_unverified(&encoded).is_ok());
+        let (key_type, public_key) = load_public_key_pem(&signer.public_key_pem()?)?;
+        assert!(verify(&encoded, key_type, &public_key).is_ok());
+        // A tampered with signature is rejected
+        let (signing_input, signature) = encoded.rsplit_once('.').ok_or("Not a compact JWS")?;
+        let mut signature = BASE64_URL.decode(signature)?;
+        let first = signature.first_mut().ok_or("Empty signature")?;
+        *first ^= 0x01;
+        let tampered = format!("{signing_input}.{}", BASE64_URL.encode(&signature));
+        // ... while it is still well-formed
+        assert!(decode_unverified(&tampered).is_ok());
+        assert!(verify(&tampered, key_type, &public_key).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_es256k() -> BoxResult<()> {
+        let badge_assert = signed_badge_assertion()?;
+        let alg = Algorithm::ES256K;
+
+        let signer = KeySigner::from_file(alg, false, "res/keys/ec-secp256k1.sec1.pem")?;
+
+        sign_and_verify(&badge_assert, &signer)?;
+
+        let encoded = sign(&badge_assert, &signer)?;
+        let parts = biscuit::Compact::decode(&encoded);
+        assert_eq!(parts.part::<Vec<u8>>(2)?.len(), 64);
+        let (key_type, public_key) = load_public_key_pem(&signer.public_key_pem()?)?;
+        assert_eq!(verify(&encoded, key_type, &public_key)?.0, alg);
+        // A P-256 key does not fit
+        let key = PrivateKey::from_file(constants::ISSUER_KEY_PATH_PRIV)?;
+        let (key_type, public_key) = load_public_key_pem(&key.public_key_pem()?)?;
+        assert!(verify(&encoded, key_type, &public_key).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_with_cert() -> BoxResult<()> {
+        let signer = KeySigner::from_file(Algorithm::RS256, false, "res/keys/rsa-2048.pkcs1.pem")?;
+        let chain =
+            crate::cert_chain::read(&["res/keys/rsa-2048.chain.pem"], &signer.public_key_der()?)?;
+        let x5c = crate::cert_chain::to_x5c(&chain)?;
+        let assertion: Assertion = serde_json::from_str(&std::fs::read_to_string(
+            constants::BADGE_ASSERTION_WITH_KEY_PATH,
+        )?)?;
+        let encoded = sign_with_cert(
+            &assertion,
+            &signer,
+            Some(x5c.clone()),
+            &HeaderOptions::default(),
+        )?;
+        let encoded_parsed: Compact<Assertion, biscuit::Empty> = Compact::new_encoded(&encoded);
+        let header = encoded_parsed.unverified_header()?;
+        assert_eq!(header.registered.x509_chain, Some(x5c));
+        Ok(())
+    }
+
+    fn unverified_header(jws: &str) -> BoxResult<Value> {
+        let parts = biscuit::Compact::decode(jws);
+        Ok(serde_json::from_slice(&parts.part::<Vec<u8>>(0)?)?)
+    }
+
+    #[test]
+    fn test_header_default() -> BoxResult<()> {
+        let signer =
+            KeySigner::from_file(Algorithm::ES256, false, constants::ISSUER_KEY_PATH_PRIV)?;
+        let header = unverified_header(&sign(&signed_badge_assertion()?, &signer)?)?;
+        assert_eq!(
+            header,
+            serde_json::json!({ "alg": "ES256", "typ": "JOSE", "kty": "EC" })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_options() -> BoxResult<()> {
+        let badge_assert = signed_badge_assertion()?;
+        let header_opts = HeaderOptions {
+            r#type: Some(String::new()),
+            content_type: Some("application/json".to_owned()),
+            embed_jwk: true,
+        };
+        for (alg, key_file, kty, crv) in [
+            (Algorithm::RS256, "res/keys/rsa-2048.pkcs1.pem", "RSA", None),
+            (
+                Algorithm::ES256,
+                constants::ISSUER_KEY_PATH_PRIV,
+                "EC",
+                Some("P-256"),
+            ),
+            (
+                Algorithm::ES384,
+                "res/keys/ec-p384.sec1.pem",
+                "EC",
+                Some("P-384"),
+            ),
+            (
+                Algorithm::ES256K,
+                "res/keys/ec-secp256k1.sec1.pem",
+                "EC",
+                Some("secp256k1"),
+            ),
+            (
+                Algorithm::EdDSA,
+                "res/keys/ed25519.pkcs8.pem",
+                "OKP",
+                Some("Ed25519"),
+            ),
+        ] {
+            let signer = KeySigner::from_file(alg, false, key_file)?
+                .with_key_id(Some(constants::ISSUER_KEY_ID.to_owned()));
+            let encoded = sign_with_cert(&badge_assert, &signer, None, &header_opts)?;
+            let header = unverified_header(&encoded)?;
+            let header_str = |key| header.get(key).and_then(Value::as_str);
+            assert_eq!(header_str("alg"), Some(<&str>::from(alg)));
+            assert_eq!(header_str("kty"), Some(kty));
+            assert_eq!(header_str("kid"), Some(constants::ISSUER_KEY_ID));
+            assert_eq!(header_str("cty"), Some("application/json"));
+            assert_eq!(header.get("typ"), None);
+            let jwk = header.get("jwk").ok_or("No jwk")?;
+            let jwk_str = |key| jwk.get(key).and_then(Value::as_str);
+            assert_eq!(jwk_str("kty"), Some(kty));
+            assert_eq!(jwk_str("crv"), crv);
+            assert_eq!(jwk_str("alg"), Some(<&str>::from(alg)));
+            assert_eq!(jwk_str("kid"), Some(constants::ISSUER_KEY_ID));
+            // Strict parsers (like biscuits) accept the header
+            let (key_type, public_key) = load_public_key_pem(&signer.public_key_pem()?)?;
+            assert_eq!(verify(&encoded, key_type, &public_key)?.0, alg);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_public_key_jwk() -> BoxResult<()> {
+        let key = PrivateKey::from_file(constants::ISSUER_KEY_PATH_PRIV)?;
+        let jwk = public_key_jwk(&key.public_key_der()?)?;
+        let x = BASE64_URL.decode(jwk.get("x").and_then(Value::as_str).ok_or("No x")?)?;
+        let y = BASE64_URL.decode(jwk.get("y").and_then(Value::as_str).ok_or("No y")?)?;
+        assert_eq!((x.len(), y.len()), (32, 32));
+        assert!(public_key_jwk(b"not a key").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialization() -> BoxResult<()> {
+        let badge_assert = signed_badge_assertion()?;
+        let signer =
+            KeySigner::from_file(Algorithm::ES256, false, constants::ISSUER_KEY_PATH_PRIV)?;
+        let (key_type, public_key) = load_public_key_pem(&signer.public_key_pem()?)?;
+
+        let compact = sign_as(&badge_assert, &signer, Serialization::Compact)?;
+        assert!(!is_json_serialization(&compact));
+        assert_eq!(to_compact(&compact)?, compact);
+        for serialization in [Serialization::Flattened, Serialization::General] {
+            let json = sign_as(&badge_assert, &signer, serialization)?;
+            assert!(is_json_serialization(&json));
+            let parsed: Value = serde_json::from_str(&json)?;
+            assert_eq!(
+                parsed.get("signatures").is_some(),
+                serialization == Serialization::General
+            );
+            let compact = to_compact(&json)?;
+            assert_eq!(
+                unverified_header(&compact)?.get("typ"),
+                Some(&Value::from(MEDIA_TYPE_JSON))
+            );
+            assert_eq!(verify(&compact, key_type, &public_key)?.1, badge_assert);
+            assert_eq!(to_compact(&serialize(&compact, serialization)?)?, compact);
+        }
+        assert!(!is_json_serialization(&serde_json::to_string(
+            &badge_assert
+        )?));
+        assert!(to_compact(r#"{"payload": "e30", "signatures": []}"#).is_err());
+        assert!(serialize("a.b", Serialization::Flattened).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_algorithm() -> BoxResult<()> {
+        let key = PrivateKey::from_file(constants::ISSUER_KEY_PATH_PRIV)?;
+        assert!(select_algorithm(Algorithm::None, false, &key).is_err());
+        assert_eq!(
+            select_algorithm(Algorithm::None, true, &key)?,
+            Algorithm::ES256
+        );
+        assert_eq!(
+            select_algorithm(Algorithm::ES256, false, &key)?,
+            Algorithm::ES256
+        );
+        assert!(matches!(
+            select_algorithm(Algorithm::RS256, true, &key),
+            Err(Error::InvalidSettings { .. })
+        ));
         Ok(())
     }
 }
@@ -2,109 +2,314 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
-use std::fmt::Display;
+//! An alternative signing and verification backend,
+//! based on *jsonwebtoken* instead of *biscuit*,
+//! enabled with the `jsonwebtoken` cargo feature.
+//!
+//! It loads PEM encoded public keys natively;
+//! private keys are decoded as in [`PrivateKey::from_bytes`],
+//! as we need their public key and type anyway.
+//! It supports all our algorithms except [`Algorithm::ES256K`],
+//! for which the built-in backend ([`crate::signature`]) is used.
+//! The JOSE header and the serialization are shared with the built-in backend;
+//! see [`crate::signature::sign_with_cert`].
 
-use chrono::TimeZone;
-use jsonwebtoken::{Algorithm, Header, encode};
-use serde::Serialize;
+use std::path::Path;
 
-use crate::{box_err::BoxResult, open_badge::{BadgeAssertion, Type}};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use jsonwebtoken::{crypto, DecodingKey, EncodingKey};
+use rsa::pkcs1::EncodeRsaPrivateKey;
 
-// fn encode_decode() -> jws::Result<()> {
-//     // Add custom header parameters.
-//     let mut header = JsonObject::new();
-//     header.insert(String::from("typ"), JsonValue::from("text/plain"));
+use crate::box_err::BoxResult;
+use crate::key::PrivateKey;
+use crate::signature::{self, Algorithm, AlgorithmType};
+use crate::signer::{KeySigner, Signer};
+use crate::Assertion;
 
-//     // Encode and sign the message.
-//     let encoded = encode_sign(header, b"payload", &Hs512Signer::new(b"secretkey"))?;
+/// Maps our algorithm to the one of jsonwebtoken, if it supports it.
+const fn to_jwt_alg(alg: Algorithm) -> Option<jsonwebtoken::Algorithm> {
+    Some(match alg {
+        Algorithm::RS256 => jsonwebtoken::Algorithm::RS256,
+        Algorithm::RS384 => jsonwebtoken::Algorithm::RS384,
+        Algorithm::RS512 => jsonwebtoken::Algorithm::RS512,
+        Algorithm::ES256 => jsonwebtoken::Algorithm::ES256,
+        Algorithm::ES384 => jsonwebtoken::Algorithm::ES384,
+        Algorithm::EdDSA => jsonwebtoken::Algorithm::EdDSA,
+        Algorithm::None | Algorithm::ES256K => return None,
+    })
+}
 
-//     // Decode and verify the message.
-//     let decoded = decode_verify(encoded.data().as_bytes(), &HmacVerifier::new(b"secretkey"))?;
+/// Whether this backend supports signing and verifying with `alg`.
+#[must_use]
+pub const fn supports(alg: Algorithm) -> bool {
+    to_jwt_alg(alg).is_some()
+}
 
-//     assert_eq!(decoded.payload, b"payload");
-//     assert_eq!(
-//         decoded.header.get("typ").and_then(|x| x.as_str()),
-//         Some("text/plain")
-//     );
+fn unsupported(alg: Algorithm) -> String {
+    format!(
+        "The jsonwebtoken backend does not support the signing algorithm {}",
+        <&str>::from(alg)
+    )
+}
 
-//     Ok(())
-// }
+/// Creates the encoding key from an already decoded private key.
+fn encoding_key_from_key(key: &PrivateKey) -> BoxResult<EncodingKey> {
+    Ok(match key {
+        PrivateKey::Rsa(key) => EncodingKey::from_rsa_der(key.to_pkcs1_der()?.as_bytes()),
+        PrivateKey::P256(_) | PrivateKey::P384(_) => EncodingKey::from_ec_der(&key.to_pkcs8_der()?),
+        PrivateKey::Ed25519(_) => EncodingKey::from_ed_der(&key.to_pkcs8_der()?),
+        PrivateKey::K256(_) => return Err(unsupported(Algorithm::ES256K).into()),
+    })
+}
 
-/// Signs a badge.
-///
-/// # Errors
-///
-/// If computing the Message Authentication Code fails.
-pub fn sign<S: AsRef<str> + Display + Serialize>(
-    badge_assertion: &BadgeAssertion<S>,
-    secret_key: &[u8],
-) -> BoxResult<String> {
+/// Loads the decoding key natively from PEM encoded `SubjectPublicKeyInfo`.
+fn decoding_key_from_pem(alg: Algorithm, pem: &[u8]) -> BoxResult<DecodingKey> {
+    Ok(match alg.r#type() {
+        AlgorithmType::RSA => DecodingKey::from_rsa_pem(pem)?,
+        AlgorithmType::ECDSA => DecodingKey::from_ec_pem(pem)?,
+        AlgorithmType::EdDSA => DecodingKey::from_ed_pem(pem)?,
+        AlgorithmType::None => return Err(unsupported(alg).into()),
+    })
+}
+
+/// Signs with a private key that is available in memory,
+/// using jsonwebtoken.
+pub struct JwtSigner {
+    key: PrivateKey,
+    alg: Algorithm,
+    key_id: Option<String>,
+    encoding_key: EncodingKey,
+}
 
-    let secret_key_parsed = jsonwebtoken::EncodingKey::from_rsa_pem(secret_key)?;
+impl JwtSigner {
+    /// Creates a signer from a private key.
+    ///
+    /// # Errors
+    ///
+    /// If the key does not fit the algorithm,
+    /// or the algorithm is not supported by this backend.
+    pub fn new(key: PrivateKey, alg: Algorithm) -> BoxResult<Self> {
+        if !supports(alg) {
+            return Err(unsupported(alg).into());
+        }
+        if !key.supports(alg) {
+            return Err(format!(
+                "The signing algorithm {} does not fit the supplied {} private key",
+                <&str>::from(alg),
+                key.name()
+            )
+            .into());
+        }
+        let encoding_key = encoding_key_from_key(&key)?;
+        Ok(Self {
+            key,
+            alg,
+            key_id: None,
+            encoding_key,
+        })
+    }
 
-    let mut header = Header::new(Algorithm::RS256);
+    /// Sets the IRI of the `CryptographicKey` belonging to the key;
+    /// see [`Signer::key_id`].
+    #[must_use]
+    pub fn with_key_id(mut self, key_id: Option<String>) -> Self {
+        self.key_id = key_id;
+        self
+    }
 
-    // See: <https://datatracker.ietf.org/doc/html/rfc7515#section-4.1.9>
-    header.typ = Some("JOSE+JSON".to_string());
+    /// Reads the private key from a file,
+    /// see [`PrivateKey::from_file`],
+    /// and chooses the signing algorithm as in [`signature::select_algorithm`].
+    ///
+    /// # Errors
+    ///
+    /// If the file could not be read,
+    /// or does not contain a supported key.
+    ///
+    /// If no fitting signing algorithm could be chosen,
+    /// or it is not supported by this backend.
+    pub fn from_file<P: AsRef<Path>>(alg: Algorithm, infer: bool, key_file: P) -> BoxResult<Self> {
+        let key = PrivateKey::from_file(key_file)?;
+        let alg = signature::select_algorithm(alg, infer, &key)?;
+        Self::new(key, alg)
+    }
 
-    Ok(encode(&header, badge_assertion, &secret_key_parsed)?)
+    /// The private key we sign with.
+    #[must_use]
+    pub const fn key(&self) -> &PrivateKey {
+        &self.key
+    }
+}
+
+impl Signer for JwtSigner {
+    fn algorithm(&self) -> Algorithm {
+        self.alg
+    }
+
+    fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn public_key_der(&self) -> BoxResult<Vec<u8>> {
+        self.key.public_key_der()
+    }
+
+    fn sign(&self, signing_input: &[u8]) -> BoxResult<Vec<u8>> {
+        let jwt_alg = to_jwt_alg(self.alg).ok_or_else(|| unsupported(self.alg))?;
+        let signature = crypto::sign(signing_input, &self.encoding_key, jwt_alg)?;
+        Ok(BASE64_URL.decode(signature)?)
+    }
+}
+
+/// Creates a signer for a private key file,
+/// using this backend if it supports the signing algorithm,
+/// and the built-in one ([`KeySigner`]) otherwise.
+///
+/// # Errors
+///
+/// If the file could not be read,
+/// or does not contain a supported key.
+///
+/// If no fitting signing algorithm could be chosen.
+pub fn signer_from_file<P: AsRef<Path>>(
+    alg: Algorithm,
+    infer: bool,
+    key_file: P,
+    key_id: Option<String>,
+) -> BoxResult<Box<dyn Signer>> {
+    let key = PrivateKey::from_file(key_file)?;
+    let alg = signature::select_algorithm(alg, infer, &key)?;
+    Ok(if supports(alg) {
+        Box::new(JwtSigner::new(key, alg)?.with_key_id(key_id))
+    } else {
+        log::info!("{}; using the built-in backend instead.", unsupported(alg));
+        Box::new(KeySigner::new(key, alg)?.with_key_id(key_id))
+    })
+}
+
+/// Verifies the signature of a signed badge (a compact JWS),
+/// and returns the algorithm used and the signed assertion.
+///
+/// Like [`signature::verify`], but with the public key in PEM format,
+/// as found in [`crate::CryptographicKey::public_key_pem`].
+///
+/// JWS signed with an algorithm not supported by this backend
+/// are verified with the built-in one.
+///
+/// # Errors
+///
+/// If the JWS is malformed.
+///
+/// If the algorithm is unsupported or does not fit the key.
+///
+/// If the signature is invalid.
+pub fn verify(jws: &str, public_key_pem: &str) -> BoxResult<(Algorithm, Assertion)> {
+    let jws = jws.trim();
+    let mut parts = jws.split('.');
+    let (Some(header), Some(payload), Some(sig), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err("A compact JWS has to consist of exactly 3 parts".into());
+    };
+    let header: serde_json::Value = serde_json::from_slice(&BASE64_URL.decode(header)?)?;
+    let alg_name = header
+        .get("alg")
+        .and_then(serde_json::Value::as_str)
+        .ok_or("JWS header lacks the signing algorithm ('alg')")?;
+    let alg = alg_name
+        .parse::<Algorithm>()
+        .map_err(|_err| format!("Unsupported JWS signing algorithm: {alg_name}"))?;
+    let Some(jwt_alg) = to_jwt_alg(alg) else {
+        log::debug!("{}; using the built-in backend instead.", unsupported(alg));
+        let (key_type, public_key) = signature::load_public_key_pem(public_key_pem)?;
+        return signature::verify(jws, key_type, &public_key);
+    };
+    let decoding_key =
+        decoding_key_from_pem(alg, public_key_pem.trim().as_bytes()).map_err(|err| {
+            format!("The public key does not fit the JWS signing algorithm {alg_name}: {err}")
+        })?;
+    let signing_input = jws
+        .rsplit_once('.')
+        .map(|(signing_input, _sig)| signing_input)
+        .unwrap_or_default();
+    if !crypto::verify(sig, signing_input.as_bytes(), &decoding_key, jwt_alg)? {
+        return Err("Invalid JWS signature".into());
+    }
+    Ok((alg, serde_json::from_slice(&BASE64_URL.decode(payload)?)?))
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::constants;
     use std::fs;
 
-    use super::*;
-    use crate::{box_err::BoxResult, constants, hash, open_badge};
-    use chrono::DateTime;
-    use jsonwebtoken::{DecodingKey, Validation, decode};
-    use serde::de::DeserializeOwned;
-
-    fn sign_and_verify<S: AsRef<str> + Display + Serialize + DeserializeOwned + PartialEq>(
-        badge_assertion: &BadgeAssertion<S>,
-        secret_key: &[u8],
-        public_key: &[u8],
-    ) -> BoxResult<()> {
-        let encoded = sign(badge_assertion, secret_key)?;
-
-        // Decode and verify the message.
-        // let decoded = decode_verify(encoded.as_bytes(), &HmacVerifier::new(secret_key))?; // HACK -> Wrong! should work with public key! :*/
-        let decoded = decode::<BadgeAssertion<S>>(&encoded, &DecodingKey::from_rsa_pem(public_key)?, &Validation::new(Algorithm::RS256))?;
-
-        assert!(&decoded.claims.eq(badge_assertion));
-        // assert_eq!(
-        //     decoded.header.get("alg").and_then(|x| x.as_str()),
-        //     Some("HS512")
-        // );
+    fn assertion() -> BoxResult<Assertion> {
+        Ok(serde_json::from_str(&fs::read_to_string(
+            constants::BADGE_ASSERTION_WITH_KEY_PATH,
+        )?)?)
+    }
+
+    #[test]
+    fn sign_and_verify() -> BoxResult<()> {
+        let assertion = assertion()?;
+        for (alg, key_file) in [
+            // PKCS#1 PEM
+            (Algorithm::RS256, "res/keys/rsa-2048.pkcs1.pem"),
+            // PKCS#8 DER
+            (Algorithm::ES256, constants::ISSUER_KEY_PATH_PRIV),
+            // SEC1 PEM
+            (Algorithm::ES384, "res/keys/ec-p384.sec1.pem"),
+            // PKCS#8 PEM
+            (Algorithm::EdDSA, "res/keys/ed25519.pkcs8.pem"),
+        ] {
+            let signer = JwtSigner::from_file(alg, false, key_file)?;
+            let public_key_pem = signer.public_key_pem()?;
+            let jws = signature::sign(&assertion, &signer)?;
+            assert_eq!(verify(&jws, &public_key_pem)?, (alg, assertion.clone()));
+
+            // Interoperates with the built-in backend, both ways
+            let (key_type, public_key) = signature::load_public_key_pem(&public_key_pem)?;
+            assert_eq!(signature::verify(&jws, key_type, &public_key)?.0, alg);
+            let builtin = KeySigner::from_file(alg, false, key_file)?;
+            let jws = signature::sign(&assertion, &builtin)?;
+            assert_eq!(verify(&jws, &public_key_pem)?.0, alg);
 
+            // A tampered with payload is rejected
+            let mut tampered = assertion.clone();
+            tampered.narrative = Some("forged".to_owned());
+            let forged = signature::sign(&tampered, &signer)?;
+            let (forged_signing_input, _sig) = forged.rsplit_once('.').ok_or("No signature")?;
+            let (_signing_input, sig) = jws.rsplit_once('.').ok_or("No signature")?;
+            assert!(verify(&format!("{forged_signing_input}.{sig}"), &public_key_pem).is_err());
+        }
         Ok(())
     }
 
     #[test]
-    fn test_sign() -> BoxResult<()> {
-        let email_hash = hash::sha256(constants::BADGE_ASSERTION_RECIPIENT_EMAIL);
-        let badge_asser = open_badge::BadgeAssertion {
-            id: constants::BADGE_ASSERTION_WITH_KEY_ID.to_string(),
-            badge_id: constants::BADGE_DEFINITION_WITH_KEY_ID.to_string(),
-            recipient_salt: Some(constants::EMAIL_SALT.to_string()),
-            recipient_hashed_email: email_hash,
-            verification_public_key: Some(constants::KEY_ID.to_string()),
-            issued_on: DateTime::parse_from_rfc3339(constants::DT_PAST)?.into(),
-            expires: DateTime::parse_from_rfc3339(constants::DT_FAR_FUTURE)?.into(),
-        };
-        // let key_priv = fs::read_to_string(constants::ISSUER_KEY_PATH_PRIV)?;
-        // let key_pub = fs::read_to_string(constants::ISSUER_KEY_PATH_PUB)?;
-        let key_priv = fs::read_to_string("example.com.key")?;
-        let key_pub = fs::read_to_string("example.com.pem")?;
-        sign_and_verify(&badge_asser, key_priv.as_bytes(), key_pub.as_bytes())?;
-        // .map_err(|err| {
-        //     format!(
-        //         "Failed to sign because: {}: '{}'",
-        //         err.kind(),
-        //         err.message()
-        //     )
-        // })?;
+    fn unsupported_algorithm() -> BoxResult<()> {
+        let key_file = "res/keys/ec-secp256k1.sec1.pem";
+        assert!(JwtSigner::from_file(Algorithm::ES256K, false, key_file).is_err());
+        let signer = signer_from_file(Algorithm::ES256K, false, key_file, None)?;
+        let jws = signature::sign(&assertion()?, signer.as_ref())?;
+        // Falls back to the built-in backend
+        assert_eq!(
+            verify(&jws, &signer.public_key_pem()?)?.0,
+            Algorithm::ES256K
+        );
+        Ok(())
+    }
 
+    #[test]
+    fn wrong_key() -> BoxResult<()> {
+        let signer =
+            JwtSigner::from_file(Algorithm::ES256, false, constants::ISSUER_KEY_PATH_PRIV)?;
+        let jws = signature::sign(&assertion()?, &signer)?;
+        let other = PrivateKey::from_file("res/keys/ec-p384.sec1.pem")?;
+        assert!(verify(&jws, &other.public_key_pem()?).is_err());
+        let rsa = PrivateKey::from_file("res/keys/rsa-2048.pkcs1.pem")?;
+        assert!(verify(&jws, &rsa.public_key_pem()?).is_err());
+        assert!(JwtSigner::new(rsa, Algorithm::ES256).is_err());
         Ok(())
     }
-}
\ No newline at end of file
+}
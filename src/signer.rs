@@ -0,0 +1,484 @@
+// SPDX-FileCopyrightText: 2026 Robin Vobruba <hoijui.quaero@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Creation of JWS signatures,
+//! independent of where the private key lives:
+//! In memory ([`KeySigner`]),
+//! or behind an external program ([`CommandSigner`]),
+//! like a PKCS#11 wrapper or an SSH agent bridge.
+
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use biscuit::jws::Secret;
+use ecdsa::signature::Signer as _;
+use p256::pkcs8::{DecodePublicKey, LineEnding};
+
+use crate::box_err::BoxResult;
+use crate::key::{self, PrivateKey};
+use crate::process::Error;
+use crate::signature::{self, Algorithm};
+
+/// Creates the signature part of a JWS.
+pub trait Signer {
+    /// The algorithm the signatures are created with.
+    fn algorithm(&self) -> Algorithm;
+
+    /// The public key belonging to the signing key,
+    /// as DER encoded `SubjectPublicKeyInfo`.
+    ///
+    /// # Errors
+    ///
+    /// If encoding fails, which should never happen.
+    fn public_key_der(&self) -> BoxResult<Vec<u8>>;
+
+    /// Signs the JWS signing input,
+    /// which is the base64url encoded header and payload,
+    /// joined by a '.'.
+    /// Returns the signature bytes as required by the JWS spec
+    /// for [`Self::algorithm`].
+    ///
+    /// # Errors
+    ///
+    /// If signing fails.
+    fn sign(&self, signing_input: &[u8]) -> BoxResult<Vec<u8>>;
+
+    /// The IRI of the `CryptographicKey` belonging to the signing key, if known.
+    /// It is used as the JWS `kid` header,
+    /// and as the `creator` of the signed assertion,
+    /// which allows verifiers to select the right key
+    /// of an issuer with multiple keys.
+    fn key_id(&self) -> Option<&str> {
+        None
+    }
+
+    /// The public key belonging to the signing key,
+    /// as PEM encoded `SubjectPublicKeyInfo`,
+    /// as required for [`crate::CryptographicKey::public_key_pem`].
+    ///
+    /// # Errors
+    ///
+    /// If encoding fails, which should never happen.
+    fn public_key_pem(&self) -> BoxResult<String> {
+        let der = self.public_key_der()?;
+        Ok(
+            pem_rfc7468::encode_string("PUBLIC KEY", LineEnding::LF, &der)
+                .map_err(|err| format!("Failed to PEM encode the public key: {err}"))?,
+        )
+    }
+}
+
+/// Signs with a private key that is available in memory.
+pub struct KeySigner {
+    key: PrivateKey,
+    alg: Algorithm,
+    key_id: Option<String>,
+    /// The key as required by biscuit,
+    /// used for all the algorithms it supports
+    secret: Secret,
+}
+
+impl KeySigner {
+    /// Creates a signer from a private key.
+    ///
+    /// # Errors
+    ///
+    /// If the key does not fit the algorithm.
+    pub fn new(key: PrivateKey, alg: Algorithm) -> BoxResult<Self> {
+        let secret = key.to_secret(alg)?;
+        Ok(Self {
+            key,
+            alg,
+            key_id: None,
+            secret,
+        })
+    }
+
+    /// Sets the IRI of the `CryptographicKey` belonging to the key;
+    /// see [`Signer::key_id`].
+    #[must_use]
+    pub fn with_key_id(mut self, key_id: Option<String>) -> Self {
+        self.key_id = key_id;
+        self
+    }
+
+    /// Reads the private key from a file,
+    /// see [`PrivateKey::from_file`],
+    /// and chooses the signing algorithm as in [`signature::select_algorithm`].
+    ///
+    /// # Errors
+    ///
+    /// If the file could not be read,
+    /// or does not contain a supported key.
+    ///
+    /// If no fitting signing algorithm could be chosen.
+    pub fn from_file<P: AsRef<Path>>(alg: Algorithm, infer: bool, key_file: P) -> BoxResult<Self> {
+        log::info!(
+            "Trying to read private key from file '{}' ...",
+            key_file.as_ref().display()
+        );
+        let key = PrivateKey::from_file(key_file)?;
+        let alg = signature::select_algorithm(alg, infer, &key)?;
+        Self::new(key, alg)
+    }
+
+    /// The private key we sign with.
+    #[must_use]
+    pub const fn key(&self) -> &PrivateKey {
+        &self.key
+    }
+}
+
+impl Signer for KeySigner {
+    fn algorithm(&self) -> Algorithm {
+        self.alg
+    }
+
+    fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn public_key_der(&self) -> BoxResult<Vec<u8>> {
+        self.key.public_key_der()
+    }
+
+    fn sign(&self, signing_input: &[u8]) -> BoxResult<Vec<u8>> {
+        if let Some(sig_alg) = self.alg.to_sig_alg() {
+            return Ok(sig_alg.sign(signing_input, &self.secret)?);
+        }
+        // biscuit does not support these algorithms
+        Ok(match &self.key {
+            PrivateKey::Ed25519(key) => key.sign(signing_input).to_vec(),
+            PrivateKey::K256(key) => {
+                let signature: k256::ecdsa::Signature =
+                    k256::ecdsa::SigningKey::from(key).sign(signing_input);
+                signature.to_vec()
+            }
+            PrivateKey::Rsa(_) | PrivateKey::P256(_) | PrivateKey::P384(_) => {
+                return Err(Error::Impossible {
+                    msg: format!(
+                        "biscuit supports the {} private key, but not the signing algorithm {:?}",
+                        self.key.name(),
+                        self.alg
+                    ),
+                }
+                .into());
+            }
+        })
+    }
+}
+
+/// Signs by piping the JWS signing input
+/// to the standard input of an external program,
+/// which has to write the signature to its standard output.
+///
+/// For ECDSA, the signature may be either in the JWS format
+/// (the fixed size concatenation of R and S)
+/// or DER encoded, as is common for other tools (e.g. OpenSSL).
+/// The private key never enters this process;
+/// all we need is the public key.
+#[derive(Debug)]
+pub struct CommandSigner {
+    alg: Algorithm,
+    key_id: Option<String>,
+    public_key_der: Vec<u8>,
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandSigner {
+    /// Creates a signer that calls `program` with `args`.
+    ///
+    /// # Errors
+    ///
+    /// If `public_key_der` is not a supported public key,
+    /// or does not fit `alg`,
+    /// including the curve for ECDSA.
+    pub fn new(
+        alg: Algorithm,
+        public_key_der: Vec<u8>,
+        program: String,
+        args: Vec<String>,
+    ) -> BoxResult<Self> {
+        let (key_type, _public_key) = signature::load_public_key_der(&public_key_der)?;
+        if alg.r#type() != key_type
+            || key_type == signature::AlgorithmType::None
+            || !Self::fits_curve(alg, &public_key_der)
+        {
+            return Err(Error::InvalidSettings {
+                msg: format!(
+                    "The signing algorithm {} does not fit the supplied {key_type:?} public key",
+                    <&str>::from(alg)
+                ),
+            }
+            .into());
+        }
+        Ok(Self {
+            alg,
+            key_id: None,
+            public_key_der,
+            program,
+            args,
+        })
+    }
+
+    /// Sets the IRI of the `CryptographicKey` belonging to the key;
+    /// see [`Signer::key_id`].
+    #[must_use]
+    pub fn with_key_id(mut self, key_id: Option<String>) -> Self {
+        self.key_id = key_id;
+        self
+    }
+
+    /// Creates a signer that calls `command`,
+    /// which is the program followed by its arguments,
+    /// with the public key read from a PEM or DER file.
+    ///
+    /// # Errors
+    ///
+    /// If `command` is empty.
+    ///
+    /// If the public key could not be read,
+    /// is not supported or does not fit `alg`.
+    pub fn from_file<P: AsRef<Path>>(
+        alg: Algorithm,
+        public_key_file: P,
+        command: &[String],
+    ) -> BoxResult<Self> {
+        let public_key_file = public_key_file.as_ref();
+        let bytes = fs::read(public_key_file).map_err(|err| Error::InvalidSettings {
+            msg: format!(
+                "Failed to read public key '{}': {err}",
+                public_key_file.display()
+            ),
+        })?;
+        let public_key_der = if key::is_pem(&bytes) {
+            pem_rfc7468::decode_vec(bytes.trim_ascii_start())
+                .map_err(|err| Error::InvalidSettings {
+                    msg: format!(
+                        "Invalid PEM in public key '{}': {err}",
+                        public_key_file.display()
+                    ),
+                })?
+                .1
+        } else {
+            bytes
+        };
+        let Some((program, args)) = command.split_first() else {
+            return Err(Error::InvalidSettings {
+                msg: "The signing command is empty".to_owned(),
+            }
+            .into());
+        };
+        Self::new(alg, public_key_der, program.clone(), args.to_vec())
+    }
+
+    /// Whether the public key is on the curve required by `alg`,
+    /// which is always the case for algorithms other than ECDSA.
+    fn fits_curve(alg: Algorithm, public_key_der: &[u8]) -> bool {
+        match alg {
+            Algorithm::ES256 => p256::PublicKey::from_public_key_der(public_key_der).is_ok(),
+            Algorithm::ES384 => p384::PublicKey::from_public_key_der(public_key_der).is_ok(),
+            Algorithm::ES256K => k256::PublicKey::from_public_key_der(public_key_der).is_ok(),
+            Algorithm::None
+            | Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::EdDSA => true,
+        }
+    }
+
+    /// Converts a DER encoded ECDSA signature
+    /// into the format required by JWS;
+    /// anything else is returned as is.
+    fn to_jws_signature(&self, signature: Vec<u8>) -> BoxResult<Vec<u8>> {
+        Ok(match self.alg {
+            Algorithm::ES256 if signature.len() != 64 => {
+                p256::ecdsa::Signature::from_der(&signature)?.to_vec()
+            }
+            Algorithm::ES384 if signature.len() != 96 => {
+                p384::ecdsa::Signature::from_der(&signature)?.to_vec()
+            }
+            Algorithm::ES256K if signature.len() != 64 => {
+                k256::ecdsa::Signature::from_der(&signature)?.to_vec()
+            }
+            Algorithm::None
+            | Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::ES256
+            | Algorithm::ES384
+            | Algorithm::ES256K
+            | Algorithm::EdDSA => signature,
+        })
+    }
+}
+
+impl Signer for CommandSigner {
+    fn algorithm(&self) -> Algorithm {
+        self.alg
+    }
+
+    fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn public_key_der(&self) -> BoxResult<Vec<u8>> {
+        Ok(self.public_key_der.clone())
+    }
+
+    fn sign(&self, signing_input: &[u8]) -> BoxResult<Vec<u8>> {
+        log::debug!("Signing with external command '{}' ...", self.program);
+        let failed = |msg: String| Error::Signing {
+            msg: format!("External signing command '{}' {msg}", self.program),
+        };
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| failed(format!("could not be started: {err}")))?;
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| failed("has no standard input".to_owned()))?;
+        // The input is written from a separate thread,
+        // while we read the output,
+        // as both may exceed the pipe buffers.
+        let (written, output) = thread::scope(|scope| {
+            let writer = scope.spawn(move || stdin.write_all(signing_input));
+            let output = child.wait_with_output();
+            (writer.join(), output)
+        });
+        match written {
+            // The program may not need the input, and exit before reading it;
+            // whether it succeeded is told by its exit status.
+            Ok(Err(err)) if err.kind() != ErrorKind::BrokenPipe => {
+                return Err(failed(format!("did not accept the input: {err}")).into());
+            }
+            Err(_panic) => {
+                return Err(failed("could not be given the input".to_owned()).into());
+            }
+            Ok(Ok(()) | Err(_)) => {}
+        }
+        let output = output.map_err(|err| failed(format!("failed: {err}")))?;
+        if !output.status.success() {
+            return Err(failed(format!("failed with {}", output.status)).into());
+        }
+        if output.stdout.is_empty() {
+            return Err(failed("returned no signature".to_owned()).into());
+        }
+        let jws_signature = self.to_jws_signature(output.stdout)?;
+        let (_key_type, public_key) = signature::load_public_key_der(&self.public_key_der)?;
+        signature::verify_signature(self.alg, signing_input, &jws_signature, &public_key).map_err(
+            |err| {
+                failed(format!(
+                    "returned a signature that does not fit the public key: {err}"
+                ))
+            },
+        )?;
+        Ok(jws_signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants;
+
+    const KEY_P384_SEC1_PEM: &str = "res/keys/ec-p384.sec1.pem";
+    const KEY_SECP256K1_SEC1_PEM: &str = "res/keys/ec-secp256k1.sec1.pem";
+
+    #[test]
+    fn key_signer() -> BoxResult<()> {
+        let signer = KeySigner::from_file(Algorithm::None, true, KEY_P384_SEC1_PEM)?;
+        assert_eq!(signer.algorithm(), Algorithm::ES384);
+        assert_eq!(signer.key_id(), None);
+        assert_eq!(signer.sign(b"abc.def")?.len(), 96);
+        assert!(signer
+            .public_key_pem()?
+            .starts_with("-----BEGIN PUBLIC KEY-----\n"));
+        assert!(KeySigner::from_file(Algorithm::ES256, false, KEY_P384_SEC1_PEM).is_err());
+        let signer = signer.with_key_id(Some("https://example.org/key.json".to_owned()));
+        assert_eq!(signer.key_id(), Some("https://example.org/key.json"));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn command_signer() -> BoxResult<()> {
+        let key = KeySigner::from_file(Algorithm::ES256, false, constants::ISSUER_KEY_PATH_PRIV)?;
+        // `cat` "signs" by echoing the input,
+        // which is no valid signature
+        let signer = CommandSigner::new(
+            Algorithm::ES256,
+            key.public_key_der()?,
+            "cat".to_owned(),
+            vec![],
+        )?;
+        assert!(signer.sign(&[7_u8; 64]).is_err());
+        assert_eq!(signer.public_key_pem()?, key.public_key_pem()?);
+        // Big in- and output does not block
+        assert!(signer.sign(&vec![b'a'; 4 * 1024 * 1024]).is_err());
+
+        // A DER encoded signature gets converted
+        let jws_sig = key.sign(b"abc.def")?;
+        let der_sig = p256::ecdsa::Signature::from_slice(&jws_sig)?.to_der();
+        let sig_file = tempfile::NamedTempFile::new()?;
+        fs::write(sig_file.path(), der_sig.as_bytes())?;
+        let signer = CommandSigner::new(
+            Algorithm::ES256,
+            key.public_key_der()?,
+            "cat".to_owned(),
+            vec![sig_file.path().display().to_string()],
+        )?;
+        assert_eq!(signer.sign(b"abc.def")?, jws_sig);
+        // A signature of something else is rejected
+        assert!(signer.sign(b"abc.xyz").is_err());
+
+        let failing = CommandSigner::new(
+            Algorithm::ES256,
+            key.public_key_der()?,
+            "false".to_owned(),
+            vec![],
+        )?;
+        assert!(failing.sign(b"abc.def").is_err());
+        assert!(CommandSigner::new(
+            Algorithm::RS256,
+            key.public_key_der()?,
+            "cat".to_owned(),
+            vec![]
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn command_signer_curve() -> BoxResult<()> {
+        let p256 = PrivateKey::from_file(constants::ISSUER_KEY_PATH_PRIV)?.public_key_der()?;
+        let p384 = PrivateKey::from_file(KEY_P384_SEC1_PEM)?.public_key_der()?;
+        let k256 = PrivateKey::from_file(KEY_SECP256K1_SEC1_PEM)?.public_key_der()?;
+        let signer = |alg, public_key_der: &Vec<u8>| {
+            CommandSigner::new(alg, public_key_der.clone(), "cat".to_owned(), vec![])
+        };
+        assert!(signer(Algorithm::ES256, &p256).is_ok());
+        assert!(signer(Algorithm::ES384, &p384).is_ok());
+        assert!(signer(Algorithm::ES256K, &k256).is_ok());
+        for (alg, public_key_der) in [
+            (Algorithm::ES256, &p384),
+            (Algorithm::ES256, &k256),
+            (Algorithm::ES384, &p256),
+            (Algorithm::ES256K, &p256),
+        ] {
+            assert!(
+                signer(alg, public_key_der).is_err(),
+                "{alg:?} accepted a key on the wrong curve"
+            );
+        }
+        Ok(())
+    }
+}
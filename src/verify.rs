@@ -0,0 +1,723 @@
+// SPDX-FileCopyrightText: 2026 Robin Vobruba <hoijui.quaero@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Verification of Open Badges,
+//! following the [Open Badges 2.0 verification procedure](
+//! https://www.imsglobal.org/sites/default/files/Badges/OBv2p0Final/index.html#BadgeVerification).
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+use crate::patcher::{Payload, PayloadKind};
+use crate::signature::{self, Algorithm};
+use crate::{Assertion, BadgeClass, CryptographicKey, Issuer, VerificationType};
+
+/// This includes any reason for a badge to not be valid.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Failed to fetch '{iri}': {msg}")]
+    Fetch { iri: String, msg: String },
+
+    #[error("The document at '{iri}' is not a valid Open Badge 2.0 {type}: {msg}")]
+    InvalidDocument {
+        iri: String,
+        r#type: &'static str,
+        msg: String,
+    },
+
+    #[error("The content is not a verifiable Open Badge: {msg}")]
+    InvalidPayload { msg: String },
+
+    #[error("The signature of the badge is invalid: {msg}")]
+    InvalidSignature { msg: String },
+
+    #[error("The badge is not within the verification scope of its issuer: {msg}")]
+    OutOfScope { msg: String },
+
+    #[error("The badge expired at {expires}")]
+    Expired { expires: String },
+
+    #[error("The badge was revoked; reason: '{reason}'")]
+    Revoked { reason: String },
+}
+
+/// Resolves IRIs to the (JSON-LD) documents they identify.
+pub trait Fetcher {
+    /// Fetches the document identified by `iri`.
+    ///
+    /// # Errors
+    ///
+    /// If the document could not be retrieved.
+    fn fetch(&self, iri: &str) -> Result<String, Error>;
+}
+
+/// Fetches documents from the web, over HTTP(S).
+pub struct HttpFetcher;
+
+impl Fetcher for HttpFetcher {
+    fn fetch(&self, iri: &str) -> Result<String, Error> {
+        log::debug!("Fetching '{iri}' ...");
+        let fetch_err = |msg: String| Error::Fetch {
+            iri: iri.to_owned(),
+            msg,
+        };
+        ureq::get(iri)
+            .set("Accept", "application/ld+json, application/json")
+            .call()
+            .map_err(|err| fetch_err(err.to_string()))?
+            .into_string()
+            .map_err(|err| fetch_err(err.to_string()))
+    }
+}
+
+/// Fetches documents from a local directory,
+/// which mirrors the hosting structure under a base IRI.
+/// This allows to verify badges without network access,
+/// for example in tests.
+pub struct DirFetcher {
+    /// The IRI under which the documents are (supposedly) hosted
+    pub base_iri: String,
+    /// The local directory that mirrors `base_iri`
+    pub dir: PathBuf,
+}
+
+impl DirFetcher {
+    pub fn new<S: Into<String>, P: Into<PathBuf>>(base_iri: S, dir: P) -> Self {
+        Self {
+            base_iri: base_iri.into(),
+            dir: dir.into(),
+        }
+    }
+}
+
+impl Fetcher for DirFetcher {
+    fn fetch(&self, iri: &str) -> Result<String, Error> {
+        let fetch_err = |msg: String| Error::Fetch {
+            iri: iri.to_owned(),
+            msg,
+        };
+        let rel_path = iri
+            .strip_prefix(self.base_iri.trim_end_matches('/'))
+            .and_then(|rest| rest.strip_prefix('/'))
+            .map(Path::new)
+            .ok_or_else(|| fetch_err(format!("Not located under '{}'", self.base_iri)))?;
+        if !rel_path
+            .components()
+            .all(|comp| matches!(comp, Component::Normal(_)))
+        {
+            return Err(fetch_err(
+                "Only plain relative paths are allowed".to_owned(),
+            ));
+        }
+        let file = self.dir.join(rel_path);
+        log::debug!("Fetching '{iri}' from '{}' ...", file.display());
+        fs::read_to_string(&file).map_err(|err| fetch_err(err.to_string()))
+    }
+}
+
+/// The result of a successful verification.
+#[derive(Debug, Clone)]
+pub struct Verified {
+    /// The authoritative version of the assertion
+    pub assertion: Assertion,
+    pub badge: BadgeClass,
+    pub issuer: Issuer,
+    /// The algorithm the assertion was signed with,
+    /// if it is a signed badge.
+    pub sign_alg: Option<Algorithm>,
+    /// The key the assertion was signed with,
+    /// if it is a signed badge.
+    pub key: Option<CryptographicKey>,
+}
+
+fn fetch_obj<T: DeserializeOwned>(
+    fetcher: &dyn Fetcher,
+    iri: &str,
+    r#type: &'static str,
+) -> Result<T, Error> {
+    let content = fetcher.fetch(iri)?;
+    serde_json::from_str(&content).map_err(|err| Error::InvalidDocument {
+        iri: iri.to_owned(),
+        r#type,
+        msg: err.to_string(),
+    })
+}
+
+/// Splits an IRI into scheme and authority (host, and optionally port).
+fn origin(iri: &str) -> Option<(&str, &str)> {
+    let (scheme, rest) = iri.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    Some((scheme, authority))
+}
+
+/// Extracts the host registered name from an IRI.
+fn host(iri: &str) -> Option<&str> {
+    let (_scheme, authority) = origin(iri)?;
+    let host_port = authority.rsplit('@').next()?;
+    host_port.split(':').next()
+}
+
+/// Checks whether the `id` of a hosted assertion is
+/// within the scope its issuer allows for.
+fn check_scope(assertion: &Assertion, issuer: &Issuer) -> Result<(), Error> {
+    let id = assertion.id.as_str();
+    let policy = issuer
+        .verification
+        .as_ref()
+        .filter(|policy| policy.starts_with.is_some() || policy.allowed_origins.is_some());
+    let Some(policy) = policy else {
+        // Without an explicit policy,
+        // the assertion has to be hosted on the same origin as the issuer.
+        return if origin(id).is_some() && origin(id) == origin(&issuer.id) {
+            Ok(())
+        } else {
+            Err(Error::OutOfScope {
+                msg: format!(
+                    "'{id}' is not hosted on the same origin as the issuer '{}'",
+                    issuer.id
+                ),
+            })
+        };
+    };
+    if let Some(prefix) = &policy.starts_with {
+        if !id.starts_with(prefix.as_str()) {
+            return Err(Error::OutOfScope {
+                msg: format!("'{id}' does not start with '{prefix}'"),
+            });
+        }
+    }
+    if let Some(allowed_origin) = &policy.allowed_origins {
+        if host(id) != Some(allowed_origin.as_str()) {
+            return Err(Error::OutOfScope {
+                msg: format!("'{id}' is not hosted on '{allowed_origin}'"),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Checks whether `assertion_id` is listed in a revocation list document,
+/// and if so, returns the reason.
+fn find_revocation(revocation_list: &serde_json::Value, assertion_id: &str) -> Option<String> {
+    revocation_list
+        .get("revokedAssertions")?
+        .as_array()?
+        .iter()
+        .find_map(|entry| {
+            if entry.as_str() == Some(assertion_id) {
+                return Some(String::new());
+            }
+            let entry_obj = entry.as_object()?;
+            (entry_obj.get("id").and_then(serde_json::Value::as_str) == Some(assertion_id)).then(
+                || {
+                    entry_obj
+                        .get("revocationReason")
+                        .and_then(serde_json::Value::as_str)
+                        .unwrap_or_default()
+                        .to_owned()
+                },
+            )
+        })
+}
+
+/// Checks whether the assertion is (still) valid,
+/// regarding expiry and revocation.
+fn check_validity(
+    fetcher: &dyn Fetcher,
+    assertion: &Assertion,
+    issuer: &Issuer,
+) -> Result<(), Error> {
+    if assertion.revoked {
+        return Err(Error::Revoked {
+            reason: assertion.revocation_reason.clone().unwrap_or_default(),
+        });
+    }
+    if let Some(revocation_list_iri) = &issuer.revocation_list {
+        let revocation_list: serde_json::Value =
+            fetch_obj(fetcher, revocation_list_iri, "RevocationList")?;
+        if let Some(reason) = find_revocation(&revocation_list, &assertion.id) {
+            return Err(Error::Revoked { reason });
+        }
+    }
+    if let Some(expires) = &assertion.expires {
+        if expires.0 < Utc::now() {
+            return Err(Error::Expired {
+                expires: expires.0.to_rfc3339(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn fetch_badge_and_issuer(
+    fetcher: &dyn Fetcher,
+    assertion: &Assertion,
+) -> Result<(BadgeClass, Issuer), Error> {
+    let badge: BadgeClass = fetch_obj(fetcher, &assertion.badge, "BadgeClass")?;
+    let issuer: Issuer = fetch_obj(fetcher, &badge.issuer, "Issuer")?;
+    Ok((badge, issuer))
+}
+
+fn verify_hosted(fetcher: &dyn Fetcher, assertion_iri: &str) -> Result<Verified, Error> {
+    let assertion: Assertion = fetch_obj(fetcher, assertion_iri, "Assertion")?;
+    if assertion.id != assertion_iri {
+        return Err(Error::InvalidDocument {
+            iri: assertion_iri.to_owned(),
+            r#type: "Assertion",
+            msg: format!(
+                "Hosted under a different IRI than its id '{}'",
+                assertion.id
+            ),
+        });
+    }
+    if assertion.verification.r#type != VerificationType::HostedBadge {
+        return Err(Error::InvalidPayload {
+            msg: format!(
+                "Expected a hosted assertion, but its verification.type is {:?}",
+                assertion.verification.r#type
+            ),
+        });
+    }
+    let (badge, issuer) = fetch_badge_and_issuer(fetcher, &assertion)?;
+    check_scope(&assertion, &issuer)?;
+    check_validity(fetcher, &assertion, &issuer)?;
+    Ok(Verified {
+        assertion,
+        badge,
+        issuer,
+        sign_alg: None,
+        key: None,
+    })
+}
+
+fn verify_signed(fetcher: &dyn Fetcher, jws: &str) -> Result<Verified, Error> {
+    let sig_err = |err: crate::box_err::BoxError| Error::InvalidSignature {
+        msg: err.to_string(),
+    };
+    let unverified = signature::decode_unverified(jws).map_err(sig_err)?;
+    let VerificationType::SignedBadge { creator } = &unverified.verification.r#type else {
+        return Err(Error::InvalidPayload {
+            msg: format!(
+                "Expected a signed assertion, but its verification.type is {:?}",
+                unverified.verification.r#type
+            ),
+        });
+    };
+    let (badge, issuer) = fetch_badge_and_issuer(fetcher, &unverified)?;
+    let kid = signature::decode_key_id_unverified(jws).map_err(sig_err)?;
+    let key_iris = select_keys(creator.as_deref(), kid.as_deref(), &issuer)?;
+    let (key, sign_alg, assertion) = if let [key_iri] = key_iris.as_slice() {
+        verify_with_key(fetcher, jws, key_iri, &issuer)?
+    } else {
+        key_iris
+            .iter()
+            .find_map(|key_iri| {
+                verify_with_key(fetcher, jws, key_iri, &issuer)
+                    .inspect_err(|err| {
+                        log::debug!("Not verifiable with the key '{key_iri}': {err}");
+                    })
+                    .ok()
+            })
+            .ok_or_else(|| Error::InvalidSignature {
+                msg: format!(
+                    "Not signed with any of the keys of the issuer '{}': {}",
+                    issuer.id,
+                    key_iris.join(", ")
+                ),
+            })?
+    };
+    check_validity(fetcher, &assertion, &issuer)?;
+    Ok(Verified {
+        assertion,
+        badge,
+        issuer,
+        sign_alg: Some(sign_alg),
+        key: Some(key),
+    })
+}
+
+/// Selects the IRIs of the keys of the issuer
+/// to try verifying a signed assertion with.
+/// The key is identified by the `creator` of the assertion,
+/// or the `kid` header of the JWS.
+/// If neither is given, all the keys of the issuer are candidates,
+/// so badges signed before a key rotation stay verifiable.
+fn select_keys<'a>(
+    creator: Option<&'a str>,
+    kid: Option<&'a str>,
+    issuer: &'a Issuer,
+) -> Result<Vec<&'a str>, Error> {
+    let key_iri = match (creator, kid) {
+        (Some(creator), Some(kid)) if creator != kid => {
+            return Err(Error::InvalidSignature {
+                msg: format!(
+                    "The creator of the assertion '{creator}' \
+                    and the key ID of the signature '{kid}' differ"
+                ),
+            });
+        }
+        (Some(key_iri), _) | (None, Some(key_iri)) => key_iri,
+        (None, None) => {
+            if issuer.public_key.is_empty() {
+                return Err(Error::InvalidSignature {
+                    msg: "Neither the assertion (creator), nor the signature (kid), \
+                        nor the issuer (publicKey) reference a key"
+                        .to_owned(),
+                });
+            }
+            return Ok(issuer.public_key.iter().map(String::as_str).collect());
+        }
+    };
+    if !issuer
+        .public_key
+        .iter()
+        .any(|issuer_key| issuer_key == key_iri)
+    {
+        return Err(Error::InvalidSignature {
+            msg: format!(
+                "The key '{key_iri}' is not authorized by the issuer '{}'",
+                issuer.id
+            ),
+        });
+    }
+    Ok(vec![key_iri])
+}
+
+/// Verifies a signed assertion with one specific key of the issuer.
+fn verify_with_key(
+    fetcher: &dyn Fetcher,
+    jws: &str,
+    key_iri: &str,
+    issuer: &Issuer,
+) -> Result<(CryptographicKey, Algorithm, Assertion), Error> {
+    let sig_err = |err: crate::box_err::BoxError| Error::InvalidSignature {
+        msg: err.to_string(),
+    };
+    let key: CryptographicKey = fetch_obj(fetcher, key_iri, "CryptographicKey")?;
+    if key.owner != issuer.id {
+        return Err(Error::InvalidSignature {
+            msg: format!(
+                "The key '{key_iri}' is owned by '{}', not the issuer '{}'",
+                key.owner, issuer.id
+            ),
+        });
+    }
+    let (sign_alg, assertion) = verify_signature(jws, &key.public_key_pem).map_err(sig_err)?;
+    Ok((key, sign_alg, assertion))
+}
+
+/// Verifies the signature of a compact JWS
+/// with the built-in signing backend.
+#[cfg(not(feature = "jsonwebtoken"))]
+fn verify_signature(
+    jws: &str,
+    public_key_pem: &str,
+) -> crate::box_err::BoxResult<(Algorithm, Assertion)> {
+    let (key_type, public_key) = signature::load_public_key_pem(public_key_pem)?;
+    signature::verify(jws, key_type, &public_key)
+}
+
+/// Verifies the signature of a compact JWS
+/// with the jsonwebtoken signing backend.
+#[cfg(feature = "jsonwebtoken")]
+fn verify_signature(
+    jws: &str,
+    public_key_pem: &str,
+) -> crate::box_err::BoxResult<(Algorithm, Assertion)> {
+    crate::signature_jsonwebtoken::verify(jws, public_key_pem)
+}
+
+/// Verifies a badge, as baked into an image (or stored elsewhere).
+///
+/// # Errors
+///
+/// If any of the verification checks fail.
+pub fn verify(payload: &Payload, fetcher: &dyn Fetcher) -> Result<Verified, Error> {
+    match payload.kind {
+        PayloadKind::Url => verify_hosted(fetcher, payload.content.trim()),
+        PayloadKind::Assertion => {
+            let assertion: Assertion =
+                serde_json::from_str(&payload.content).map_err(|err| Error::InvalidPayload {
+                    msg: err.to_string(),
+                })?;
+            if let VerificationType::SignedBadge { .. } = assertion.verification.r#type {
+                return Err(Error::InvalidPayload {
+                    msg: "A signed assertion has to be supplied as JWS, not as plain JSON"
+                        .to_owned(),
+                });
+            }
+            // The hosted version is authoritative
+            let verified = verify_hosted(fetcher, &assertion.id)?;
+            if verified.assertion != assertion {
+                log::warn!(
+                    "The supplied assertion differs from the hosted one; using the hosted version."
+                );
+            }
+            Ok(verified)
+        }
+        PayloadKind::Jws => {
+            let jws =
+                signature::to_compact(&payload.content).map_err(|err| Error::InvalidPayload {
+                    msg: err.to_string(),
+                })?;
+            verify_signed(fetcher, &jws)
+        }
+        PayloadKind::Unknown => Err(Error::InvalidPayload {
+            msg: "Neither a URL, nor a JSON assertion, nor a JWS".to_owned(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::box_err::BoxResult;
+    use crate::signature::Serialization;
+    use crate::signer::KeySigner;
+    use crate::util::test::is_good_error;
+    use crate::{constants, ToJsonLd, Verification};
+    use chrono::DateTime;
+    use const_format::formatcp;
+    use std::fs;
+
+    fn fetcher() -> DirFetcher {
+        DirFetcher::new(constants::BASE_ENTS_URL, constants::BASE_HOSTING_PATH)
+    }
+
+    fn signed_payload(assertion: &Assertion) -> BoxResult<Payload> {
+        let alg = Algorithm::ES256;
+        let signer = KeySigner::from_file(alg, false, constants::ISSUER_KEY_PATH_PRIV)?;
+        Ok(Payload::new(signature::sign(assertion, &signer)?))
+    }
+
+    fn signed_assertion() -> BoxResult<Assertion> {
+        Ok(serde_json::from_str(&fs::read_to_string(
+            constants::BADGE_ASSERTION_WITH_KEY_PATH,
+        )?)?)
+    }
+
+    #[test]
+    fn normal_types() {
+        is_good_error::<Error>();
+    }
+
+    #[test]
+    fn dir_fetcher() {
+        let fetcher = fetcher();
+        assert!(fetcher.fetch(constants::ISSUER_SIMPLE_ID).is_ok());
+        assert!(fetcher.fetch("https://example.org/issuer.json").is_err());
+        assert!(fetcher
+            .fetch(&format!("{}/../../Cargo.toml", constants::BASE_ENTS_URL))
+            .is_err());
+    }
+
+    #[test]
+    fn hosted_url() -> Result<(), Error> {
+        let payload = Payload::new(constants::BADGE_ASSERTION_SIMPLE_ID.to_owned());
+        let verified = verify(&payload, &fetcher())?;
+        assert_eq!(verified.assertion.id, constants::BADGE_ASSERTION_SIMPLE_ID);
+        assert_eq!(verified.issuer.id, constants::ISSUER_SIMPLE_ID);
+        assert_eq!(verified.sign_alg, None);
+        Ok(())
+    }
+
+    #[test]
+    fn hosted_json() -> BoxResult<()> {
+        let payload = Payload::new(fs::read_to_string(constants::BADGE_ASSERTION_SIMPLE_PATH)?);
+        let verified = verify(&payload, &fetcher())?;
+        assert_eq!(verified.assertion.id, constants::BADGE_ASSERTION_SIMPLE_ID);
+        Ok(())
+    }
+
+    #[test]
+    fn signed() -> BoxResult<()> {
+        let payload = signed_payload(&signed_assertion()?)?;
+        let verified = verify(&payload, &fetcher())?;
+        assert_eq!(verified.assertion, signed_assertion()?);
+        assert_eq!(verified.sign_alg, Some(Algorithm::ES256));
+        Ok(())
+    }
+
+    #[test]
+    fn signed_json_serialization() -> BoxResult<()> {
+        let compact = signed_payload(&signed_assertion()?)?.content;
+        for serialization in [Serialization::Flattened, Serialization::General] {
+            let payload = Payload::new(signature::serialize(&compact, serialization)?);
+            assert_eq!(payload.kind, PayloadKind::Jws);
+            let verified = verify(&payload, &fetcher())?;
+            assert_eq!(verified.assertion, signed_assertion()?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn signed_tampered() -> BoxResult<()> {
+        let payload = signed_payload(&signed_assertion()?)?;
+        let mut forged = signed_assertion()?;
+        forged.narrative = Some("forged".to_owned());
+        let forged_payload = signed_payload(&forged)?;
+        // Combines the forged payload with the original signature
+        let mut parts: Vec<_> = forged_payload.content.split('.').collect();
+        let original_sig = payload.content.rsplit('.').next().unwrap_or_default();
+        parts.pop();
+        parts.push(original_sig);
+        let tampered = Payload::new(parts.join("."));
+        assert!(matches!(
+            verify(&tampered, &fetcher()),
+            Err(Error::InvalidSignature { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn signed_expired() -> BoxResult<()> {
+        let mut assertion = signed_assertion()?;
+        assertion.expires = Some(DateTime::parse_from_rfc3339(constants::DT_PAST)?.into());
+        let payload = signed_payload(&assertion)?;
+        assert!(matches!(
+            verify(&payload, &fetcher()),
+            Err(Error::Expired { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn signed_revoked() -> BoxResult<()> {
+        let mut assertion = signed_assertion()?;
+        assertion.revoked = true;
+        let payload = signed_payload(&assertion)?;
+        assert!(matches!(
+            verify(&payload, &fetcher()),
+            Err(Error::Revoked { .. })
+        ));
+        Ok(())
+    }
+
+    const ROTATED_KEY_ID: &str = formatcp!("{}/issuer-key-2.json", constants::BASE_ENTS_URL);
+    const ROTATED_KEY_PATH: &str = "res/keys/ec-p384.sec1.pem";
+
+    /// Hosts the issuer with an additional, newer key,
+    /// as after a key rotation.
+    fn rotated_fetcher(dir: &Path) -> BoxResult<DirFetcher> {
+        for file in ["badge-definition-with-key.json", "issuer-key.json"] {
+            fs::copy(
+                Path::new(constants::BASE_HOSTING_PATH).join(file),
+                dir.join(file),
+            )?;
+        }
+        let mut issuer: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(constants::ISSUER_WITH_KEY_PATH)?)?;
+        crate::key_publish::link_issuer(&mut issuer, ROTATED_KEY_ID)?;
+        fs::write(dir.join("issuer-with-key.json"), issuer.to_string())?;
+        let key = CryptographicKey::new(
+            ROTATED_KEY_ID,
+            constants::ISSUER_WITH_KEY_ID,
+            &crate::key_publish::read_public_key_pem(ROTATED_KEY_PATH)?,
+        );
+        fs::write(dir.join("issuer-key-2.json"), key.to_json_ld()?)?;
+        Ok(DirFetcher::new(constants::BASE_ENTS_URL, dir))
+    }
+
+    fn signed_rotated(creator: Option<&str>, key_id: Option<&str>) -> BoxResult<Payload> {
+        let mut assertion = signed_assertion()?;
+        assertion.verification.r#type = VerificationType::SignedBadge {
+            creator: creator.map(ToOwned::to_owned),
+        };
+        let signer = KeySigner::from_file(Algorithm::ES384, false, ROTATED_KEY_PATH)?
+            .with_key_id(key_id.map(ToOwned::to_owned));
+        Ok(Payload::new(signature::sign(&assertion, &signer)?))
+    }
+
+    #[test]
+    fn signed_rotated_key() -> BoxResult<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let fetcher = rotated_fetcher(tmp_dir.path())?;
+
+        // Selected by creator and kid
+        let payload = signed_rotated(Some(ROTATED_KEY_ID), Some(ROTATED_KEY_ID))?;
+        let verified = verify(&payload, &fetcher)?;
+        assert_eq!(
+            verified.key.map(|key| key.id).as_deref(),
+            Some(ROTATED_KEY_ID)
+        );
+        assert_eq!(verified.sign_alg, Some(Algorithm::ES384));
+        // Selected by kid only
+        let payload = signed_rotated(None, Some(ROTATED_KEY_ID))?;
+        assert_eq!(
+            verify(&payload, &fetcher)?.key.map(|key| key.id).as_deref(),
+            Some(ROTATED_KEY_ID)
+        );
+        // All keys of the issuer are tried
+        let payload = signed_rotated(None, None)?;
+        assert_eq!(
+            verify(&payload, &fetcher)?.key.map(|key| key.id).as_deref(),
+            Some(ROTATED_KEY_ID)
+        );
+        // Badges signed before the rotation remain valid
+        let payload = signed_payload(&signed_assertion()?)?;
+        assert_eq!(
+            verify(&payload, &fetcher)?.key.map(|key| key.id).as_deref(),
+            Some(constants::ISSUER_KEY_ID)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn signed_rotated_key_mismatch() -> BoxResult<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rotated = rotated_fetcher(tmp_dir.path())?;
+
+        // Selected the old key, but signed with the new one
+        let payload = signed_rotated(Some(constants::ISSUER_KEY_ID), None)?;
+        assert!(matches!(
+            verify(&payload, &rotated),
+            Err(Error::InvalidSignature { .. })
+        ));
+        // Creator and kid differ
+        let payload = signed_rotated(Some(constants::ISSUER_KEY_ID), Some(ROTATED_KEY_ID))?;
+        assert!(matches!(
+            verify(&payload, &rotated),
+            Err(Error::InvalidSignature { .. })
+        ));
+        // The new key is not (yet) listed by the issuer
+        let payload = signed_rotated(Some(ROTATED_KEY_ID), Some(ROTATED_KEY_ID))?;
+        assert!(matches!(
+            verify(&payload, &fetcher()),
+            Err(Error::InvalidSignature { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn scope() -> BoxResult<()> {
+        let mut assertion = signed_assertion()?;
+        let mut issuer = Issuer::new("https://issuer.example.org/profile.json");
+
+        assertion.id = "https://issuer.example.org/assertions/1.json".to_owned();
+        assert!(check_scope(&assertion, &issuer).is_ok());
+        assertion.id = "https://other.example.org/assertions/1.json".to_owned();
+        assert!(check_scope(&assertion, &issuer).is_err());
+
+        issuer.verification = Some(
+            Verification::builder()
+                .r#type(VerificationType::VerificationObject)
+                .allowed_origins(Some("other.example.org".to_owned()))
+                .build(),
+        );
+        assert!(check_scope(&assertion, &issuer).is_ok());
+
+        issuer.verification = Some(
+            Verification::builder()
+                .r#type(VerificationType::VerificationObject)
+                .starts_with(Some("https://other.example.org/badges/".to_owned()))
+                .build(),
+        );
+        assert!(check_scope(&assertion, &issuer).is_err());
+        Ok(())
+    }
+}